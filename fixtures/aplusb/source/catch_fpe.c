@@ -0,0 +1,17 @@
+#include <signal.h>
+#include <stdio.h>
+
+volatile int caught = 0;
+
+void handler(int sig) {
+  caught = 1;
+}
+
+int main() {
+  int a = 1, b = 1;
+  signal(SIGFPE, handler);
+  raise(SIGFPE);
+  scanf("%d%d", &a, &b);
+  printf("%d,%d\n", caught, a + b);
+  return 0;
+}
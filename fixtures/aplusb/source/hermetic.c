@@ -0,0 +1,20 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+
+const char* ensure(const char* t) {
+  if (t == NULL) {
+    return "null";
+  } else {
+    return t;
+  }
+}
+
+int main() {
+  int a = 1, b = 1;
+  scanf("%d%d", &a, &b);
+  FILE* null = fopen("/dev/null", "w");
+  printf("%d,%d,%d,%d,%s\n", a + b, access("/proc/self/status", R_OK) == 0,
+         null != NULL, access("/usr", F_OK) == 0, ensure(getenv("PATH")));
+  return 0;
+}
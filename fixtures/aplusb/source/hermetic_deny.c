@@ -0,0 +1,13 @@
+#include <errno.h>
+#include <stdio.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+int main() {
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  int socket_errno = errno;
+  pid_t pid = fork();
+  int fork_errno = errno;
+  printf("%d,%d,%d,%d\n", fd, socket_errno == EPERM, pid, fork_errno == EPERM);
+  return 0;
+}
@@ -0,0 +1,15 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+int main() {
+  for (int i = 0; i < 1024; i++) {
+    char *a = (char *)malloc(1024 * 1024);
+    if (a == NULL) {
+      fprintf(stderr, "malloc fails after %d MB\n", i);
+      return 1;
+    }
+    memset(a, 1, 1024 * 1024);
+  }
+  return 0;
+}
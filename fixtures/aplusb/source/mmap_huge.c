@@ -0,0 +1,13 @@
+#include <stdio.h>
+#include <sys/mman.h>
+
+int main() {
+  size_t size = (size_t)1 << 30;
+  char *buffer = mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  if (buffer == MAP_FAILED) {
+    return 1;
+  }
+  buffer[0] = 1;
+  printf("mapped\n");
+  return 0;
+}
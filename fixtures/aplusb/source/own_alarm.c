@@ -0,0 +1,15 @@
+#include <signal.h>
+#include <unistd.h>
+
+static void handler(int sig) {
+  alarm(1);
+}
+
+int main() {
+  signal(SIGALRM, handler);
+  alarm(1);
+  for (;;) {
+    pause();
+  }
+  return 0;
+}
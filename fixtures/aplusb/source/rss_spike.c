@@ -0,0 +1,16 @@
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+int main() {
+  size_t size = 64 * 1024 * 1024;
+  char *buffer = malloc(size);
+  if (buffer == NULL) {
+    return 1;
+  }
+  memset(buffer, 1, size);
+  usleep(300000);
+  free(buffer);
+  usleep(300000);
+  return 0;
+}
@@ -0,0 +1,12 @@
+#include <signal.h>
+#include <stdlib.h>
+
+void handler(int sig) {
+  abort();
+}
+
+int main() {
+  signal(SIGSEGV, handler);
+  raise(SIGSEGV);
+  return 0;
+}
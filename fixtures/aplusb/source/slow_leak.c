@@ -0,0 +1,17 @@
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+int main() {
+  size_t size = 4 * 1024 * 1024;
+  for (int i = 0; i < 20; i++) {
+    char *buffer = malloc(size);
+    if (buffer == NULL) {
+      return 1;
+    }
+    memset(buffer, 1, size);
+    usleep(50000);
+  }
+  usleep(1000000);
+  return 0;
+}
@@ -0,0 +1,9 @@
+#include <errno.h>
+#include <stdio.h>
+#include <sys/socket.h>
+
+int main() {
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  printf("%d %d\n", fd < 0 ? -1 : 0, fd < 0 ? errno : 0);
+  return 0;
+}
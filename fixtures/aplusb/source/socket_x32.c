@@ -0,0 +1,13 @@
+#include <errno.h>
+#include <stdio.h>
+#include <sys/socket.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define X32_SYSCALL_BIT 0x40000000
+
+int main() {
+  long fd = syscall(SYS_socket | X32_SYSCALL_BIT, AF_INET, SOCK_STREAM, 0);
+  printf("%d %d\n", fd < 0 ? -1 : 0, fd < 0 ? errno : 0);
+  return 0;
+}
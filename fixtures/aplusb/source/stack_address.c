@@ -0,0 +1,7 @@
+#include <stdio.h>
+
+int main() {
+  int local = 0;
+  printf("%p\n", (void *)&local);
+  return 0;
+}
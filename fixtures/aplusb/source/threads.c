@@ -0,0 +1,21 @@
+#include <pthread.h>
+#include <stdio.h>
+
+void* busy(void* arg) {
+  volatile unsigned long long sum = 0;
+  while (1) {
+    sum++;
+  }
+  return NULL;
+}
+
+int main() {
+  pthread_t threads[2];
+  for (int i = 0; i < 2; i++) {
+    pthread_create(&threads[i], NULL, busy, NULL);
+  }
+  for (int i = 0; i < 2; i++) {
+    pthread_join(threads[i], NULL);
+  }
+  return 0;
+}
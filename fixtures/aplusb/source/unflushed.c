@@ -0,0 +1,8 @@
+#include <stdio.h>
+#include <stdlib.h>
+
+int main() {
+  printf("unflushed");
+  abort();
+  return 0;
+}
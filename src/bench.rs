@@ -0,0 +1,88 @@
+//! Measure the baseline sandbox overhead by running a no-op program
+
+use std::time::Instant;
+
+use log::info;
+use serde_json::json;
+
+use crate::context::{CatBox, CatBoxOption};
+use crate::error::CatBoxError;
+
+/// Wall time of each sandboxed run (unit: us)
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+  /// Sorted samples
+  samples: Vec<u64>,
+}
+
+impl CatBox {
+  /// Run the command `iterations` times, and measure the wall time of each [`crate::run`], which
+  /// covers fork, cgroup, chroot, exec and wait. Run a no-op program (e.g. `true`) to get the
+  /// overhead of the sandbox itself.
+  pub fn bench(option: &CatBoxOption, iterations: usize) -> Result<BenchReport, CatBoxError> {
+    let mut samples = Vec::with_capacity(iterations);
+    for i in 0..iterations {
+      let start = Instant::now();
+      let result = crate::run(option)?;
+      let elapsed = start.elapsed().as_micros() as u64;
+      if *result.status() != Some(0) {
+        return Err(CatBoxError::exec(format!(
+          "Bench program exits with {:?} at iteration {}",
+          result.status(),
+          i + 1
+        )));
+      }
+      samples.push(elapsed);
+    }
+    samples.sort_unstable();
+    info!("Bench {} iterations: {:?}", iterations, samples);
+    Ok(BenchReport { samples })
+  }
+}
+
+impl BenchReport {
+  pub fn iterations(&self) -> usize {
+    self.samples.len()
+  }
+
+  /// Mean wall time (unit: us)
+  pub fn mean(&self) -> u64 {
+    if self.samples.is_empty() {
+      0
+    } else {
+      self.samples.iter().sum::<u64>() / self.samples.len() as u64
+    }
+  }
+
+  /// Percentile of wall time by nearest rank, e.g. 50 for the median (unit: us)
+  pub fn percentile(&self, p: u64) -> u64 {
+    if self.samples.is_empty() {
+      return 0;
+    }
+    let rank = (p.min(100) as usize * self.samples.len()).div_ceil(100);
+    self.samples[rank.max(1) - 1]
+  }
+
+  pub fn min(&self) -> u64 {
+    self.samples.first().copied().unwrap_or(0)
+  }
+
+  pub fn max(&self) -> u64 {
+    self.samples.last().copied().unwrap_or(0)
+  }
+
+  /// Export in JSON format
+  pub fn to_json(&self) -> Result<String, CatBoxError> {
+    let report = json!({
+      "ok": true,
+      "iterations": self.iterations(),
+      "mean_us": self.mean(),
+      "min_us": self.min(),
+      "p50_us": self.percentile(50),
+      "p90_us": self.percentile(90),
+      "p99_us": self.percentile(99),
+      "max_us": self.max(),
+    });
+    Ok(serde_json::to_string_pretty(&report)?)
+  }
+}
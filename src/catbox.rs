@@ -1,40 +1,68 @@
+use std::cmp::max;
+use std::collections::BTreeSet;
 use std::ffi::{c_uint, CString};
-use std::fs::create_dir_all;
+use std::fs::{self, create_dir_all, read_link, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::symlink;
+use std::os::unix::io::{FromRawFd, RawFd};
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use libc_stdhandle::{stderr, stdin, stdout};
-use log::{debug, error, info};
-use nix::libc::{self, freopen};
+use log::{debug, error, info, warn};
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
+use nix::libc::{self, freopen, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
 use nix::mount::{mount, MsFlags};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sched::{sched_getaffinity, sched_setaffinity, CpuSet};
+use nix::sys::personality::{self, Persona};
 use nix::sys::ptrace;
 use nix::sys::resource::{setrlimit, Resource};
-use nix::sys::signal::Signal;
-use nix::sys::wait::{waitpid, WaitStatus};
-use nix::unistd::{alarm, chdir, chroot, execvpe, fork, setgid, setuid, ForkResult};
+use nix::sys::signal::{kill, killpg, signal, sigprocmask, SigHandler, SigSet, SigmaskHow, Signal};
+use nix::sys::statvfs::statvfs;
+use nix::sys::wait::WaitStatus;
+use nix::unistd::{
+  access, alarm, chdir, chroot, close, dup2, execvpe, fork, pipe2, setgid, setpgid, setsid, setuid,
+  AccessFlags, ForkResult, Pid,
+};
+use tempfile::tempdir;
 
 use crate::cgroup::CatBoxCgroup;
-use crate::context::CatBoxResult;
+use crate::context::{CatBoxResult, TimeLimitReason, WATCHDOG_GRACE};
 use crate::error::CatBoxError;
-use crate::utils::{into_c_string, CatBoxPipe};
+use crate::judge::JudgeVerdict;
+use crate::syscall::{NetPolicy, DEFAULT_ALLOWED_SYSCALLS, NET_SYSCALLS};
+use crate::utils::capability::{drop_bounding_capabilities, drop_process_capabilities};
+use crate::utils::mount::{is_mount_point, retry_mount, symlink_chain};
+use crate::utils::pipe::CatBoxWritePipe;
+use crate::utils::probe::check_available;
+use crate::utils::seccomp::{allow_syscalls, deny_syscalls};
+use crate::utils::{into_c_string, CatBoxPipe, RunLogContext};
 use crate::CatBoxOption;
 
 /// 重定向输出输出
 fn redirect_io(option: &CatBoxOption) -> Result<(), CatBoxError> {
   unsafe {
     if let Some(in_path) = option.stdin() {
-      let in_path = into_c_string(&in_path);
+      let in_path = into_c_string(in_path)?;
       let mode = CString::new("r").unwrap();
       freopen(in_path.as_ptr(), mode.as_ptr(), stdin());
     }
 
     if let Some(out_path) = option.stdout() {
-      let out_path = into_c_string(&out_path);
+      let out_path = into_c_string(out_path)?;
       let mode = CString::new("w").unwrap();
       freopen(out_path.as_ptr(), mode.as_ptr(), stdout());
     }
 
-    if let Some(err_path) = option.stderr() {
-      let err_path = into_c_string(&err_path);
+    // debug 状态下不重定向 stderr，否则看不到子进程的错误输出
+    if option.debug_stderr_to_terminal() {
+      info!("Keep child process stderr connected to the terminal");
+    } else if let Some(err_path) = option.stderr() {
+      let err_path = into_c_string(err_path)?;
       let mode = CString::new("w").unwrap();
       freopen(err_path.as_ptr(), mode.as_ptr(), stderr());
     }
@@ -122,94 +150,678 @@ fn redirect_io(option: &CatBoxOption) -> Result<(), CatBoxError> {
   Ok(())
 }
 
+/// 检查输出重定向文件的父目录存在且可写，避免子进程中 freopen 静默失败
+fn check_redirect_targets(option: &CatBoxOption) -> Result<(), CatBoxError> {
+  let stderr = if option.debug_stderr_to_terminal() {
+    None
+  } else {
+    option.stderr().as_ref()
+  };
+  for (name, path) in [("stdout", option.stdout().as_ref()), ("stderr", stderr)] {
+    let Some(path) = path else {
+      continue;
+    };
+    let parent = match Path::new(path).parent() {
+      Some(parent) if !parent.as_os_str().is_empty() => parent,
+      _ => Path::new("."),
+    };
+    if !parent.is_dir() {
+      return Err(CatBoxError::Fs(format!(
+        "Parent directory of {} {} does not exist",
+        name, path
+      )));
+    }
+    if access(parent, AccessFlags::W_OK).is_err() {
+      return Err(CatBoxError::Fs(format!(
+        "Parent directory of {} {} is not writable",
+        name, path
+      )));
+    }
+  }
+  Ok(())
+}
+
+/// 创建捕获子进程输出的管道 (读端, 写端)，指定了重定向文件时不捕获
+fn capture_pipe(flag: bool, path: &Option<String>) -> Result<Option<(RawFd, RawFd)>, CatBoxError> {
+  if flag && path.is_none() {
+    Ok(Some(pipe2(OFlag::O_CLOEXEC)?))
+  } else {
+    Ok(None)
+  }
+}
+
+/// 父进程在后台线程中读取管道，与 waitpid 并发进行，避免子进程写满管道缓冲区后阻塞。
+/// 丢弃 Sender 后只读取管道中剩余的数据，逃逸的孙进程仍持有写端时也不会一直阻塞
+fn spawn_drain((read, write): (RawFd, RawFd)) -> Watcher<Vec<u8>> {
+  close(write).ok();
+  let mut file = unsafe { File::from_raw_fd(read) };
+  let (sender, receiver) = channel::<()>();
+  let handle = thread::spawn(move || {
+    let mut buf = vec![];
+    let mut chunk = [0_u8; 4096];
+    // 停止后最多再读取一个管道缓冲区，即子进程退出时留在管道中的数据
+    let mut remaining: Option<usize> = None;
+    loop {
+      if remaining.is_none() && receiver.try_recv() != Err(TryRecvError::Empty) {
+        remaining = Some(fcntl(read, FcntlArg::F_GETPIPE_SZ).map_or(1 << 16, |size| size as usize));
+      }
+      let mut fds = [PollFd::new(read, PollFlags::POLLIN)];
+      match poll(&mut fds, if remaining.is_some() { 0 } else { 50 }) {
+        Ok(0) if remaining.is_some() => break,
+        Ok(0) | Err(Errno::EINTR) => continue,
+        Ok(_) => {}
+        Err(err) => {
+          error!("Poll captured output fails: {}", err);
+          break;
+        }
+      }
+      let size = remaining.map_or(chunk.len(), |remaining| remaining.min(chunk.len()));
+      match file.read(&mut chunk[..size]) {
+        Ok(0) => break,
+        Ok(size) => {
+          buf.extend_from_slice(&chunk[..size]);
+          if let Some(remaining) = remaining.as_mut() {
+            *remaining -= size;
+            if *remaining == 0 {
+              break;
+            }
+          }
+        }
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+        Err(err) => {
+          error!("Read captured output fails: {}", err);
+          break;
+        }
+      }
+    }
+    buf
+  });
+  (sender, handle)
+}
+
+/// 父进程在后台线程中向管道写入 stdin，避免输入较大时与子进程互相阻塞
+fn spawn_feed((read, write): (RawFd, RawFd), bytes: Vec<u8>) -> JoinHandle<()> {
+  close(read).ok();
+  let mut file = unsafe { File::from_raw_fd(write) };
+  thread::spawn(move || {
+    // 子进程可能未读完输入就退出
+    if let Err(err) = file.write_all(&bytes) {
+      debug!("Write stdin bytes fails: {}", err);
+    }
+  })
+}
+
+/// 设置伪终端为控制终端和标准输入输出
+fn set_controlling_terminal(master: RawFd, slave: RawFd) -> Result<(), CatBoxError> {
+  close(master)?;
+  setsid()?;
+  if unsafe { libc::ioctl(slave, libc::TIOCSCTTY, 0) } == -1 {
+    return Err(CatBoxError::Nix(Errno::last()));
+  }
+  // 伪终端带有 CLOEXEC，需要清除复制后 fd 的标志
+  for fd in [STDIN_FILENO, STDOUT_FILENO, STDERR_FILENO] {
+    dup_inheritable(slave, fd)?;
+  }
+  if slave > STDERR_FILENO {
+    close(slave)?;
+  }
+  debug!("Set controlling terminal ok");
+  Ok(())
+}
+
+/// 将 socket pair 的一端作为子进程的 stdin 和 stdout
+fn set_stdio_socket(caller: RawFd, child: RawFd) -> Result<(), CatBoxError> {
+  close(caller)?;
+  for fd in [STDIN_FILENO, STDOUT_FILENO] {
+    dup_inheritable(child, fd)?;
+  }
+  if child > STDOUT_FILENO {
+    close(child)?;
+  }
+  debug!("Set stdio socket ok");
+  Ok(())
+}
+
+/// 将 fd 复制到指定的 fd，并清除 CLOEXEC 标志
+fn dup_inheritable(fd: RawFd, target: RawFd) -> Result<(), CatBoxError> {
+  if fd == target {
+    // dup2 相同的 fd 不会清除 CLOEXEC
+    fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty()))?;
+  } else {
+    dup2(fd, target)?;
+  }
+  Ok(())
+}
+
+/// 将父进程的 fd 转发到子进程指定的 fd
+fn forward_fds(fds: &[(RawFd, RawFd)]) -> Result<(), CatBoxError> {
+  for (parent, child) in fds {
+    dup_inheritable(*parent, *child)?;
+  }
+  debug!("Forward fds {:?} ok", fds);
+  Ok(())
+}
+
+/// 子进程中出错时通过管道通知父进程后直接退出，避免 fork 出的调用方副本继续运行
+fn exit_child(pipe: CatBoxWritePipe, message: String) -> ! {
+  error!("{}", message);
+  pipe.write(message).ok();
+  pipe.close().ok();
+  unsafe { libc::_exit(1) }
+}
+
+extern "C" {
+  // libc crate 没有导出 setitimer，ITIMER_REAL 会在 exec 后保留
+  fn setitimer(
+    which: libc::c_int,
+    new_value: *const libc::itimerval,
+    old_value: *mut libc::itimerval,
+  ) -> libc::c_int;
+}
+
+/// 清空子进程的信号掩码，并恢复默认的信号处理（exec 不会重置信号掩码和被忽略的信号）
+fn reset_signals(option: &CatBoxOption) {
+  if option.inherit_signals() {
+    return;
+  }
+  for sig in Signal::iterator() {
+    if sig == Signal::SIGKILL || sig == Signal::SIGSTOP {
+      continue;
+    }
+    if let Err(err) = unsafe { signal(sig, SigHandler::SigDfl) } {
+      error!("Reset signal {} fails: {}", sig, err);
+    }
+  }
+  if let Err(err) = sigprocmask(SigmaskHow::SIG_SETMASK, Some(&SigSet::empty()), None) {
+    error!("Reset signal mask fails: {}", err);
+  }
+}
+
 /// 设置子进程时钟 signal，运行时限 + 1 秒
 fn set_alarm(option: &CatBoxOption) {
-  let time_limit = (option.time_limit() as f64 / 1000.0 as f64).ceil() as c_uint;
+  if option.timeout_signal().is_some() {
+    // 使用自定义超时信号时由父进程计时
+    return;
+  }
+  if let Some(time_limit) = option.wall_time_limit() {
+    // 设置了墙钟时限，精确到毫秒
+    let time_limit = time_limit as libc::suseconds_t;
+    let timer = libc::itimerval {
+      it_interval: libc::timeval {
+        tv_sec: 0,
+        tv_usec: 0,
+      },
+      it_value: libc::timeval {
+        tv_sec: time_limit / 1000,
+        tv_usec: time_limit % 1000 * 1000,
+      },
+    };
+    if unsafe { setitimer(libc::ITIMER_REAL, &timer, std::ptr::null_mut()) } == -1 {
+      error!("Set wall timer fails: {}", Errno::last());
+    }
+    info!("Set wall timer {} ms", time_limit);
+    return;
+  }
+  let time_limit = (option.time_limit() as f64 / 1000.0_f64).ceil() as c_uint;
   alarm::set(time_limit + 1);
   info!("Set alarm {} seconds", time_limit + 1);
 }
 
+/// 父进程中的后台线程，丢弃 Sender 后线程结束
+type Watcher<T> = (Sender<()>, JoinHandle<T>);
+
+/// (毫秒, KB) 常驻内存样本
+type RssSamples = Vec<(u64, u64)>;
+
+/// 父进程计时，超时后向子进程发送自定义信号，线程返回是否已超时
+fn spawn_timeout(option: &CatBoxOption, child: Pid) -> Option<Watcher<bool>> {
+  let signal = option.timeout_signal()?;
+  // 与 set_alarm 一致：设置了墙钟时限时精确到毫秒，否则为运行时限 + 1 秒
+  let timeout = match option.wall_time_limit() {
+    Some(time_limit) => Duration::from_millis(time_limit),
+    None => Duration::from_secs((option.time_limit() as f64 / 1000.0_f64).ceil() as u64 + 1),
+  };
+  let (sender, receiver) = channel::<()>();
+  let handle = thread::spawn(move || match receiver.recv_timeout(timeout) {
+    Err(RecvTimeoutError::Timeout) => {
+      info!("Send {} to child process #{}. on timeout", signal, child);
+      if let Err(err) = kill(child, signal) {
+        error!(
+          "Send {} to child process #{}. fails: {}",
+          signal, child, err
+        );
+      }
+      true
+    }
+    _ => false,
+  });
+  info!("Set timeout {:?} with {}", timeout, signal);
+  Some((sender, handle))
+}
+
+/// 父进程的墙钟时间看门狗，子进程可能屏蔽 SIGALRM 或阻塞在 IO 上，超时后直接 SIGKILL，线程返回是否已超时
+fn spawn_watchdog(option: &CatBoxOption, child: Pid) -> Watcher<bool> {
+  let timeout = Duration::from_millis(option.watchdog_time_limit());
+  let kill_signal = option.kill_signal();
+  let (sender, receiver) = channel::<()>();
+  let handle = thread::spawn(move || match receiver.recv_timeout(timeout) {
+    Err(RecvTimeoutError::Timeout) => {
+      warn!(
+        "Send {} to child process #{}. by wall time watchdog",
+        kill_signal, child
+      );
+      if let Err(err) = kill(child, kill_signal) {
+        error!("Kill child process #{}. fails: {}", child, err);
+      }
+      // 子进程未响应终止信号时升级为 SIGKILL
+      if kill_signal != Signal::SIGKILL
+        && matches!(
+          receiver.recv_timeout(Duration::from_millis(WATCHDOG_GRACE)),
+          Err(RecvTimeoutError::Timeout)
+        )
+      {
+        warn!("Kill child process #{}. after {}", child, kill_signal);
+        kill(child, Signal::SIGKILL).ok();
+      }
+      true
+    }
+    _ => false,
+  });
+  info!("Set wall time watchdog {:?}", timeout);
+  (sender, handle)
+}
+
+/// 发送终止信号后，宽限期内子进程仍未退出则发送 SIGKILL
+fn spawn_grace_kill(child: Pid) -> Watcher<()> {
+  let (sender, receiver) = channel::<()>();
+  let handle = thread::spawn(move || {
+    let grace = Duration::from_millis(WATCHDOG_GRACE);
+    if let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(grace) {
+      warn!("Kill child process #{}. after forbidden syscall", child);
+      kill(child, Signal::SIGKILL).ok();
+    }
+  });
+  (sender, handle)
+}
+
+/// 读取进程的常驻内存 (unit: KB)
+fn read_rss(pid: Pid) -> Option<u64> {
+  let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+  status
+    .lines()
+    .find_map(|line| line.strip_prefix("VmRSS:"))
+    .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+/// 父进程定时采样子进程的常驻内存，线程返回 (毫秒, KB) 样本，以及是否因内存持续超过阈值而杀死子进程
+fn spawn_rss_sampler(option: &CatBoxOption, child: Pid) -> Option<Watcher<(RssSamples, bool)>> {
+  let policy = option.sustained_memory_limit();
+  // 仅启用持续内存限制时，默认每 50ms 采样一次，且不记录样本
+  let record = option.rss_sample_interval().is_some();
+  let interval = match (option.rss_sample_interval(), policy) {
+    (Some(interval), _) => interval,
+    (None, Some(_)) => 50,
+    (None, None) => return None,
+  };
+  let interval = Duration::from_millis(interval);
+  let (sender, receiver) = channel::<()>();
+  let handle = thread::spawn(move || {
+    let start = Instant::now();
+    let mut samples = vec![];
+    let mut above_since = None;
+    let mut exceeded = false;
+    loop {
+      if let Some(rss) = read_rss(child) {
+        let elapsed = start.elapsed().as_millis() as u64;
+        if record {
+          samples.push((elapsed, rss));
+        }
+        if let Some((threshold, duration)) = policy {
+          if rss <= threshold {
+            above_since = None;
+          } else if !exceeded && elapsed - *above_since.get_or_insert(elapsed) >= duration {
+            info!(
+              "Kill child process #{} since RSS {} KB exceeds {} KB for {} ms",
+              child, rss, threshold, duration
+            );
+            exceeded = kill(child, Signal::SIGKILL).is_ok();
+          }
+        }
+      }
+      if receiver.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+        break;
+      }
+    }
+    (samples, exceeded)
+  });
+  Some((sender, handle))
+}
+
 /// 调用 setrlimit
 fn set_resource_limit(option: &CatBoxOption) -> Result<(), CatBoxError> {
-  // 运行时限
-  let time_limit = (option.time_limit() as f64 / 1000.0 as f64).ceil() as u64;
-  setrlimit(Resource::RLIMIT_CPU, time_limit + 1, time_limit + 1)?;
+  // 运行时限，内核中 RLIMIT_CPU 的精度为秒
+  let time_limit = (option.time_limit() as f64 / 1000.0_f64).ceil() as u64;
+  if option.wall_time_basis() {
+    // 以墙钟时间为准时不限制 CPU 时间
+    setrlimit(
+      Resource::RLIMIT_CPU,
+      libc::RLIM_INFINITY,
+      libc::RLIM_INFINITY,
+    )?;
+  } else if option.strict_cpu_limit() {
+    // 严格模式下 hard limit 不额外增加 1 秒，soft limit 提前 1 秒发送 SIGXCPU
+    let hard = max(time_limit, 1);
+    let soft = max(hard - 1, 1);
+    setrlimit(Resource::RLIMIT_CPU, soft, hard)?;
+  } else {
+    setrlimit(Resource::RLIMIT_CPU, time_limit + 1, time_limit + 1)?;
+  }
 
-  // 地址空间无限
-  setrlimit(
-    Resource::RLIMIT_AS,
-    libc::RLIM_INFINITY,
-    libc::RLIM_INFINITY,
-  )?;
+  // 地址空间默认无限
+  let address_space = option.address_space_limit().unwrap_or(libc::RLIM_INFINITY);
+  setrlimit(Resource::RLIMIT_AS, address_space, address_space)?;
 
   // 设置栈空间
   let stack_size = option.stack_size();
   setrlimit(Resource::RLIMIT_STACK, stack_size, stack_size)?;
 
-  // 输出大小 256 MB
-  let fsize = 256 * 1024 * 1024 as u64;
+  // 输出大小，默认 256 MB
+  let fsize = option.output_limit();
   setrlimit(Resource::RLIMIT_FSIZE, fsize, fsize)?;
 
   Ok(())
 }
 
-/// chroot
-fn change_root(new_root: &PathBuf, option: &CatBoxOption) -> Result<(), CatBoxError> {
+/// 预先检查绑定的 CPU 是否存在，且在当前进程允许的范围内（离线的 CPU 不在其中）
+fn check_cpu_affinity(option: &CatBoxOption) -> Result<(), CatBoxError> {
+  if option.cpu_affinity().is_empty() {
+    return Ok(());
+  }
+  let allowed = sched_getaffinity(Pid::from_raw(0))?;
+  for cpu in option.cpu_affinity() {
+    if !allowed.is_set(*cpu).unwrap_or(false) {
+      return Err(CatBoxError::cli(format!(
+        "CPU {} is not available for affinity",
+        cpu
+      )));
+    }
+  }
+  Ok(())
+}
+
+/// 调用 sched_setaffinity
+fn set_cpu_affinity(option: &CatBoxOption) -> Result<(), CatBoxError> {
+  if option.cpu_affinity().is_empty() {
+    return Ok(());
+  }
+  let mut cpu_set = CpuSet::new();
+  for cpu in option.cpu_affinity() {
+    cpu_set.set(*cpu)?;
+  }
+  sched_setaffinity(Pid::from_raw(0), &cpu_set)?;
+  info!("Set cpu affinity {:?}", option.cpu_affinity());
+  Ok(())
+}
+
+/// 调用 personality 关闭地址空间随机化，exec 后仍然保留
+fn disable_aslr(option: &CatBoxOption) -> Result<(), CatBoxError> {
+  if !option.disable_aslr() {
+    return Ok(());
+  }
+  let persona = personality::get()?;
+  personality::set(persona | Persona::ADDR_NO_RANDOMIZE)?;
+  info!("Disable ASLR");
+  Ok(())
+}
+
+/// 挂载 chroot 的新根目录
+fn mount_root(new_root: &PathBuf, option: &CatBoxOption) -> Result<(), CatBoxError> {
   info!("Mount new root: {}", new_root.to_string_lossy());
+  let retries = option.mount_retries();
+
+  if option.hermetic() {
+    // hermetic 模式下使用全新的 tmpfs 作为根目录
+    retry_mount(retries, || {
+      mount(
+        Some("tmpfs"),
+        new_root,
+        Some("tmpfs"),
+        MsFlags::MS_NOSUID,
+        Some("mode=755,size=64m"),
+      )
+    })?;
+  } else {
+    retry_mount(retries, || {
+      mount::<PathBuf, PathBuf, PathBuf, PathBuf>(
+        Some(new_root),
+        new_root,
+        None,
+        MsFlags::MS_BIND | MsFlags::MS_REC,
+        None,
+      )
+    })?;
 
-  mount::<PathBuf, PathBuf, PathBuf, PathBuf>(
-    Some(new_root),
-    new_root,
-    None,
-    MsFlags::MS_BIND | MsFlags::MS_REC,
-    None,
-  )?;
-
-  mount::<PathBuf, PathBuf, PathBuf, PathBuf>(
-    None,
-    new_root,
-    None,
-    MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_REC,
-    None,
-  )?;
+    retry_mount(retries, || {
+      mount::<PathBuf, PathBuf, PathBuf, PathBuf>(
+        None,
+        new_root,
+        None,
+        MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_REC,
+        None,
+      )
+    })?;
+  }
 
   for mount_point in option.mounts() {
-    if !mount_point.dst().is_absolute() {
-      error!(
-        "The dst path {} in mounts should be absolute",
-        mount_point.dst().to_string_lossy()
-      );
-      continue;
+    bind_mount(
+      new_root,
+      mount_point.src(),
+      mount_point.dst(),
+      mount_point.read_only(),
+      retries,
+    )?;
+  }
+
+  // 程序（和启动器）路径为符号链接时（如 /usr/bin/c++ -> /etc/alternatives/c++），补全 jail 中缺失的链接目标：
+  // 中间的符号链接原样创建，最终的文件只读挂载
+  let launcher = option.launcher()?.map(|(launcher, _)| launcher);
+  let programs = [Some(option.program()?), launcher]
+    .into_iter()
+    .flatten()
+    .filter_map(|program| find_program(option, &program));
+  for program in programs {
+    for target in symlink_chain(&program) {
+      let jail_target = new_root.join(target.strip_prefix(Path::new("/")).unwrap_or(&target));
+      if jail_target.symlink_metadata().is_ok() {
+        continue;
+      }
+      match read_link(&target) {
+        Ok(link) => {
+          info!("Create symlink {:?} -> {:?}", &target, &link);
+          if let Some(parent) = jail_target.parent() {
+            create_dir_all(parent)?;
+          }
+          symlink(link, &jail_target)?;
+        }
+        Err(_) => {
+          info!("Mount symlink target {:?}", &target);
+          bind_mount(new_root, &target, &target, true, retries)?;
+        }
+      }
     }
-    if !mount_point.dst().is_dir() {
-      error!(
-        "The dst path {} in mounts should be a directory",
-        mount_point.dst().to_string_lossy()
-      );
-      continue;
+  }
+
+  // 挂载 tmpfs 作为干净的工作目录
+  if let Some((dst, size)) = option.tmpfs_workdir() {
+    let target = new_root.join(dst.strip_prefix(Path::new("/")).unwrap_or(dst));
+    create_dir_all(&target)?;
+    let data = format!("mode=777,size={}", size);
+    retry_mount(retries, || {
+      mount(
+        Some("tmpfs"),
+        &target,
+        Some("tmpfs"),
+        MsFlags::MS_NOSUID,
+        Some(data.as_str()),
+      )
+    })?;
+    info!("Mount tmpfs workdir {:?} ({} bytes)", dst, size);
+  }
+
+  if option.hermetic() {
+    mount_proc_dev(new_root, retries)?;
+    if let Some(max_files) = option.max_files_created() {
+      limit_files_created(new_root, max_files, retries)?;
     }
+  } else if option.max_files_created().is_some() {
+    warn!("Max files created only applies to the tmpfs root in hermetic mode");
+  }
 
-    let target = mount_point.dst().strip_prefix(Path::new("/")).unwrap();
-    let target = new_root.join(target);
+  Ok(())
+}
+
+/// 通过 tmpfs 的 nr_inodes 限制新建文件数量，已有的挂载点不计入
+fn limit_files_created(new_root: &Path, max_files: u64, retries: u32) -> Result<(), CatBoxError> {
+  let stat = statvfs(new_root)?;
+  let used = stat.files() - stat.files_free();
+  let data = format!("nr_inodes={}", used + max_files);
+  retry_mount(retries, || {
+    mount(
+      None::<&str>,
+      new_root,
+      None::<&str>,
+      MsFlags::MS_REMOUNT | MsFlags::MS_NOSUID,
+      Some(data.as_str()),
+    )
+  })?;
+  info!("Limit files created in tmpfs root: {}", max_files);
+  Ok(())
+}
+
+/// 将 src 绑定挂载到 jail 中的 dst
+fn bind_mount(
+  new_root: &Path,
+  src: &PathBuf,
+  dst: &Path,
+  read_only: bool,
+  retries: u32,
+) -> Result<(), CatBoxError> {
+  if !dst.is_absolute() {
+    error!(
+      "The dst path {} in mounts should be absolute",
+      dst.to_string_lossy()
+    );
+    return Ok(());
+  }
+  let target = dst.strip_prefix(Path::new("/")).unwrap();
+  let target = new_root.join(target);
+  if src.is_dir() {
     create_dir_all(&target)?;
-    debug!("Mount directory {:?} -> {:?}", mount_point.src(), &target);
+    debug!("Mount directory {:?} -> {:?}", src, &target);
+  } else if src.is_file() {
+    // 单个文件需要先创建空文件作为挂载点
+    if let Some(parent) = target.parent() {
+      create_dir_all(parent)?;
+    }
+    if !target.exists() {
+      File::create(&target)?;
+    }
+    debug!("Mount file {:?} -> {:?}", src, &target);
+  } else {
+    error!(
+      "The src path {} in mounts should be a directory or a file",
+      src.to_string_lossy()
+    );
+    return Ok(());
+  }
 
+  retry_mount(retries, || {
     mount::<PathBuf, PathBuf, PathBuf, PathBuf>(
-      Some(mount_point.src()),
+      Some(src),
       &target,
       None,
       MsFlags::MS_BIND | MsFlags::MS_REC,
       None,
-    )?;
-    if mount_point.read_only() {
+    )
+  })?;
+  if read_only {
+    retry_mount(retries, || {
       mount::<PathBuf, PathBuf, PathBuf, PathBuf>(
         None,
         &target,
         None,
         MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY | MsFlags::MS_REC,
         None,
-      )?;
-    }
+      )
+    })?;
+  }
+  Ok(())
+}
+
+/// 查找要运行的程序在宿主机上的路径
+fn find_program(option: &CatBoxOption, program: &CString) -> Option<PathBuf> {
+  let program = Path::new(program.to_str().ok()?);
+  if program.is_absolute() {
+    return Some(program.to_path_buf());
+  }
+  if program.components().count() > 1 {
+    return Some(option.cwd().join(program));
+  }
+  let path = option
+    .env()
+    .iter()
+    .rev()
+    .find(|(key, _)| key == "PATH")
+    .map(|(_, value)| value.clone())
+    .or_else(|| std::env::var("PATH").ok())?;
+  path
+    .split(':')
+    .map(|dir| Path::new(dir).join(program))
+    .find(|candidate| candidate.symlink_metadata().is_ok())
+}
+
+/// 挂载全新的 /proc 和最小的 /dev
+fn mount_proc_dev(new_root: &Path, retries: u32) -> Result<(), CatBoxError> {
+  let proc = new_root.join("proc");
+  create_dir_all(&proc)?;
+  debug!("Mount proc -> {:?}", &proc);
+  retry_mount(retries, || {
+    mount(
+      Some("proc"),
+      &proc,
+      Some("proc"),
+      MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+      None::<&str>,
+    )
+  })?;
+
+  let dev = new_root.join("dev");
+  create_dir_all(&dev)?;
+  for name in ["null", "zero", "random", "urandom"] {
+    let src = Path::new("/dev").join(name);
+    let target = dev.join(name);
+    File::create(&target)?;
+    debug!("Mount device {:?} -> {:?}", &src, &target);
+    retry_mount(retries, || {
+      mount(
+        Some(&src),
+        &target,
+        None::<&str>,
+        MsFlags::MS_BIND,
+        None::<&str>,
+      )
+    })?;
+  }
+
+  Ok(())
+}
+
+/// chroot
+fn change_root(new_root: &PathBuf, option: &CatBoxOption) -> Result<(), CatBoxError> {
+  if is_mount_point(new_root) {
+    info!("Reuse mounted new root: {}", new_root.to_string_lossy());
+  } else {
+    mount_root(new_root, option)?;
   }
 
   chroot(new_root)?;
@@ -228,49 +840,246 @@ fn change_root(new_root: &PathBuf, option: &CatBoxOption) -> Result<(), CatBoxEr
   Ok(())
 }
 
+/// Dynamic linker env which can subvert the sandboxed program
+const DANGEROUS_ENV: [&str; 8] = [
+  "LD_PRELOAD",
+  "LD_LIBRARY_PATH",
+  "LD_AUDIT",
+  "LD_DEBUG",
+  "LD_DEBUG_OUTPUT",
+  "LD_PROFILE",
+  "LD_PROFILE_OUTPUT",
+  "LD_ORIGIN_PATH",
+];
+
+/// 获取 exec 的程序和参数，设置了启动器时先运行启动器
+fn get_exec_args(option: &CatBoxOption) -> Result<(CString, Vec<CString>), CatBoxError> {
+  let program = option.program()?;
+  let args = [vec![program.clone()], option.arguments()?].concat();
+  match option.launcher()? {
+    Some((launcher, launcher_args)) => {
+      let args = [vec![launcher.clone()], launcher_args, args].concat();
+      Ok((launcher, args))
+    }
+    None => Ok((program, args)),
+  }
+}
+
+/// 获取 jail 中的 PATH，root 为 jail 的根目录在当前进程中的路径
+fn get_jail_path(option: &CatBoxOption, root: &Path) -> Option<String> {
+  if let Some(path) = option.jail_path() {
+    return Some(path.clone());
+  }
+  option.chroot().as_ref()?;
+
+  // 移除 jail 中不存在的路径
+  let path = option
+    .env()
+    .iter()
+    .rev()
+    .find(|(key, _)| key == "PATH")
+    .map(|(_, value)| value.as_str())?;
+  let path = path
+    .split(':')
+    .filter(|dir| {
+      let exists = root.join(dir.trim_start_matches('/')).is_dir();
+      if !exists {
+        warn!("PATH entry {} is not mounted in the jail", dir);
+      }
+      exists
+    })
+    .collect::<Vec<&str>>()
+    .join(":");
+  Some(path)
+}
+
 /// 获取环境变量
 /// 默认只传递 PATH 环境变量
-fn get_env(option: &CatBoxOption) -> Vec<CString> {
+fn get_env(option: &CatBoxOption, jail_path: &Option<String>) -> Result<Vec<CString>, CatBoxError> {
   let mut envs = vec![];
   for (key, value) in option.env().iter() {
+    // 默认移除动态链接相关的环境变量
+    if !option.allow_ld_env() && DANGEROUS_ENV.contains(&key.as_str()) {
+      debug!("Scrub env {}", key);
+      continue;
+    }
+    if key == "PATH" && jail_path.is_some() {
+      continue;
+    }
     let pair = format!("{}={}", key, value);
-    envs.push(into_c_string(&pair));
+    envs.push(into_c_string(&pair)?);
+  }
+  if let Some(path) = jail_path {
+    envs.push(into_c_string(&format!("PATH={}", path))?);
+  }
+  Ok(envs)
+}
+
+/// 等待子进程，被信号中断时重试，同时返回该子进程的资源用量，子进程已被回收时返回 None
+fn wait_child(child: Pid) -> Result<Option<(WaitStatus, libc::rusage)>, CatBoxError> {
+  loop {
+    // nix 没有封装 wait4，getrusage(RUSAGE_CHILDREN) 会累计当前进程回收过的所有子进程
+    let mut status = 0;
+    let mut rusage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    let pid = unsafe { libc::wait4(child.as_raw(), &mut status, 0, &mut rusage) };
+    match Errno::result(pid) {
+      Ok(pid) => {
+        return Ok(Some((
+          WaitStatus::from_raw(Pid::from_raw(pid), status)?,
+          rusage,
+        )))
+      }
+      Err(Errno::EINTR) => {
+        debug!("Waiting for child process #{}. is interrupted", child);
+        continue;
+      }
+      Err(Errno::ECHILD) => return Ok(None),
+      Err(err) => return Err(err.into()),
+    }
   }
-  envs
 }
 
 /// Run process isolation sandbox
 pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
+  // 要求 stderr 为空但未重定向时，捕获到临时文件
+  if option.require_empty_stderr() && option.stderr().is_none() {
+    let dir = tempdir()?;
+    let stderr = dir.path().join("stderr.out");
+    return run(&option.with_stderr(stderr.to_string_lossy()));
+  }
+
+  // 与答案比较时需要捕获 stdout，运行结束后再转发到标准输出
+  if option.answer().is_some() && option.stdout().is_none() {
+    let dir = tempdir()?;
+    let stdout = dir.path().join("stdout.out");
+    let result = run(&option.with_stdout(stdout.to_string_lossy()))?;
+    io::copy(&mut File::open(&stdout)?, &mut io::stdout())?;
+    return Ok(result);
+  }
+
+  // 日志中附带运行编号和标签
+  let _log_context = RunLogContext::enter(option.label());
+
+  // force 模式下预先检查沙箱功能是否可用
+  check_available(option)?;
+
+  // 预先检查命令行和环境变量中是否含有 NUL 字符，避免子进程中才出错
+  get_exec_args(option)?;
+  get_env(option, &None)?;
+
+  // 预先检查输出重定向的目录
+  check_redirect_targets(option)?;
+
+  // 预先检查绑定的 CPU
+  check_cpu_affinity(option)?;
+
+  // 校验挂载目录的内容没有变化
+  if option.chroot().is_some() {
+    for mount_point in option.mounts() {
+      mount_point.verify()?;
+    }
+  }
+
   let pipe = CatBoxPipe::new()?;
+  let capture_stdout = capture_pipe(option.capture_stdout(), option.stdout())?;
+  let capture_stderr = capture_pipe(option.capture_stderr(), option.stderr())?;
+  let stdin_pipe = match option.stdin_bytes() {
+    Some(_) => Some(pipe2(OFlag::O_CLOEXEC)?),
+    None => None,
+  };
+
+  // 墙钟时间从 fork 之前开始计时
+  let start = Instant::now();
 
   match unsafe { fork() } {
     Ok(ForkResult::Parent { child, .. }) => {
       let pipe = pipe.read()?;
+      let stdout_drain = capture_stdout.map(spawn_drain);
+      let stderr_drain = capture_stderr.map(spawn_drain);
+      let stdin_feed = stdin_pipe
+        .zip(option.stdin_bytes())
+        .map(|(pipe, bytes)| spawn_feed(pipe, bytes.to_vec()));
+
+      // 设置进程组，避免子进程还未调用 setpgid（使用伪终端时子进程会创建新会话）
+      if option.process_group() && option.pty().is_none() {
+        setpgid(child, child).ok();
+      }
 
       // 设置 cgroup
-      let cgroup = CatBoxCgroup::new(&option, child)?;
+      let cgroup = CatBoxCgroup::new(option, child)?;
+
+      // 设置自定义超时信号
+      let timeout = spawn_timeout(option, child);
+      // 设置墙钟时间看门狗
+      let watchdog = spawn_watchdog(option, child);
+      // 采样常驻内存
+      let sampler = spawn_rss_sampler(option, child);
 
       // 复制 SyscallFilter
       let mut filter = option.ptrace().clone();
+      if let (Some(filter), Some(_)) = (filter.as_mut(), option.launcher()?) {
+        // 启动器通过 execve 额外 exec 一次，syscall 进入、退出和 exec 后的 SIGTRAP 各检查一次
+        filter.extend_allowance(libc::SYS_execve, 3);
+      }
       let mut last_signal: Option<Signal> = None;
+      // 重新注入的 RE 信号，只有进程因此终止才记为 RE
+      let mut injected_signal: Option<Signal> = None;
+      let mut signal_history: Vec<Signal> = vec![];
+      let mut distinct_syscalls = BTreeSet::new();
+      let mut forbidden_syscall: Option<u64> = None;
+      let mut grace_kill: Option<Watcher<()>> = None;
+      // 子进程终止时 wait4 返回的资源用量
+      let mut child_usage: Option<libc::rusage> = None;
 
       debug!("Start waiting for child process");
 
-      let (status, signal) = loop {
-        let status = waitpid(child, None)?;
+      let (status, signal, core_dumped) = loop {
+        let status = match wait_child(child)? {
+          Some((status, rusage)) => {
+            child_usage = Some(rusage);
+            status
+          }
+          None => {
+            warn!("Child process #{}. has already been reaped", child);
+            break (None, last_signal, false);
+          }
+        };
 
         match status {
           WaitStatus::Exited(pid, status) => {
             info!("Child process #{}. exited with status {}", pid, status);
-            break (Some(status), last_signal);
+            if let Some(signal) = injected_signal {
+              info!(
+                "Child process #{}. has recovered from injected {}",
+                pid, signal
+              );
+            }
+            break (Some(status), last_signal, false);
           }
-          WaitStatus::Signaled(pid, signal, _) => {
+          WaitStatus::Signaled(pid, signal, core_dumped) => {
             info!("Child process #{}. is signaled by {}", pid, signal);
-            break (None, Some(signal));
+            if let Some(injected) = injected_signal.filter(|injected| *injected != signal) {
+              info!(
+                "Child process #{}. has recovered from injected {}",
+                pid, injected
+              );
+            }
+            if TimeLimitReason::from_signal(signal).is_some() {
+              last_signal = Some(signal);
+            }
+            break (None, Some(signal), core_dumped);
           }
           WaitStatus::Stopped(pid, signal) => {
+            if option.signal_history() && signal != Signal::SIGTRAP {
+              signal_history.push(signal);
+            }
             // 完整 Signal 定义见：https://man7.org/linux/man-pages/man7/signal.7.html
             match signal {
+              // 禁止的系统调用后发送的终止信号，即使与下面的信号相同，也要继续跟踪系统调用
+              signal if forbidden_syscall.is_some() && option.kill_signal() == signal => {
+                info!("Child process #{}. is stopped by {}", pid, signal);
+                ptrace::syscall(pid, signal)?;
+              }
               // 可能是超时了
               Signal::SIGALRM | Signal::SIGVTALRM | Signal::SIGXCPU => {
                 info!(
@@ -286,6 +1095,9 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
               Signal::SIGTRAP => {
                 match ptrace::getregs(pid) {
                   Ok(user_regs) => {
+                    if option.record_syscalls() {
+                      distinct_syscalls.insert(user_regs.orig_rax);
+                    }
                     // let syscall_id = user_regs.orig_rax;
                     // debug!(
                     //   "Child process #{}. performed a syscall: {}",
@@ -304,7 +1116,20 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
                           "Child process #{}. is stopped for forbidden syscall (id = {})",
                           pid, user_regs.orig_rax
                         );
-                        ptrace::kill(pid)?;
+                        let kill_signal = option.kill_signal();
+                        // 再次调用被禁止的系统调用时直接杀死
+                        if kill_signal == Signal::SIGKILL || forbidden_syscall.is_some() {
+                          ptrace::kill(pid)?;
+                        } else {
+                          // 跳过被禁止的系统调用，再发送终止信号，宽限期后仍未退出则杀死
+                          let mut regs = user_regs;
+                          regs.orig_rax = u64::MAX;
+                          ptrace::setregs(pid, regs)?;
+                          kill(pid, kill_signal)?;
+                          grace_kill = Some(spawn_grace_kill(pid));
+                          ptrace::syscall(pid, None)?;
+                        }
+                        forbidden_syscall.get_or_insert(user_regs.orig_rax);
                       }
                     } else {
                       debug!(
@@ -331,7 +1156,7 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
               | Signal::SIGXFSZ
               | Signal::SIGABRT => {
                 info!("Child process #{}. is stopped by {}", pid, signal);
-                last_signal = Some(signal);
+                injected_signal = Some(signal);
                 ptrace::cont(pid, signal)?;
                 // ptrace::kill(pid)?;
                 // break (None, Some(signal));
@@ -340,6 +1165,20 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
               Signal::SIGCONT | Signal::SIGHUP | Signal::SIGINT => {
                 unreachable!()
               }
+              // 自定义超时信号
+              signal if option.timeout_signal() == Some(signal) => {
+                info!(
+                  "Child process #{}. is stopped by {} (may be time limit exceeded)",
+                  pid, signal
+                );
+                last_signal = Some(signal);
+                ptrace::cont(pid, signal)?;
+              }
+              // 终止信号，继续跟踪系统调用，以免程序捕获信号后不受过滤
+              signal if option.kill_signal() == signal => {
+                info!("Child process #{}. is stopped by {}", pid, signal);
+                ptrace::syscall(pid, signal)?;
+              }
               _ => {
                 info!(
                   "Child process #{}. is stopped by an unhandled signal {}",
@@ -363,11 +1202,53 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
           }
         }
       };
+      let wall_time = start.elapsed().as_millis() as u64;
 
       debug!("Finish waiting for child process");
 
-      if let Ok(message) = pipe.read() {
-        if message.len() > 0 {
+      // 停止超时计时
+      let timed_out = timeout.is_some_and(|(sender, handle)| {
+        drop(sender);
+        handle.join().unwrap_or(false)
+      });
+      let watchdog_fired = {
+        let (sender, handle) = watchdog;
+        drop(sender);
+        handle.join().unwrap_or(false)
+      };
+      if let Some((sender, handle)) = grace_kill {
+        drop(sender);
+        handle.join().ok();
+      }
+      let (rss_samples, sustained_mle) =
+        sampler.map_or_else(Default::default, |(sender, handle)| {
+          drop(sender);
+          handle.join().unwrap_or_default()
+        });
+
+      // 杀死进程组中残留的子进程
+      if option.process_group() {
+        match killpg(child, Signal::SIGKILL) {
+          Ok(_) => info!("Kill remaining processes in group #{}.", child),
+          Err(err) => debug!("No remaining processes in group #{}. ({})", child, err),
+        }
+      }
+
+      // 子进程已结束，读取管道中剩余的输出
+      let join_drain = |drain: Option<Watcher<Vec<u8>>>| {
+        drain.map_or_else(Vec::new, |(sender, handle)| {
+          drop(sender);
+          handle.join().unwrap_or_default()
+        })
+      };
+      let stdout_bytes = join_drain(stdout_drain);
+      let stderr_bytes = join_drain(stderr_drain);
+      if let Some(handle) = stdin_feed {
+        handle.join().ok();
+      }
+
+      if let Ok(message) = pipe.read(option.error_message_limit()) {
+        if !message.is_empty() {
           debug!("Recv message: {:?}", message);
           pipe.close()?;
           let exec_error = message.strip_prefix("Execvpe fails: ");
@@ -379,10 +1260,77 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
       }
       pipe.close()?;
 
-      let usage = cgroup.usage();
+      let usage = cgroup.usage(child_usage).with_wall_time(wall_time);
       info!("{:?}", usage);
 
-      Ok(CatBoxResult::new(status, signal, usage))
+      // 区分 CPU 时间超限 (RLIMIT_CPU) 和墙钟时间超限 (alarm)
+      let time_limit_reason = match status {
+        Some(_) => None,
+        None if timed_out || watchdog_fired => Some(TimeLimitReason::Wall),
+        // 使用自定义超时信号时，程序自身的 SIGALRM 不算超时
+        None if option.timeout_signal().is_some() => last_signal
+          .filter(|signal| *signal == Signal::SIGXCPU)
+          .and_then(TimeLimitReason::from_signal),
+        None => last_signal.and_then(TimeLimitReason::from_signal),
+      };
+
+      // 实际运行的命令行和环境变量，chroot 的挂载在 CatBox 关闭前仍然保留
+      // 仅在需要报告时计算，避免每次运行都重复检查 PATH
+      let (command_line, command_env) = if option.report_command_line() {
+        let (_, args) = get_exec_args(option)?;
+        let command_line = args
+          .iter()
+          .map(|arg| arg.to_string_lossy().to_string())
+          .collect::<Vec<String>>();
+        let root = option.chroot().clone().unwrap_or(PathBuf::from("/"));
+        let command_env = get_env(option, &get_jail_path(option, &root))?
+          .iter()
+          .map(|env| env.to_string_lossy().to_string())
+          .collect::<Vec<String>>();
+        (command_line, command_env)
+      } else {
+        (vec![], vec![])
+      };
+
+      // 检查 stderr 是否为空
+      let stderr_violated = option.require_empty_stderr()
+        && !option.debug_stderr_to_terminal()
+        && option
+          .stderr()
+          .as_ref()
+          .and_then(|path| fs::metadata(path).ok())
+          .is_some_and(|metadata| metadata.len() > 0);
+
+      let result = CatBoxResult::new(
+        status,
+        signal,
+        core_dumped,
+        time_limit_reason,
+        stderr_violated,
+        signal_history,
+        usage,
+      )
+      .with_program(option.display_name())
+      .with_limits(option.time_limit(), option.memory_limit())
+      .with_rss_samples(rss_samples)
+      .with_distinct_syscalls(distinct_syscalls.into_iter().collect())
+      .with_forbidden_syscall(forbidden_syscall)
+      .with_sustained_mle(sustained_mle)
+      .with_command_line(command_line, command_env, option.report_command_line())
+      .with_cgroup(cgroup.persisted())
+      .with_captured_output(stdout_bytes, stderr_bytes);
+
+      // 与答案比较
+      let verdict = match (option.answer(), option.stdout()) {
+        (Some((answer, comparator)), Some(output)) => {
+          Some(JudgeVerdict::classify(result.verdict(option), || {
+            Ok(comparator.compare_files(output, answer)?.same())
+          })?)
+        }
+        _ => None,
+      };
+
+      Ok(result.with_verdict(verdict))
     }
     Ok(ForkResult::Child) => {
       info!("Child process is running");
@@ -395,14 +1343,47 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
         // parent process may have been dead
       }
 
+      // 重置信号掩码和信号处理
+      reset_signals(option);
+
       let pipe = pipe.write()?;
 
+      // 使用伪终端作为控制终端，同时创建新的会话和进程组
+      if let Some((master, slave)) = option.pty() {
+        if let Err(err) = set_controlling_terminal(master, slave) {
+          exit_child(pipe, format!("Set controlling terminal fails: {}", err));
+        }
+      } else if option.process_group() {
+        // 创建新的进程组
+        if let Err(err) = setpgid(Pid::from_raw(0), Pid::from_raw(0)) {
+          error!("Set process group fails: {}", err);
+        }
+      }
+
+      // 使用 socket pair 作为输入输出
+      if let Some((caller, child)) = option.socketpair() {
+        if let Err(err) = set_stdio_socket(caller, child) {
+          exit_child(pipe, format!("Set stdio socket fails: {}", err));
+        }
+      }
+
       // 重定向输入输出
-      redirect_io(&option)?;
+      redirect_io(option)?;
+      // 父进程的标准输入关闭时，管道可能恰好占用 fd 0
+      if let Some((read, _)) = stdin_pipe {
+        if let Err(err) = dup_inheritable(read, STDIN_FILENO) {
+          exit_child(pipe, format!("Feed stdin fails: {}", err));
+        }
+      }
+
+      // 转发父进程的 fd
+      if let Err(err) = forward_fds(option.forward_fds()) {
+        exit_child(pipe, format!("Forward fds fails: {}", err));
+      }
 
       // chroot
       if let Some(chroot) = option.chroot() {
-        match change_root(chroot, &option) {
+        match change_root(chroot, option) {
           Ok(_) => {
             debug!("Chroot ok: {}", chroot.to_string_lossy());
           }
@@ -413,10 +1394,26 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
       }
 
       // 设置时钟
-      set_alarm(&option);
+      set_alarm(option);
 
       // setrlimit
-      set_resource_limit(&option)?;
+      set_resource_limit(option)?;
+
+      // 绑定 CPU
+      if let Err(err) = set_cpu_affinity(option) {
+        exit_child(pipe, format!("Set cpu affinity fails: {}", err));
+      }
+
+      // 关闭 ASLR
+      if let Err(err) = disable_aslr(option) {
+        exit_child(pipe, format!("Disable ASLR fails: {}", err));
+      }
+
+      // 从 bounding set 中移除 capabilities，需要在 setuid 之前
+      let capabilities = option.drop_capabilities();
+      if !capabilities.is_empty() {
+        drop_bounding_capabilities(&capabilities);
+      }
 
       // 设置用户
       if let Err(err) = setgid(option.gid()) {
@@ -426,14 +1423,21 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
         error!("Set uid {} fails: {}", option.uid(), err);
       }
 
+      // 以 root 运行时 setuid 不会清空 capabilities
+      if !capabilities.is_empty() {
+        drop_process_capabilities(&capabilities);
+      }
+
       // execvpe 运行用户程序
-      let program = option.program();
-      let path = program.clone();
+      let (path, args) = get_exec_args(option)?;
       let path = path.as_ref();
-      let args = option.arguments();
-      let args = [vec![program], args].concat();
       let args = args.as_slice();
-      let env = get_env(&option);
+      // execvpe 使用当前进程的 PATH 搜索程序
+      let jail_path = get_jail_path(option, Path::new("/"));
+      if let Some(path) = &jail_path {
+        std::env::set_var("PATH", path);
+      }
+      let env = get_env(option, &jail_path)?;
 
       {
         let args = args
@@ -443,25 +1447,61 @@ pub fn run(option: &CatBoxOption) -> Result<CatBoxResult, CatBoxError> {
         info!("Start running program {}", args.join(" "));
       }
 
+      // 用户自定义的初始化
+      if let Some(hook) = option.pre_exec() {
+        if let Err(err) = hook.call() {
+          error!("Pre-exec hook fails: {}", err);
+          pipe.write(format!("Pre-exec hook fails: {}", err))?;
+          pipe.close()?;
+          unsafe { libc::_exit(1) };
+        }
+      }
+
+      // 使用 seccomp 禁止网络，安装失败时不运行程序
+      if option.net_policy() == NetPolicy::Seccomp {
+        match deny_syscalls(&NET_SYSCALLS, Errno::EPERM) {
+          Ok(_) => debug!("Deny network by seccomp"),
+          Err(err) => exit_child(pipe, format!("Install seccomp filter fails: {}", err)),
+        }
+      }
+
+      // 最后再捕获输出到管道，避免混入子进程中的日志
+      if let Some((_, write)) = capture_stdout {
+        if let Err(err) = dup_inheritable(write, STDOUT_FILENO) {
+          exit_child(pipe, format!("Capture stdout fails: {}", err));
+        }
+      }
+      if let Some((_, write)) = capture_stderr {
+        if let Err(err) = dup_inheritable(write, STDERR_FILENO) {
+          exit_child(pipe, format!("Capture stderr fails: {}", err));
+        }
+      }
+
       // 启动 ptrace 追踪子进程
       if option.ptrace().is_some() {
         ptrace::traceme().unwrap();
       }
 
-      let result = execvpe(path, &args, env.as_slice());
-      if let Err(e) = result {
-        pipe.write(format!("Execvpe fails: {} (Errno: {:?})", &e.desc(), &e))?;
+      // 默认拒绝的 seccomp filter 最后安装，之后只剩 execve
+      if option.seccomp_default_deny() {
+        match allow_syscalls(&DEFAULT_ALLOWED_SYSCALLS, Errno::EPERM) {
+          Ok(_) => debug!("Deny syscalls by default by seccomp"),
+          Err(err) => exit_child(pipe, format!("Install seccomp filter fails: {}", err)),
+        }
+      }
 
-        error!("Execvpe fails: {}", e.desc());
-        info!("Submission path: {}", option.program().to_string_lossy());
-        let args = args
-          .iter()
-          .map(|cstr| cstr.to_string_lossy().into())
-          .collect::<Vec<Box<str>>>();
-        info!("Submission args: {}", args.join(" "));
+      let Err(e) = execvpe(path, args, env.as_slice());
+      pipe.write(format!("Execvpe fails: {} (Errno: {:?})", &e.desc(), &e))?;
 
-        pipe.close()?;
-      }
+      error!("Execvpe fails: {}", e.desc());
+      info!("Submission path: {}", option.program()?.to_string_lossy());
+      let args = args
+        .iter()
+        .map(|cstr| cstr.to_string_lossy().into())
+        .collect::<Vec<Box<str>>>();
+      info!("Submission args: {}", args.join(" "));
+
+      pipe.close()?;
 
       unsafe { libc::_exit(1) };
     }
@@ -1,15 +1,24 @@
+use std::collections::BTreeSet;
+use std::env;
 use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
 
 use cgroups_rs::cgroup_builder::CgroupBuilder;
 use cgroups_rs::cpu::CpuController;
 use cgroups_rs::cpuacct::{CpuAcct, CpuAcctController};
+use cgroups_rs::cpuset::CpuSetController;
 use cgroups_rs::memory::{MemController, MemSwap, Memory};
 use cgroups_rs::pid::PidController;
 use cgroups_rs::{Cgroup, CgroupPid, Controller, MaxValue};
-use log::{debug, error, warn, info};
+use log::{debug, error, info, warn};
+use nix::libc;
+use nix::sys::eventfd::{eventfd, EfdFlags};
 use nix::sys::resource::{getrusage, UsageWho};
 use nix::sys::time::TimeVal;
-use nix::unistd::Pid;
+use nix::unistd::{Pid, Uid, User};
 
 use crate::error::CatBoxError;
 use crate::CatBoxOption;
@@ -19,6 +28,18 @@ pub struct CatBoxCgroup {
   cgroup: Option<Cgroup>,
   enable_cpuacct: bool,
   enable_memory: bool,
+  /// Swap accounting (`CONFIG_MEMCG_SWAP`) is available
+  enable_memswap: bool,
+  /// Memory limit (unit: KB)
+  memory_limit: u64,
+  /// `RLIMIT_AS` of the child (unit: byte)
+  address_space_limit: Option<u64>,
+  /// eventfd notified on OOM of the cgroup v1 memory controller
+  oom_event: Option<File>,
+  /// Skip deleting the cgroup on drop
+  persist: bool,
+  /// Collect both cgroup and getrusage numbers
+  diagnose: bool,
 }
 
 #[derive(Debug)]
@@ -27,10 +48,39 @@ pub struct CatBoxUsage {
   time_user: u64,
   time_sys: u64,
   memory: u64,
+  memory_limit: u64,
+  address_space_limit: Option<u64>,
+  /// Peak memory is read from cgroup, which includes the page cache
+  cgroup_memory: bool,
+  /// OOM is recorded by cgroup
+  memory_limit_hit: bool,
+  diagnostics: Option<UsageDiagnostics>,
+  /// Elapsed real time from fork to the child terminates
+  wall_time: u64,
+}
+
+/// Time (unit: ms) and memory (unit: KB) measured by cgroup and getrusage side by side, see
+/// [`crate::CatBoxOptionBuilder::diagnose_usage`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UsageDiagnostics {
+  /// CPU time from cpuacct, or `None` if it is unavailable
+  pub cgroup_time: Option<u64>,
+  /// Peak memory from the memory controller, or `None` if it is unavailable
+  pub cgroup_memory: Option<u64>,
+  pub rusage_time: u64,
+  pub rusage_memory: u64,
 }
 
 impl CatBoxCgroup {
   pub fn new(option: &CatBoxOption, child: Pid) -> Result<Self, CatBoxError> {
+    let cgroup_name = format!("{}/{}.{}", option.cgroup(), option.cgroup(), child.as_raw());
+
+    // 显式关闭 cgroup，直接使用 getrusage 统计
+    if option.no_cgroup() {
+      info!("Skip initializing cgroup, use getrusage instead");
+      return Ok(Self::disabled(option, cgroup_name));
+    }
+
     let hierarchy = cgroups_rs::hierarchies::auto();
 
     info!(
@@ -55,24 +105,31 @@ impl CatBoxCgroup {
       .subsystems()
       .iter()
       .any(|subsystem| subsystem.controller_name() == "cpu");
+    // 只在绑定 CPU 时使用 cpuset
+    let enable_cpuset = !option.cpu_affinity().is_empty()
+      && hierarchy
+        .subsystems()
+        .iter()
+        .any(|subsystem| subsystem.controller_name() == "cpuset");
     let enable_pids = hierarchy
       .subsystems()
       .iter()
       .any(|subsystem| subsystem.controller_name() == "pids");
 
-    let cgroup_name = format!("{}/{}.{}", option.cgroup(), option.cgroup(), child.as_raw());
-
     info!("Start initializing cgroup {}", cgroup_name);
 
     let builder = CgroupBuilder::new(cgroup_name.as_str());
     let builder = if enable_memory {
       let memory_limit = option.memory_limit() as i64 * 1024 + 4 * 1024;
-      builder
+      let builder = builder
         .memory()
         .memory_soft_limit(memory_limit)
-        .memory_hard_limit(memory_limit)
-        .memory_swap_limit(memory_limit)
-        .done()
+        .memory_hard_limit(memory_limit);
+      if option.swap_accounting() {
+        builder.memory_swap_limit(memory_limit).done()
+      } else {
+        builder.done()
+      }
     } else {
       builder
     };
@@ -103,6 +160,13 @@ impl CatBoxCgroup {
     if enable_pids {
       supported_controller.push("pids".to_string());
     }
+    if enable_cpuset {
+      supported_controller.push("cpuset".to_string());
+    }
+    // cgroup v2 中需要先在父 cgroup 的 subtree_control 中开启控制器
+    if hierarchy.v2() {
+      enable_subtree_controllers(&hierarchy.root(), option.cgroup(), &supported_controller);
+    }
     let builder = builder.set_specified_controllers(supported_controller);
 
     let cgroup = match builder.build(hierarchy) {
@@ -112,12 +176,7 @@ impl CatBoxCgroup {
         if option.force() {
           return Err(CatBoxError::cgroup(err.to_string()));
         } else {
-          return Ok(CatBoxCgroup {
-            name: cgroup_name,
-            cgroup: None,
-            enable_cpuacct: false,
-            enable_memory: false,
-          });
+          return Ok(Self::disabled(option, cgroup_name));
         }
       }
     };
@@ -142,6 +201,13 @@ impl CatBoxCgroup {
           .controller_of()
           .ok_or(Box::<dyn Error>::from("Get memory controller fails"))?;
         memory.reset_max_usage()?;
+        if option.oom_kill_disable() {
+          // 关闭 OOM killer 后，超出内存限制的进程会被挂起而不是被杀死
+          match memory.disable_oom_killer() {
+            Ok(_) => info!("Disable cgroup OOM killer"),
+            Err(err) => error!("Disable cgroup OOM killer fails: {}", err),
+          }
+        }
         memory.add_task(&task)?;
         Ok(())
       };
@@ -149,8 +215,43 @@ impl CatBoxCgroup {
         enable_memory = false;
       }
     }
+    // 内核未开启 swap 统计时，只读取 memory 控制器
+    let enable_memswap = enable_memory
+      && option.swap_accounting()
+      && cgroup
+        .controller_of::<MemController>()
+        .is_some_and(|memory| {
+          let path = memory.path();
+          path.join("memory.memsw.max_usage_in_bytes").exists()
+            || path.join("memory.swap.current").exists()
+        });
+    if enable_memory && !enable_memswap {
+      info!("cgroup memory swap accounting is disabled");
+    }
+    let oom_event = match cgroup.controller_of::<MemController>() {
+      Some(memory) if enable_memory && !memory.v2() => match register_oom_event(memory) {
+        Ok(event) => Some(event),
+        Err(err) => {
+          error!("Register cgroup OOM event fails: {}", err);
+          None
+        }
+      },
+      _ => None,
+    };
     if enable_cpu {
       if let Some(cpu) = cgroup.controller_of::<CpuController>() {
+        if let Some(burst) = option.cpu_burst() {
+          // cgroups-rs 不支持设置 burst，直接写入控制文件
+          let file = if cpu.v2() {
+            "cpu.max.burst"
+          } else {
+            "cpu.cfs_burst_us"
+          };
+          match fs::write(cpu.path().join(file), burst.to_string()) {
+            Ok(_) => debug!("Set cgroup {} to {}", file, burst),
+            Err(err) => error!("Set cgroup {} fails: {}", file, err),
+          }
+        }
         if let Err(err) = cpu.add_task(&task) {
           error!("Add cgroup cpu task fails: {}", err)
         }
@@ -167,6 +268,26 @@ impl CatBoxCgroup {
         error!("Get pids cgroup controller fails")
       }
     }
+    if enable_cpuset {
+      let add_task = || -> Result<(), Box<dyn Error>> {
+        let cpuset: &CpuSetController = cgroup
+          .controller_of()
+          .ok_or(Box::<dyn Error>::from("Get cpuset controller fails"))?;
+        let cpus = option
+          .cpu_affinity()
+          .iter()
+          .map(|cpu| cpu.to_string())
+          .collect::<Vec<String>>()
+          .join(",");
+        cpuset.set_cpus(&cpus)?;
+        cpuset.add_task(&task)?;
+        Ok(())
+      };
+      // 失败时仍由子进程调用 sched_setaffinity 绑定
+      if let Err(err) = add_task() {
+        error!("Add cgroup cpuset task fails: {}", err)
+      }
+    }
 
     info!("Finish initializing cgroup {}", cgroup_name);
 
@@ -205,9 +326,39 @@ impl CatBoxCgroup {
       cgroup: Some(cgroup),
       enable_cpuacct,
       enable_memory,
+      enable_memswap,
+      memory_limit: option.memory_limit(),
+      address_space_limit: option.address_space_limit(),
+      oom_event,
+      persist: option.persist_cgroup(),
+      diagnose: option.diagnose_usage(),
     })
   }
 
+  /// Without cgroup, the usage is read by getrusage
+  fn disabled(option: &CatBoxOption, name: String) -> Self {
+    CatBoxCgroup {
+      name,
+      cgroup: None,
+      enable_cpuacct: false,
+      enable_memory: false,
+      enable_memswap: false,
+      memory_limit: option.memory_limit(),
+      address_space_limit: option.address_space_limit(),
+      oom_event: None,
+      persist: false,
+      diagnose: option.diagnose_usage(),
+    }
+  }
+
+  /// Name of the cgroup if it is kept after drop
+  pub fn persisted(&self) -> Option<String> {
+    match self.cgroup {
+      Some(_) if self.persist => Some(self.name.clone()),
+      _ => None,
+    }
+  }
+
   fn get_cpuacct(&self) -> Result<CpuAcct, Box<dyn Error>> {
     if self.enable_cpuacct {
       match &self.cgroup {
@@ -229,7 +380,7 @@ impl CatBoxCgroup {
     }
   }
 
-  fn get_memory(&self) -> Result<(Memory, MemSwap), Box<dyn Error>> {
+  fn get_memory(&self) -> Result<(Memory, Option<MemSwap>), Box<dyn Error>> {
     if self.enable_memory {
       match &self.cgroup {
         None => Err(Box::<dyn Error>::from("cgroup is None")),
@@ -239,8 +390,14 @@ impl CatBoxCgroup {
             .ok_or(Box::<dyn Error>::from("Get memory controller fails"))?;
           let mem = memory.memory_stat();
           debug!("mem.max_usage_in_bytes: {}", mem.max_usage_in_bytes);
-          let memswap = memory.memswap();
-          debug!("memswap.max_usage_in_bytes: {}", memswap.max_usage_in_bytes);
+          // memswap 不可用时不影响 memory 的读取
+          let memswap = if self.enable_memswap {
+            let memswap = memory.memswap();
+            debug!("memswap.max_usage_in_bytes: {}", memswap.max_usage_in_bytes);
+            Some(memswap)
+          } else {
+            None
+          };
           memory.reset_max_usage()?;
           Ok((mem, memswap))
         }
@@ -250,55 +407,217 @@ impl CatBoxCgroup {
     }
   }
 
-  pub fn usage(&self) -> CatBoxUsage {
-    let mut rusage = None;
+  /// Whether OOM happens in the cgroup, including the child frozen with the OOM killer disabled
+  fn oom_occurred(&self, mem: &Memory) -> bool {
+    if mem.oom_control.oom_kill > 0 || mem.oom_control.under_oom {
+      return true;
+    }
+    if let Some(mut event) = self.oom_event.as_ref() {
+      // eventfd 为非阻塞，未发生 OOM 时读取失败
+      let mut buf = [0; 8];
+      if event.read(&mut buf).is_ok_and(|len| len == 8) && u64::from_ne_bytes(buf) > 0 {
+        return true;
+      }
+    }
+    // cgroup v2 从 memory.events 读取 OOM 计数
+    let events = self
+      .cgroup
+      .as_ref()
+      .and_then(|cgroup| cgroup.controller_of::<MemController>())
+      .filter(|memory| memory.v2())
+      .and_then(|memory| fs::read_to_string(memory.path().join("memory.events")).ok());
+    events.is_some_and(|events| {
+      events.lines().any(|line| match line.split_once(' ') {
+        Some(("oom", count)) | Some(("oom_kill", count)) => count.trim() != "0",
+        _ => false,
+      })
+    })
+  }
+
+  /// Usage of the child, `child_usage` is returned by `wait4` and used when cgroup is not available
+  pub fn usage(&self, child_usage: Option<libc::rusage>) -> CatBoxUsage {
+    // 子进程已被回收时，退回到当前进程所有子进程的累计用量
+    let rusage =
+      child_usage.unwrap_or_else(|| *getrusage(UsageWho::RUSAGE_CHILDREN).unwrap().as_ref());
+    let rusage_user = TimeVal::from(rusage.ru_utime);
+    let rusage_sys = TimeVal::from(rusage.ru_stime);
+    let rusage_memory = rusage.ru_maxrss as u64;
+    let (mut cgroup_time, mut cgroup_memory) = (None, None);
 
+    // time 为总 CPU 时间，保证不小于 time_user + time_sys，与 getrusage 的结果一致
     let (time, time_user, time_sys) = match self.get_cpuacct() {
-      Ok(acct) => (
-        acct.usage / 1000000,
-        acct.usage_user / 1000000,
-        acct.usage_sys / 1000000,
-      ),
+      Ok(acct) => {
+        let time = std::cmp::max(acct.usage, acct.usage_user + acct.usage_sys) / 1000000;
+        cgroup_time = Some(time);
+        (time, acct.usage_user / 1000000, acct.usage_sys / 1000000)
+      }
       Err(_) => {
-        let usage = getrusage(UsageWho::RUSAGE_CHILDREN).unwrap();
-        rusage = Some(usage);
-        debug!("usage.user_time: {}", usage.user_time());
-        debug!("usage.system_time: {}", usage.system_time());
-        let time_user = usage.user_time();
-        let time_sys = usage.system_time();
+        debug!("usage.user_time: {}", rusage_user);
+        debug!("usage.system_time: {}", rusage_sys);
         (
-          microseconds(time_user + time_sys),
-          microseconds(time_user),
-          microseconds(time_sys),
+          microseconds(rusage_user + rusage_sys),
+          microseconds(rusage_user),
+          microseconds(rusage_sys),
         )
       }
     };
 
-    let memory = match self.get_memory() {
+    // 只以 OOM 作为触及内存限制的依据，failcnt 在回收 page cache 时也会增加
+    let (memory, limit_hit) = match self.get_memory() {
       Ok((mem, memswap)) => {
-        std::cmp::max(mem.max_usage_in_bytes, memswap.max_usage_in_bytes) / 1024
+        let swap_usage = memswap.map_or(0, |memswap| memswap.max_usage_in_bytes);
+        let memory = std::cmp::max(mem.max_usage_in_bytes, swap_usage) / 1024;
+        cgroup_memory = Some(memory);
+        (memory, self.oom_occurred(&mem))
       }
       Err(_) => {
-        let usage = rusage.unwrap_or_else(|| getrusage(UsageWho::RUSAGE_CHILDREN).unwrap());
-        debug!("usage.max_rss: {}", usage.max_rss());
-        usage.max_rss() as u64
+        debug!("usage.max_rss: {}", rusage_memory);
+        (rusage_memory, false)
       }
     };
 
+    // 诊断模式下额外记录 getrusage 的结果，与 cgroup 的结果对照
+    let diagnostics = if self.diagnose {
+      Some(UsageDiagnostics {
+        cgroup_time,
+        cgroup_memory,
+        rusage_time: microseconds(rusage_user + rusage_sys),
+        rusage_memory,
+      })
+    } else {
+      None
+    };
+
     CatBoxUsage {
       time,
       time_user,
       time_sys,
       memory,
+      memory_limit: self.memory_limit,
+      address_space_limit: self.address_space_limit,
+      cgroup_memory: cgroup_memory.is_some(),
+      memory_limit_hit: limit_hit,
+      diagnostics,
+      wall_time: 0,
     }
   }
 }
 
+/// Default cgroup parent name, read from `CATJ_CGROUP` or fallback to current user name
+pub fn default_cgroup() -> String {
+  env::var("CATJ_CGROUP").unwrap_or_else(|_| {
+    User::from_uid(Uid::current())
+      .ok()
+      .flatten()
+      .map(|user| user.name)
+      .unwrap_or_default()
+  })
+}
+
+/// Enable the controllers in `cgroup.subtree_control` of each cgroup from the root down to the
+/// parent (cgroup v2 only), so that the child cgroup created under the parent can use them.
+/// On a delegated subtree, the ancestors outside the delegation are usually enabled already.
+fn enable_subtree_controllers(root: &Path, parent: &str, controllers: &[String]) {
+  let mut dir = root.to_path_buf();
+  let mut ancestors = vec![dir.clone()];
+  for component in parent.split('/').filter(|c| !c.is_empty()) {
+    dir.push(component);
+    ancestors.push(dir.clone());
+  }
+
+  for dir in ancestors {
+    if let Err(err) = fs::create_dir_all(&dir) {
+      error!("Create cgroup {} fails: {}", dir.to_string_lossy(), err);
+      return;
+    }
+    let available = fs::read_to_string(dir.join("cgroup.controllers")).unwrap_or_default();
+    let enabled = fs::read_to_string(dir.join("cgroup.subtree_control")).unwrap_or_default();
+    for controller in controllers {
+      if enabled.split_whitespace().any(|c| c == controller) {
+        continue;
+      }
+      if !available.split_whitespace().any(|c| c == controller) {
+        warn!(
+          "cgroup controller {} is not available in {}",
+          controller,
+          dir.to_string_lossy()
+        );
+        continue;
+      }
+      match fs::write(
+        dir.join("cgroup.subtree_control"),
+        format!("+{}", controller),
+      ) {
+        Ok(_) => debug!(
+          "Enable cgroup controller {} in {}",
+          controller,
+          dir.to_string_lossy()
+        ),
+        Err(err) => error!(
+          "Enable cgroup controller {} in {} fails: {}",
+          controller,
+          dir.to_string_lossy(),
+          err
+        ),
+      }
+    }
+  }
+}
+
+/// Delete leftover sandbox cgroups under parent, which have no live tasks
+pub fn cleanup_stale_cgroups(parent: &str) -> Result<Vec<String>, CatBoxError> {
+  let hierarchy = cgroups_rs::hierarchies::auto();
+  let prefix = format!("{}.", parent);
+
+  // 收集所有子系统下符合命名规则的 cgroup
+  let mut names = BTreeSet::new();
+  for subsystem in hierarchy.subsystems() {
+    let dir = subsystem.to_controller().path().join(parent);
+    let entries = match fs::read_dir(&dir) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+      let name = entry.file_name().to_string_lossy().to_string();
+      let is_sandbox = name
+        .strip_prefix(&prefix)
+        .is_some_and(|pid| !pid.is_empty() && pid.chars().all(|c| c.is_ascii_digit()));
+      if is_sandbox && entry.path().is_dir() {
+        names.insert(name);
+      }
+    }
+  }
+
+  let mut removed = vec![];
+  for name in names {
+    let cgroup_name = format!("{}/{}", parent, name);
+    let cgroup = Cgroup::load(cgroups_rs::hierarchies::auto(), cgroup_name.as_str());
+    if !cgroup.tasks().is_empty() {
+      info!("Skip cgroup {} with live tasks", cgroup_name);
+      continue;
+    }
+    match cgroup.delete() {
+      Ok(_) => {
+        info!("Delete stale cgroup {}", cgroup_name);
+        removed.push(cgroup_name);
+      }
+      Err(err) => {
+        error!("Delete stale cgroup {} fails: {}", cgroup_name, err);
+      }
+    }
+  }
+  Ok(removed)
+}
+
 impl Drop for CatBoxCgroup {
   fn drop(&mut self) {
     if let Some(cgroup) = &self.cgroup {
-      debug!("Delete cgroup {}", self.name);
-      cgroup.delete().unwrap();
+      if self.persist {
+        info!("Persist cgroup {}", self.name);
+      } else {
+        debug!("Delete cgroup {}", self.name);
+        cgroup.delete().unwrap();
+      }
     }
   }
 }
@@ -319,6 +638,46 @@ impl CatBoxUsage {
   pub fn memory(&self) -> u64 {
     self.memory
   }
+
+  pub fn diagnostics(&self) -> Option<UsageDiagnostics> {
+    self.diagnostics
+  }
+
+  pub fn wall_time(&self) -> u64 {
+    self.wall_time
+  }
+
+  pub(crate) fn with_wall_time(mut self, wall_time: u64) -> Self {
+    self.wall_time = wall_time;
+    self
+  }
+
+  // cgroup 的峰值包含 page cache，只以 OOM 作为超限的依据，wait4 返回的子进程峰值则直接与限制比较；
+  // 设置了 RLIMIT_AS 且进程异常退出时，峰值接近地址空间限制 (1/16) 说明分配因该限制失败
+  pub fn memory_limit_exceeded(&self, failed: bool) -> bool {
+    let limit_hit = if self.cgroup_memory {
+      self.memory_limit_hit
+    } else {
+      self.memory > self.memory_limit
+    };
+    let address_space_hit = self.address_space_limit.is_some_and(|limit| {
+      let limit = limit / 1024;
+      self.memory + limit / 16 >= limit
+    });
+    limit_hit || (failed && address_space_hit)
+  }
+}
+
+/// 通过 cgroup.event_control 注册 OOM 通知，发生 OOM 时 eventfd 计数增加
+fn register_oom_event(memory: &MemController) -> Result<File, Box<dyn Error>> {
+  let control = File::open(memory.path().join("memory.oom_control"))?;
+  let fd = eventfd(0, EfdFlags::EFD_CLOEXEC | EfdFlags::EFD_NONBLOCK)?;
+  let event = unsafe { File::from_raw_fd(fd) };
+  fs::write(
+    memory.path().join("cgroup.event_control"),
+    format!("{} {}", fd, control.as_raw_fd()),
+  )?;
+  Ok(event)
 }
 
 fn microseconds(val: TimeVal) -> u64 {
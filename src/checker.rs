@@ -0,0 +1,166 @@
+//! Checker output parsing
+
+use std::fs;
+
+use log::info;
+use tempfile::tempdir;
+
+use crate::{CatBoxError, CatBoxOption};
+
+/// How to read the verdict from checker
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CheckerFormat {
+  /// testlib style exit code (0: AC, 1: WA, 2: PE, 7: points, others: FAIL)
+  ExitCode,
+  /// `key=value` pairs in stdout, e.g. `result=AC score=0.5`
+  Kv,
+}
+
+/// Checker verdict
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CheckVerdict {
+  Accepted,
+  WrongAnswer,
+  PresentationError,
+  /// Partially correct with score
+  Partial,
+  /// Checker itself fails
+  Fail,
+}
+
+/// Checker result
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+  verdict: CheckVerdict,
+  score: Option<f64>,
+  message: String,
+}
+
+impl CheckerFormat {
+  /// Try parsing checker format string
+  pub fn parse<S: AsRef<str>>(text: S) -> Result<Self, CatBoxError> {
+    match text.as_ref().trim().to_ascii_lowercase().as_str() {
+      "exitcode" | "testlib" => Ok(CheckerFormat::ExitCode),
+      "kv" => Ok(CheckerFormat::Kv),
+      _ => Err(CatBoxError::cli("Parse checker format string fails")),
+    }
+  }
+
+  /// Parse checker exit status and stdout into result
+  pub fn parse_result(
+    &self,
+    status: Option<i32>,
+    stdout: &str,
+  ) -> Result<CheckResult, CatBoxError> {
+    match self {
+      CheckerFormat::ExitCode => {
+        let verdict = match status {
+          Some(0) => CheckVerdict::Accepted,
+          Some(1) => CheckVerdict::WrongAnswer,
+          Some(2) => CheckVerdict::PresentationError,
+          Some(7) => CheckVerdict::Partial,
+          _ => CheckVerdict::Fail,
+        };
+        let message = stdout.trim();
+        if verdict == CheckVerdict::Partial {
+          // testlib 输出格式为 points <score> <message>
+          let message = message.strip_prefix("points").unwrap_or(message).trim();
+          let (score, message) = message.split_once(' ').unwrap_or((message, ""));
+          let score = score
+            .parse::<f64>()
+            .map_err(|_| CatBoxError::cli("Parse checker score fails"))?;
+          Ok(CheckResult {
+            verdict,
+            score: Some(score),
+            message: message.trim().to_string(),
+          })
+        } else {
+          Ok(CheckResult {
+            verdict,
+            score: None,
+            message: message.to_string(),
+          })
+        }
+      }
+      CheckerFormat::Kv => {
+        let mut verdict = None;
+        let mut score = None;
+        let mut message = vec![];
+        for token in stdout.split_whitespace() {
+          match token.split_once('=') {
+            Some(("result", value)) | Some(("verdict", value)) => {
+              verdict = Some(CheckVerdict::parse(value)?);
+            }
+            Some(("score", value)) | Some(("points", value)) => {
+              let value = value
+                .parse::<f64>()
+                .map_err(|_| CatBoxError::cli("Parse checker score fails"))?;
+              score = Some(value);
+            }
+            _ => message.push(token),
+          }
+        }
+        match verdict {
+          Some(verdict) => Ok(CheckResult {
+            verdict,
+            score,
+            message: message.join(" "),
+          }),
+          None => Err(CatBoxError::cli("Checker does not output result")),
+        }
+      }
+    }
+  }
+}
+
+impl CheckVerdict {
+  /// Try parsing verdict keyword
+  pub fn parse<S: AsRef<str>>(text: S) -> Result<Self, CatBoxError> {
+    match text.as_ref().trim().to_ascii_uppercase().as_str() {
+      "AC" | "OK" | "ACCEPTED" => Ok(CheckVerdict::Accepted),
+      "WA" | "WRONG_ANSWER" => Ok(CheckVerdict::WrongAnswer),
+      "PE" | "PRESENTATION_ERROR" => Ok(CheckVerdict::PresentationError),
+      "PC" | "PARTIAL" | "POINTS" => Ok(CheckVerdict::Partial),
+      "FAIL" => Ok(CheckVerdict::Fail),
+      _ => Err(CatBoxError::cli("Parse checker verdict string fails")),
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      CheckVerdict::Accepted => "AC",
+      CheckVerdict::WrongAnswer => "WA",
+      CheckVerdict::PresentationError => "PE",
+      CheckVerdict::Partial => "PC",
+      CheckVerdict::Fail => "FAIL",
+    }
+  }
+}
+
+impl CheckResult {
+  pub fn verdict(&self) -> CheckVerdict {
+    self.verdict
+  }
+
+  /// Score reported by checker
+  pub fn score(&self) -> Option<f64> {
+    self.score
+  }
+
+  pub fn message(&self) -> &String {
+    &self.message
+  }
+}
+
+/// Run checker and parse its verdict, checker stdout is captured
+pub fn check(option: &CatBoxOption, format: CheckerFormat) -> Result<CheckResult, CatBoxError> {
+  let dir = tempdir()?;
+  let stdout = dir.path().join("checker.out");
+  let option = option.with_stdout(stdout.to_string_lossy().to_string());
+
+  let result = crate::run(&option)?;
+  let text = fs::read_to_string(&stdout).unwrap_or_default();
+  info!("Checker exits with {:?}: {}", result.status(), text.trim());
+
+  format.parse_result(*result.status(), &text)
+}
@@ -0,0 +1,378 @@
+//! Output comparator
+
+use std::fs::File;
+use std::io::{stdin, Read};
+use std::path::Path;
+
+use log::info;
+
+use crate::CatBoxError;
+
+/// Size of each chunk read from the compared files
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Compare mode
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompareMode {
+  /// Compare byte by byte
+  Strict,
+  /// Compare whitespace separated tokens
+  Token,
+  /// Compare line by line, ignoring trailing whitespace of each line and trailing blank lines
+  Lenient,
+}
+
+/// Position of the first difference in the output
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CompareDifference {
+  /// Byte offset (start from 0)
+  byte: u64,
+  /// Line number (start from 1)
+  line: u64,
+  /// Token index (start from 1)
+  token: u64,
+}
+
+/// Compare result
+#[derive(Debug, Clone)]
+pub struct CompareResult {
+  difference: Option<CompareDifference>,
+}
+
+/// Streaming comparator, which reads files in chunks and stops at the first mismatch
+#[derive(Debug, Clone)]
+pub struct Comparator {
+  mode: CompareMode,
+  max_bytes: Option<u64>,
+}
+
+/// Buffered byte stream over a reader
+struct ByteStream<R: Read> {
+  reader: R,
+  buf: Vec<u8>,
+  pos: usize,
+  len: usize,
+}
+
+/// Current position while scanning the output
+#[derive(Debug, Copy, Clone)]
+struct Cursor {
+  byte: u64,
+  line: u64,
+  token: u64,
+  in_token: bool,
+}
+
+impl CompareMode {
+  /// Try parsing compare mode string
+  pub fn parse<S: AsRef<str>>(text: S) -> Result<Self, CatBoxError> {
+    match text.as_ref().trim().to_ascii_lowercase().as_str() {
+      "strict" | "exact" => Ok(CompareMode::Strict),
+      "token" | "word" => Ok(CompareMode::Token),
+      "lenient" | "line" => Ok(CompareMode::Lenient),
+      _ => Err(CatBoxError::cli("Parse compare mode string fails")),
+    }
+  }
+}
+
+impl CompareDifference {
+  pub fn byte(&self) -> u64 {
+    self.byte
+  }
+
+  pub fn line(&self) -> u64 {
+    self.line
+  }
+
+  pub fn token(&self) -> u64 {
+    self.token
+  }
+}
+
+impl CompareResult {
+  /// Whether output is the same with answer
+  pub fn same(&self) -> bool {
+    self.difference.is_none()
+  }
+
+  /// Position of the first difference
+  pub fn difference(&self) -> &Option<CompareDifference> {
+    &self.difference
+  }
+}
+
+impl Comparator {
+  /// Create a comparator with compare mode
+  pub fn new(mode: CompareMode) -> Self {
+    Comparator {
+      mode,
+      max_bytes: None,
+    }
+  }
+
+  /// Only compare the first `value` bytes of each file
+  pub fn max_bytes(mut self, value: u64) -> Self {
+    self.max_bytes = Some(value);
+    self
+  }
+
+  /// Set max compared bytes or not
+  pub fn set_max_bytes(mut self, value: Option<u64>) -> Self {
+    self.max_bytes = value;
+    self
+  }
+
+  pub fn mode(&self) -> CompareMode {
+    self.mode
+  }
+
+  /// Compare output file with answer file, either of them can be `-` to read from stdin
+  pub fn compare_files<OP: AsRef<Path>, AP: AsRef<Path>>(
+    &self,
+    output: OP,
+    answer: AP,
+  ) -> Result<CompareResult, CatBoxError> {
+    info!(
+      "Compare output {} with answer {}",
+      output.as_ref().to_string_lossy(),
+      answer.as_ref().to_string_lossy()
+    );
+    if is_stdin(&output) && is_stdin(&answer) {
+      return Err(CatBoxError::cli(
+        "Output and answer can not both be read from stdin",
+      ));
+    }
+    let output = open_input(output)?;
+    let answer = open_input(answer)?;
+    self.compare(output, answer)
+  }
+
+  /// Compare output stream with answer stream
+  pub fn compare<OR: Read, AR: Read>(
+    &self,
+    output: OR,
+    answer: AR,
+  ) -> Result<CompareResult, CatBoxError> {
+    let limit = self.max_bytes.unwrap_or(u64::MAX);
+    let mut output = ByteStream::new(output.take(limit));
+    let mut answer = ByteStream::new(answer.take(limit));
+    let difference = match self.mode {
+      CompareMode::Strict => Self::compare_strict(&mut output, &mut answer)?,
+      CompareMode::Token => Self::compare_token(&mut output, &mut answer)?,
+      CompareMode::Lenient => Self::compare_lenient(&mut output, &mut answer)?,
+    };
+    Ok(CompareResult { difference })
+  }
+
+  fn compare_strict<OR: Read, AR: Read>(
+    output: &mut ByteStream<OR>,
+    answer: &mut ByteStream<AR>,
+  ) -> Result<Option<CompareDifference>, CatBoxError> {
+    let mut cursor = Cursor::new();
+    loop {
+      let out = output.next()?;
+      let ans = answer.next()?;
+      if out != ans {
+        return Ok(Some(cursor.difference(out)));
+      }
+      match out {
+        Some(c) => cursor.advance(c),
+        None => return Ok(None),
+      }
+    }
+  }
+
+  fn compare_token<OR: Read, AR: Read>(
+    output: &mut ByteStream<OR>,
+    answer: &mut ByteStream<AR>,
+  ) -> Result<Option<CompareDifference>, CatBoxError> {
+    let mut cursor = Cursor::new();
+    loop {
+      while let Some(c) = output.peek()? {
+        if !is_space(c) {
+          break;
+        }
+        cursor.advance(c);
+        output.next()?;
+      }
+      while let Some(c) = answer.peek()? {
+        if !is_space(c) {
+          break;
+        }
+        answer.next()?;
+      }
+
+      let start = cursor;
+      match (output.peek()?, answer.peek()?) {
+        (None, None) => return Ok(None),
+        (None, _) | (_, None) => return Ok(Some(start.next_token())),
+        _ => {}
+      }
+
+      loop {
+        let out = output.peek()?.filter(|c| !is_space(*c));
+        let ans = answer.peek()?.filter(|c| !is_space(*c));
+        match (out, ans) {
+          (None, None) => break,
+          (Some(out), Some(ans)) if out == ans => {
+            cursor.advance(out);
+            output.next()?;
+            answer.next()?;
+          }
+          _ => return Ok(Some(start.next_token())),
+        }
+      }
+    }
+  }
+
+  fn compare_lenient<OR: Read, AR: Read>(
+    output: &mut ByteStream<OR>,
+    answer: &mut ByteStream<AR>,
+  ) -> Result<Option<CompareDifference>, CatBoxError> {
+    let mut cursor = Cursor::new();
+    loop {
+      let out = output.read_line()?;
+      let ans = answer.read_line()?;
+      if out.is_none() && ans.is_none() {
+        return Ok(None);
+      }
+
+      // 文件结束后视为空行，从而忽略末尾的空行
+      let out_line = trim_end(out.as_deref().unwrap_or_default());
+      let ans_line = trim_end(ans.as_deref().unwrap_or_default());
+      if out_line != ans_line {
+        let common = out_line
+          .iter()
+          .zip(ans_line.iter())
+          .take_while(|(out, ans)| out == ans)
+          .count();
+        for c in &out_line[..common] {
+          cursor.advance(*c);
+        }
+        return Ok(Some(cursor.difference(out_line.get(common).copied())));
+      }
+      for c in out.unwrap_or_default() {
+        cursor.advance(c);
+      }
+    }
+  }
+}
+
+impl<R: Read> ByteStream<R> {
+  fn new(reader: R) -> Self {
+    ByteStream {
+      reader,
+      buf: vec![0; CHUNK_SIZE],
+      pos: 0,
+      len: 0,
+    }
+  }
+
+  fn peek(&mut self) -> Result<Option<u8>, CatBoxError> {
+    if self.pos == self.len {
+      self.len = self.reader.read(&mut self.buf)?;
+      self.pos = 0;
+    }
+    if self.pos < self.len {
+      Ok(Some(self.buf[self.pos]))
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn next(&mut self) -> Result<Option<u8>, CatBoxError> {
+    let c = self.peek()?;
+    if c.is_some() {
+      self.pos += 1;
+    }
+    Ok(c)
+  }
+
+  /// Read the next line including `\n`, or `None` at the end of stream
+  fn read_line(&mut self) -> Result<Option<Vec<u8>>, CatBoxError> {
+    let mut line = vec![];
+    while let Some(c) = self.next()? {
+      line.push(c);
+      if c == b'\n' {
+        break;
+      }
+    }
+    if line.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some(line))
+    }
+  }
+}
+
+impl Cursor {
+  fn new() -> Self {
+    Cursor {
+      byte: 0,
+      line: 1,
+      token: 0,
+      in_token: false,
+    }
+  }
+
+  fn advance(&mut self, c: u8) {
+    self.byte += 1;
+    if c == b'\n' {
+      self.line += 1;
+    }
+    if is_space(c) {
+      self.in_token = false;
+    } else if !self.in_token {
+      self.token += 1;
+      self.in_token = true;
+    }
+  }
+
+  /// Difference at the start of the next token
+  fn next_token(&self) -> CompareDifference {
+    CompareDifference {
+      byte: self.byte,
+      line: self.line,
+      token: self.token + 1,
+    }
+  }
+
+  /// Difference at the current position, `c` is the output byte here
+  fn difference(&self, c: Option<u8>) -> CompareDifference {
+    let starts_token = c.is_some_and(|c| !is_space(c)) && !self.in_token;
+    CompareDifference {
+      byte: self.byte,
+      line: self.line,
+      token: if starts_token || self.token == 0 {
+        self.token + 1
+      } else {
+        self.token
+      },
+    }
+  }
+}
+
+fn is_stdin<P: AsRef<Path>>(path: &P) -> bool {
+  path.as_ref() == Path::new("-")
+}
+
+/// Open file, or stdin for `-`
+fn open_input<P: AsRef<Path>>(path: P) -> Result<Box<dyn Read>, CatBoxError> {
+  if is_stdin(&path) {
+    Ok(Box::new(stdin().lock()))
+  } else {
+    Ok(Box::new(File::open(path)?))
+  }
+}
+
+fn trim_end(line: &[u8]) -> &[u8] {
+  let len = line
+    .iter()
+    .rposition(|c| !is_space(*c))
+    .map_or(0, |i| i + 1);
+  &line[..len]
+}
+
+fn is_space(c: u8) -> bool {
+  c.is_ascii_whitespace()
+}
@@ -1,36 +1,66 @@
+use std::cmp::max;
 use std::env;
 use std::ffi::CString;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use log::{debug, error};
+use nix::fcntl::{fcntl, FcntlArg};
 use nix::libc;
 use nix::mount::{umount2, MntFlags};
+use nix::sys::signal::Signal;
+use nix::sys::socket::{socketpair, AddressFamily, SockFlag, SockType};
 use nix::unistd::{Gid, Group, Uid, User};
+use path_absolutize::Absolutize;
 use tempfile::tempdir;
 
-use crate::context::{CatBoxCompileContext, CatBoxContext, CatBoxJudgeContext, CatBoxRunContext};
-use crate::syscall::{RestrictedSyscall, SyscallFilter};
-use crate::utils::mount::MountPoint;
-use crate::utils::{into_c_string, parse_env, GidType, MemoryLimitType, TimeLimitType, UidType};
+use crate::cgroup::default_cgroup;
+use crate::compare::{Comparator, CompareMode};
+use crate::context::{
+  CatBoxCompileContext, CatBoxContext, CatBoxJudgeContext, CatBoxResult, CatBoxRunContext,
+  ColorChoice, CompleteCallback, Limits, PreExecHook, ReportOptions, WATCHDOG_GRACE,
+};
+use crate::syscall::{NetPolicy, RestrictedSyscall, SyscallFilter};
+use crate::utils::capability::DropCapabilities;
+use crate::utils::mount::{is_mount_point, resolve_dependencies, MountOrigin, MountPoint};
+use crate::utils::{
+  into_c_string, open_pty, parse_env, read_env_file, GidType, MemoryLimitType, TimeLimitType,
+  UidType,
+};
 use crate::{CatBox, CatBoxError, CatBoxOption};
 
+/// Index of the next uid in the pool, shared by all the builders
+static UID_POOL_INDEX: AtomicUsize = AtomicUsize::new(0);
+
 /// Build CatBox
 pub struct CatBoxBuilder {
   context: Box<dyn CatBoxContext>,
   options: Vec<CatBoxOption>,
+  callbacks: Vec<Option<CompleteCallback>>,
   env: Vec<(String, String)>,
   force: Option<bool>,
   time_limit: Option<TimeLimitType>,
+  wall_time_limit: Option<TimeLimitType>,
   memory_limit: Option<MemoryLimitType>,
+  output_limit: Option<u64>,
   uid: Option<UidType>,
   gid: Option<GidType>,
+  /// Uids assigned to the commands in rotation
+  uid_pool: Vec<UidType>,
   cwd: Option<PathBuf>,
+  total_wall_budget: Option<TimeLimitType>,
+  color: ColorChoice,
+  report_options: ReportOptions,
+  lite: bool,
 }
 
 /// Build CatBox running option
 pub struct CatBoxOptionBuilder {
   parent: CatBoxBuilder,
   option: CatBoxOption,
+  on_complete: Option<CompleteCallback>,
 }
 
 impl CatBoxBuilder {
@@ -39,13 +69,21 @@ impl CatBoxBuilder {
     CatBoxBuilder {
       context,
       options: vec![],
+      callbacks: vec![],
       env: vec![],
       force: None,
       time_limit: None,
+      wall_time_limit: None,
       memory_limit: None,
+      output_limit: None,
       uid: None,
       gid: None,
+      uid_pool: vec![],
       cwd: None,
+      total_wall_budget: None,
+      color: ColorChoice::Auto,
+      report_options: ReportOptions::default(),
+      lite: false,
     }
   }
 
@@ -81,10 +119,18 @@ impl CatBoxBuilder {
     if let Some(time_limit) = self.time_limit {
       option.time_limit = time_limit;
     }
+    // Set default wall time limit
+    if let Some(wall_time_limit) = self.wall_time_limit {
+      option.wall_time_limit = Some(wall_time_limit);
+    }
     // Set default memory limit
     if let Some(memory_limit) = self.memory_limit {
       option.memory_limit = memory_limit;
     }
+    // Set default output limit
+    if let Some(output_limit) = self.output_limit {
+      option.output_limit = output_limit;
+    }
     // Set default force mode
     if let Some(force) = self.force {
       option.force = force;
@@ -97,6 +143,11 @@ impl CatBoxBuilder {
     if let Some(gid) = self.gid {
       option.gid = Gid::from(gid);
     }
+    // Take the next uid from the pool
+    if !self.uid_pool.is_empty() {
+      let index = UID_POOL_INDEX.fetch_add(1, Ordering::Relaxed);
+      option.uid = Uid::from(self.uid_pool[index % self.uid_pool.len()]);
+    }
     // Set default cwd
     if let Some(cwd) = &self.cwd {
       option.cwd = cwd.clone();
@@ -105,10 +156,16 @@ impl CatBoxBuilder {
     for env_pair in self.env.iter() {
       option.env.push(env_pair.clone());
     }
+    // Disable ptrace and chroot in lite mode
+    if self.lite {
+      option.ptrace = None;
+      option.chroot = None;
+    }
 
     CatBoxOptionBuilder {
       parent: self,
       option,
+      on_complete: None,
     }
   }
 
@@ -117,21 +174,66 @@ impl CatBoxBuilder {
     CatBox {
       context: self.context,
       options: self.options,
+      callbacks: self.callbacks,
+      total_wall_budget: self.total_wall_budget,
+      pipeline_timeout: false,
+      color: self.color,
+      report_options: self.report_options,
     }
   }
 
+  /// Set whether to use ANSI colors in the human readable report (default: [`ColorChoice::Auto`])
+  pub fn color(mut self, color: ColorChoice) -> Self {
+    self.color = color;
+    self
+  }
+
+  /// Set the options of the human readable report, e.g. localized labels
+  pub fn report_options(mut self, options: ReportOptions) -> Self {
+    self.report_options = options;
+    self
+  }
+
+  /// Set the total real time budget across all the commands (unit: ms).
+  /// Each command is limited to the remaining budget, and the remaining commands are skipped
+  /// once the budget is exhausted.
+  pub fn total_wall_budget(mut self, value: TimeLimitType) -> Self {
+    self.total_wall_budget = Some(value);
+    self
+  }
+
+  /// Run trusted code (e.g. generators) with resource limits only: ptrace and chroot are disabled
+  /// for the following commands, while cgroup and rlimit are still enforced, which reduces the
+  /// overhead. Same as calling `disable_ptrace()` and `set_chroot(false)` on each command.
+  pub fn sandboxed_lite(mut self) -> Self {
+    self.lite = true;
+    self
+  }
+
   /// Set default time limit
   pub fn set_default_time_limit(mut self, value: Option<TimeLimitType>) -> Self {
     self.time_limit = value;
     self
   }
 
+  /// Set default wall time limit
+  pub fn set_default_wall_time_limit(mut self, value: Option<TimeLimitType>) -> Self {
+    self.wall_time_limit = value;
+    self
+  }
+
   /// Set default memory limit
   pub fn set_default_memory_limit(mut self, value: Option<MemoryLimitType>) -> Self {
     self.memory_limit = value;
     self
   }
 
+  /// Set default output limit
+  pub fn set_default_output_limit(mut self, value: Option<u64>) -> Self {
+    self.output_limit = value;
+    self
+  }
+
   /// Set default force mode
   pub fn set_default_force(mut self, flag: bool) -> Self {
     self.force = Some(flag);
@@ -150,6 +252,14 @@ impl CatBoxBuilder {
     self
   }
 
+  /// Assign uids to the commands from the pool in rotation, which continues across all the
+  /// CatBox in the process, so that successive runs do not share the uid while files of the
+  /// previous run may linger. It overrides the default uid.
+  pub fn uid_pool(mut self, pool: Vec<UidType>) -> Self {
+    self.uid_pool = pool;
+    self
+  }
+
   /// Set current user
   pub fn set_current_user(mut self, flag: bool) -> Self {
     if flag {
@@ -173,6 +283,12 @@ impl CatBoxBuilder {
     }
     Ok(self)
   }
+
+  /// Parse default env list from a file of `KEY=VALUE` lines, ignoring blank lines and `#` comments
+  pub fn parse_env_file<P: AsRef<Path>>(self, path: P) -> Result<Self, CatBoxError> {
+    let list = read_env_file(path.as_ref())?;
+    self.parse_env_list(list)
+  }
 }
 
 impl CatBoxOptionBuilder {
@@ -180,6 +296,7 @@ impl CatBoxOptionBuilder {
   pub fn done(self) -> CatBoxBuilder {
     let mut builder = self.parent;
     builder.options.push(self.option);
+    builder.callbacks.push(self.on_complete);
     builder
   }
 
@@ -189,24 +306,198 @@ impl CatBoxOptionBuilder {
     builder.build()
   }
 
+  /// Invoke the callback with the result after this command finishes, before the next one starts.
+  /// The subsequent commands are skipped only when the callback returns `false`.
+  pub fn on_complete<F: FnOnce(&CatBoxResult) -> bool + 'static>(mut self, callback: F) -> Self {
+    self.on_complete = Some(Box::new(callback));
+    self
+  }
+
   /// Set label
   pub fn label(mut self, label: String) -> Self {
     self.option.label = label;
     self
   }
 
-  /// Set time limit (unit: ms)
+  /// Set the program name shown in reports, while the program is still the path to exec.
+  /// For example, `python3` may be displayed as `Python 3`.
+  pub fn display_name<S: Into<String>>(mut self, name: S) -> Self {
+    self.option.display_name = Some(name.into());
+    self
+  }
+
+  /// Wrap the command with a launcher (e.g. `stdbuf -o0`), so that the child execs
+  /// `launcher arguments... program arguments...`. The launcher is searched in PATH, and mounted
+  /// read-only into the chroot jail if missing.
+  pub fn launcher<PS: Into<String>, AS: Into<String>>(
+    mut self,
+    program: PS,
+    arguments: Vec<AS>,
+  ) -> Self {
+    self.option.launcher = Some((
+      program.into(),
+      arguments.into_iter().map(|a| a.into()).collect(),
+    ));
+    self
+  }
+
+  /// Set CPU time limit (unit: ms), which is enforced by `RLIMIT_CPU` (SIGXCPU).
+  /// The wall time is loosely bounded to the time limit + 1 second by default, see
+  /// [`CatBoxOptionBuilder::wall_time_limit`].
   pub fn time_limit(mut self, value: TimeLimitType) -> Self {
     self.option.time_limit = value;
     self
   }
 
+  /// Set `RLIMIT_CPU` hard limit to exactly the time limit instead of one extra second, with the
+  /// soft limit one second lower for a SIGXCPU warning.
+  /// Note that the kernel checks `RLIMIT_CPU` in whole seconds, so the limit is rounded up.
+  pub fn strict_cpu_limit(mut self, flag: bool) -> Self {
+    self.option.strict_cpu_limit = flag;
+    self
+  }
+
+  /// Base the time limit on wall time instead of CPU time.
+  /// `RLIMIT_CPU` counts the aggregate CPU time of all the threads, so a multithreaded program
+  /// running on multiple cores reaches it faster than the wall clock. When enabled, `RLIMIT_CPU`
+  /// is not set and the child is killed by SIGALRM exactly after the time limit in wall time.
+  pub fn wall_time_basis(mut self, flag: bool) -> Self {
+    self.option.wall_time_basis = flag;
+    self
+  }
+
+  /// Set wall time limit (unit: ms), which is enforced by a real timer (SIGALRM) exactly after
+  /// the limit, independent of the CPU time limit. It bounds sleeping or blocked programs, which
+  /// use little CPU time. It also sets the deadline of the parent-side watchdog, see
+  /// [`CatBoxOption::watchdog_time_limit`].
+  pub fn wall_time_limit(mut self, value: TimeLimitType) -> Self {
+    self.option.wall_time_limit = Some(value);
+    self
+  }
+
+  /// Send this signal to the child on wall time limit, instead of arming `alarm` in the child.
+  /// The timer lives in the parent process, so it does not collide with the program's own
+  /// `alarm` or `setitimer` (SIGALRM).
+  pub fn timeout_signal(mut self, signal: Signal) -> Self {
+    self.option.timeout_signal = Some(signal);
+    self
+  }
+
+  /// Send this signal instead of SIGKILL to terminate the child on forbidden syscalls (which are
+  /// skipped) and by the wall time watchdog, so that it may clean up. The watchdog escalates to
+  /// SIGKILL if the child is still alive after a grace period.
+  pub fn kill_signal(mut self, signal: Signal) -> Self {
+    self.option.kill_signal = signal;
+    self
+  }
+
   /// Set memory limit (unit: KB)
   pub fn memory_limit(mut self, value: MemoryLimitType) -> Self {
     self.option.memory_limit = value;
     self
   }
 
+  /// Set stack size limit (unit: byte), which is unlimited by default
+  pub fn stack_size(mut self, bytes: u64) -> Self {
+    self.option.stack_size = bytes;
+    self
+  }
+
+  /// Limit the virtual address space by `RLIMIT_AS` (unit: byte), which is unlimited by default,
+  /// so that memory is still capped when cgroup falls back. Exceeding it fails the allocation
+  /// (e.g. `mmap` returns `ENOMEM`) instead of killing the child.
+  ///
+  /// Note that the address space counts reserved but untouched memory, e.g. the stack of each
+  /// thread and the heap reserved by JVM, so programs reserving huge virtual space may fail with
+  /// a limit close to the memory limit. Leave it unset for them, or set a larger slack.
+  pub fn address_space_limit(mut self, bytes: u64) -> Self {
+    self.option.address_space_limit = Some(bytes);
+    self
+  }
+
+  /// Limit the virtual address space by `RLIMIT_AS` to the memory limit plus `slack` (unit: byte),
+  /// see [`CatBoxOptionBuilder::address_space_limit`], which takes precedence over it.
+  pub fn address_space_limit_with_slack(mut self, slack: u64) -> Self {
+    self.option.address_space_slack = Some(slack);
+    self
+  }
+
+  /// Set output file size limit by `RLIMIT_FSIZE` (unit: byte, default: 256 MB).
+  /// Exceeding it raises SIGXFSZ.
+  pub fn output_limit(mut self, bytes: u64) -> Self {
+    self.option.output_limit = bytes;
+    self
+  }
+
+  /// Apply all the resource limits at once
+  pub fn limits(mut self, limits: Limits) -> Self {
+    self.option.wall_time_limit = limits.wall_time_ms;
+    self.option.stack_size = limits.stack_bytes.unwrap_or(u64::MAX);
+    self
+      .time_limit(limits.cpu_time_ms)
+      .memory_limit(limits.memory_kb)
+      .output_limit(limits.output_bytes)
+      .process(limits.processes)
+  }
+
+  /// Set whether to read and limit swap usage by cgroup (default: true).
+  /// It is detected automatically, but can be disabled on hosts without swap accounting
+  /// (`CONFIG_MEMCG_SWAP`), where the peak memory is read from the memory controller only.
+  pub fn swap_accounting(mut self, flag: bool) -> Self {
+    self.option.swap_accounting = flag;
+    self
+  }
+
+  /// Disable the cgroup OOM killer (`memory.oom_control`, cgroup v1 only), so the child is not
+  /// killed by SIGKILL on exceeding memory limit. Note that the kernel freezes the child instead of
+  /// failing its allocation, until memory is freed or it is killed: only fatal signals wake it up,
+  /// so keep ptrace disabled to let the time limit alarm kill the frozen child, otherwise the
+  /// sandbox may hang forever.
+  pub fn oom_kill_disable(mut self, flag: bool) -> Self {
+    self.option.oom_kill_disable = flag;
+    self
+  }
+
+  /// Run without creating any cgroup, and account time and memory by getrusage only, e.g. in nested
+  /// containers where cgroup writes are forbidden. Unlike the silent fallback, it never tries the
+  /// cgroup, so the memory and process limits rely on rlimit only.
+  pub fn no_cgroup(mut self) -> Self {
+    self.option.no_cgroup = true;
+    self
+  }
+
+  /// Keep the cgroup after the run for external inspection, like the chroot jail in debug mode.
+  /// Its path is returned by [`CatBoxResult::cgroup`], and the caller must delete it then, e.g. by
+  /// `rmdir` or [`crate::CatBox::cleanup_stale`].
+  pub fn persist_cgroup(mut self, flag: bool) -> Self {
+    self.option.persist_cgroup = flag;
+    self
+  }
+
+  /// Collect both the cgroup and getrusage time and memory into
+  /// [`CatBoxResult::diagnostics`] to validate the cgroup setup, which costs an extra `getrusage`.
+  /// Note that getrusage accumulates all the children reaped by the current process.
+  pub fn diagnose_usage(mut self, flag: bool) -> Self {
+    self.option.diagnose_usage = flag;
+    self
+  }
+
+  /// Run custom code in the child after the built-in setup (chroot, rlimits, setuid and so on) but
+  /// before installing seccomp and ptrace and calling `execvpe`, like
+  /// [`std::os::unix::process::CommandExt::pre_exec`]. Returning an error aborts the run with
+  /// [`CatBoxError::Exec`].
+  ///
+  /// The child is forked from a possibly multi-threaded parent, so the hook should only do
+  /// async-signal-safe things: raw syscalls such as `prctl` and `setrlimit` are fine, while
+  /// allocating, locking or logging may deadlock.
+  pub fn pre_exec<F: Fn() -> Result<(), CatBoxError> + Send + Sync + 'static>(
+    mut self,
+    hook: F,
+  ) -> Self {
+    self.option.pre_exec = Some(PreExecHook::new(hook));
+    self
+  }
+
   /// Set uid
   pub fn uid(mut self, uid: UidType) -> Self {
     self.option.uid = Uid::from(uid);
@@ -227,6 +518,25 @@ impl CatBoxOptionBuilder {
     self
   }
 
+  /// Set uid / gid with the user of this name in the user database
+  pub fn user<S: AsRef<str>>(mut self, name: S) -> Result<Self, CatBoxError> {
+    let user = User::from_name(name.as_ref())?.ok_or(CatBoxError::cli(format!(
+      "User {} is not found",
+      name.as_ref()
+    )))?;
+    self.option.uid = user.uid;
+    self.option.gid = user.gid;
+    Ok(self)
+  }
+
+  /// Drop these capabilities before exec, from the bounding set and the process itself.
+  /// By default, all the capabilities are dropped when running as a non-root user, and none is
+  /// dropped when running as root.
+  pub fn drop_capabilities(mut self, capabilities: DropCapabilities) -> Self {
+    self.option.drop_capabilities = Some(capabilities);
+    self
+  }
+
   /// Set the max number of processes
   pub fn process(mut self, value: u64) -> Self {
     self.option.process = value;
@@ -241,6 +551,39 @@ impl CatBoxOptionBuilder {
     self
   }
 
+  /// Run the child in a new process group, and kill the whole group after it finishes.
+  /// It is used to clean up subprocesses (i.e. `cc1plus` spawned by `g++`) left after time limit exceeded.
+  pub fn kill_process_group(mut self, flag: bool) -> Self {
+    self.option.process_group = flag;
+    self
+  }
+
+  /// Pin the child to these CPUs, by cgroup cpuset if it is supported, and also `sched_setaffinity`
+  pub fn cpu_affinity(mut self, cpus: Vec<usize>) -> Self {
+    self.option.cpu_affinity = cpus;
+    self
+  }
+
+  /// Set the burst allowance of the cgroup cpu quota (unit: us), so that short CPU bursts are not
+  /// throttled (`cpu.max.burst` on cgroup v2, or `cpu.cfs_burst_us` on v1). Not set by default.
+  pub fn cpu_burst(mut self, value: u64) -> Self {
+    self.option.cpu_burst = Some(value);
+    self
+  }
+
+  /// Disable address space layout randomization of the child by `personality(ADDR_NO_RANDOMIZE)`,
+  /// so that memory addresses are deterministic across runs. Disabled by default.
+  pub fn disable_aslr(mut self, flag: bool) -> Self {
+    self.option.disable_aslr = flag;
+    self
+  }
+
+  /// Treat these nonzero exit codes as successful compilation (only status 0 by default)
+  pub fn compile_tolerate_status(mut self, status: Vec<i32>) -> Self {
+    self.option.tolerate_status = status;
+    self
+  }
+
   /// Set stdin redirection or not
   pub fn set_stdin<PS: Into<String>>(mut self, path: Option<PS>) -> Self {
     self.option.stdin = path.map(|p| p.into());
@@ -253,6 +596,13 @@ impl CatBoxOptionBuilder {
     self
   }
 
+  /// Feed stdin from the in-memory bytes through a pipe instead of a file, which takes precedence
+  /// over the stdin redirection. The parent writes the bytes in a background thread.
+  pub fn stdin_bytes(mut self, bytes: Vec<u8>) -> Self {
+    self.option.stdin_bytes = Some(bytes);
+    self
+  }
+
   /// Set stdout redirection or not
   pub fn set_stdout<PS: Into<String>>(mut self, path: Option<PS>) -> Self {
     self.option.stdout = path.map(|p| p.into());
@@ -277,6 +627,127 @@ impl CatBoxOptionBuilder {
     self
   }
 
+  /// Compare stdout with the answer file after the run, and classify the result as
+  /// [`crate::CatBoxResult::verdict`]. Stdout is captured into a temp file (and then forwarded to
+  /// the stdout of the caller) if it is not redirected.
+  pub fn answer<PS: Into<PathBuf>>(mut self, path: PS, comparator: Comparator) -> Self {
+    self.option.answer = Some((path.into(), comparator));
+    self
+  }
+
+  /// Parse answer file and compare mode (default: token)
+  pub fn parse_answer(
+    self,
+    path: Option<String>,
+    mode: Option<String>,
+  ) -> Result<Self, CatBoxError> {
+    match path {
+      Some(path) => {
+        let mode = match mode {
+          Some(mode) => CompareMode::parse(mode)?,
+          None => CompareMode::Token,
+        };
+        Ok(self.answer(path, Comparator::new(mode)))
+      }
+      None => Ok(self),
+    }
+  }
+
+  /// Require the child to write nothing to stderr, otherwise the result is marked as
+  /// [`crate::CatBoxResult::stderr_violated`]. Stderr is captured into a temp file if it is not
+  /// redirected.
+  pub fn require_empty_stderr(mut self, flag: bool) -> Self {
+    self.option.require_empty_stderr = flag;
+    self
+  }
+
+  /// Allocate a pseudo-terminal pair. The child becomes a session leader with the slave as its
+  /// controlling terminal and stdio (unless redirected), and the caller drives it through
+  /// [`CatBoxOption::pty_master`].
+  pub fn allocate_pty(mut self, flag: bool) -> Result<Self, CatBoxError> {
+    self.option.pty = match flag {
+      true => Some(Arc::new(open_pty()?)),
+      false => None,
+    };
+    Ok(self)
+  }
+
+  /// Connect the child stdin and stdout (unless redirected) to one end of a Unix stream socket pair,
+  /// and the caller communicates with it bidirectionally through [`CatBoxOption::stdio_socket`].
+  pub fn stdio_socketpair(mut self, flag: bool) -> Result<Self, CatBoxError> {
+    self.option.socketpair = match flag {
+      true => {
+        let (caller, child) = socketpair(
+          AddressFamily::Unix,
+          SockType::Stream,
+          None,
+          SockFlag::SOCK_CLOEXEC,
+        )?;
+        let pair = unsafe { (OwnedFd::from_raw_fd(caller), OwnedFd::from_raw_fd(child)) };
+        Some(Arc::new(pair))
+      }
+      false => None,
+    };
+    Ok(self)
+  }
+
+  /// Capture the child stdout into [`CatBoxResult::stdout_bytes`] through a pipe, unless stdout is
+  /// redirected to a file. The whole output is kept in memory, while the output limit (`RLIMIT_FSIZE`)
+  /// does not apply to pipes.
+  pub fn capture_stdout(mut self) -> Self {
+    self.option.capture_stdout = true;
+    self
+  }
+
+  /// Capture the child stderr into [`CatBoxResult::stderr_bytes`] through a pipe, unless stderr is
+  /// redirected to a file
+  pub fn capture_stderr(mut self) -> Self {
+    self.option.capture_stderr = true;
+    self
+  }
+
+  /// Forward the open `parent_fd` to the child as `child_fd` by `dup2`, which survives exec
+  /// even if the parent fd is opened with `O_CLOEXEC`. Fails if either fd is negative, or
+  /// `parent_fd` is not open.
+  pub fn forward_fd(mut self, parent_fd: RawFd, child_fd: RawFd) -> Result<Self, CatBoxError> {
+    if parent_fd < 0 || child_fd < 0 {
+      return Err(CatBoxError::cli(format!(
+        "Invalid forwarded fd {} -> {}",
+        parent_fd, child_fd
+      )));
+    }
+    if let Err(err) = fcntl(parent_fd, FcntlArg::F_GETFD) {
+      return Err(CatBoxError::cli(format!(
+        "Forwarded fd {} is not open: {}",
+        parent_fd, err
+      )));
+    }
+    self.option.forward_fds.push((parent_fd, child_fd));
+    Ok(self)
+  }
+
+  /// Keep child stderr connected to the terminal for debugging.
+  /// When enabled, the `stderr` redirection path is ignored, while stdin / stdout are still redirected.
+  pub fn debug_stderr_to_terminal(mut self, flag: bool) -> Self {
+    self.option.debug_stderr = flag;
+    self
+  }
+
+  /// Include the executed command line and env in the JSON report, which may leak sensitive env.
+  /// Disabled by default, while they are always available by [`CatBoxResult::command_line`] and
+  /// [`CatBoxResult::env`].
+  pub fn report_command_line(mut self, flag: bool) -> Self {
+    self.option.report_command_line = flag;
+    self
+  }
+
+  /// Set the max size of the exec error message sent from the child (unit: byte, default: 4096).
+  /// Longer message is truncated with a `…(truncated)` marker.
+  pub fn error_message_limit(mut self, bytes: usize) -> Self {
+    self.option.error_message_limit = bytes;
+    self
+  }
+
   /// Parse ptrace syscall filter
   pub fn parse_ptrace_presets(mut self, presets: Option<Vec<String>>) -> Result<Self, CatBoxError> {
     if let Some(presets) = presets {
@@ -297,12 +768,83 @@ impl CatBoxOptionBuilder {
     self
   }
 
+  /// Kill the program if it mmaps more than `max_bytes` at once, see [`SyscallFilter::limit_mmap`].
+  /// It enables ptrace if it is disabled.
+  pub fn limit_mmap(mut self, max_bytes: u64) -> Self {
+    self
+      .option
+      .ptrace
+      .get_or_insert(SyscallFilter::new())
+      .limit_mmap(max_bytes);
+    self
+  }
+
   /// Disable ptrace
   pub fn disable_ptrace(mut self) -> Self {
     self.option.ptrace = None;
     self
   }
 
+  /// Set how network is restricted (default: [`NetPolicy::Allow`]).
+  /// [`NetPolicy::Seccomp`] makes `socket`, `connect`, `bind` and so on fail with `EPERM`.
+  pub fn net_policy(mut self, policy: NetPolicy) -> Self {
+    self.option.net_policy = policy;
+    self
+  }
+
+  /// Install a default-deny seccomp filter right before exec, which only allows the syscalls
+  /// commonly used by compiled programs (file IO, memory, signals, time and program startup), and
+  /// makes all the others fail with `EPERM`, e.g. socket, fork, clone, mount and ptrace.
+  /// The program is not run if the filter fails to install.
+  pub fn seccomp_default_deny(mut self, flag: bool) -> Self {
+    self.option.seccomp_default_deny = flag;
+    self
+  }
+
+  pub fn parse_net_policy(self, policy: Option<String>) -> Result<Self, CatBoxError> {
+    match policy {
+      Some(policy) => Ok(self.net_policy(NetPolicy::parse(policy)?)),
+      None => Ok(self),
+    }
+  }
+
+  /// Record the sequence of signals stopping the child, which requires ptrace enabled
+  pub fn record_signal_history(mut self, flag: bool) -> Self {
+    self.option.signal_history = flag;
+    self
+  }
+
+  /// Record the distinct syscalls invoked by the child, which requires ptrace enabled
+  pub fn record_syscalls(mut self, flag: bool) -> Self {
+    self.option.record_syscalls = flag;
+    self
+  }
+
+  /// Keep the signal mask and ignored signals of the parent process in the child. By default, the
+  /// child resets them to an empty mask and default dispositions before exec.
+  pub fn inherit_signals(mut self, flag: bool) -> Self {
+    self.option.inherit_signals = flag;
+    self
+  }
+
+  /// Sample the resident set size of the child every `interval` ms from the parent, which costs
+  /// an extra thread and reads of `/proc/<pid>/status`. Disabled by default.
+  pub fn sample_rss(mut self, interval: u64) -> Self {
+    self.option.rss_sample_interval = Some(interval);
+    self
+  }
+
+  /// Kill the child as MLE once its RSS stays above `threshold` KB for `duration` ms, which catches
+  /// slow leaks before they reach the memory limit.
+  ///
+  /// It is best-effort: RSS is only sampled from the child itself (every 50 ms, or the interval of
+  /// [`sample_rss`](Self::sample_rss)), so short spikes between samples and memory of descendants
+  /// are not seen.
+  pub fn sustained_memory_limit(mut self, threshold: MemoryLimitType, duration: u64) -> Self {
+    self.option.sustained_memory_limit = Some((threshold, duration));
+    self
+  }
+
   /// Set chroot or not
   pub fn set_chroot(mut self, flag: bool) -> Self {
     if flag {
@@ -321,6 +863,82 @@ impl CatBoxOptionBuilder {
     self
   }
 
+  /// Enable hermetic mode for maximum isolation, which
+  /// + chroots into a fresh tmpfs root (64 MB)
+  /// + drops the default read mounts, so only the explicitly requested mounts are kept
+  /// + mounts a fresh `/proc` and a minimal `/dev` (`null`, `zero`, `random` and `urandom`)
+  /// + clears all the env
+  /// + denies network by [`NetPolicy::Seccomp`]
+  /// + installs the default-deny seccomp filter, see [`seccomp_default_deny`](Self::seccomp_default_deny)
+  /// + forbids network and process syscalls with the default ptrace filter
+  ///
+  /// Mounts, env and the options set after calling this are still applied, e.g. call
+  /// `seccomp_default_deny(false)` to run programs which fork, such as compilers.
+  pub fn hermetic(mut self) -> Self {
+    self.option.hermetic = true;
+    self.option.net_policy = NetPolicy::Seccomp;
+    self.option.seccomp_default_deny = true;
+    self
+      .option
+      .mounts
+      .retain(|mount_point| mount_point.origin() != MountOrigin::Default);
+    self.option.env.clear();
+    self.option.ptrace = Some(SyscallFilter::default());
+    self.chroot()
+  }
+
+  /// Limit the number of files (and directories) the program may create, by the `nr_inodes` option
+  /// of tmpfs. It only applies to tmpfs-backed scratch, i.e. the tmpfs root in hermetic mode, and
+  /// files created in bind mounted writable directories are not counted.
+  pub fn max_files_created(mut self, value: u64) -> Self {
+    self.option.max_files_created = Some(value);
+    self
+  }
+
+  /// Mount a fresh tmpfs of `size` bytes at `dst` in the jail and use it as the working directory,
+  /// which gives the program a clean writable scratch directory with a size cap. Writes beyond the
+  /// cap fail with `ENOSPC`. It enables chroot, and the tmpfs is unmounted with its content on close.
+  pub fn tmpfs_workdir<P: Into<PathBuf>>(mut self, dst: P, size: u64) -> Self {
+    let dst = dst.into();
+    self.option.cwd = dst.clone();
+    self.option.tmpfs_workdir = Some((dst, size));
+    if self.option.chroot.is_none() {
+      self.chroot()
+    } else {
+      self
+    }
+  }
+
+  /// Enable chroot with a minimal jail, which only contains the executable and its shared library
+  /// dependencies (mounted read-only at the same paths). Fallback to the default mounts with the
+  /// executable when resolving dependencies fails.
+  pub fn minimal_jail<P: Into<PathBuf>>(mut self, executable: P) -> Self {
+    let executable: PathBuf = executable.into();
+    let executable = executable.absolutize().unwrap().to_path_buf();
+    match resolve_dependencies(&executable) {
+      Ok(deps) => {
+        self
+          .option
+          .mounts
+          .retain(|mount_point| mount_point.origin() != MountOrigin::Default);
+        for dep in deps {
+          self
+            .option
+            .mounts
+            .push(MountPoint::read(dep.clone(), dep).with_origin(MountOrigin::Preset));
+        }
+      }
+      Err(err) => {
+        error!("Fallback to default mounts: {}", err);
+      }
+    }
+    self
+      .option
+      .mounts
+      .push(MountPoint::read(executable.clone(), executable).with_origin(MountOrigin::Preset));
+    self.chroot()
+  }
+
   /// Set work directory in chroot or not
   pub fn set_cwd(mut self, path: Option<PathBuf>) -> Self {
     if let Some(path) = path {
@@ -341,7 +959,11 @@ impl CatBoxOptionBuilder {
     self
   }
 
-  /// Mount read directory
+  /// Mount read directory.
+  ///
+  /// Writes to it are not trapped: `open` and friends fail with `EROFS` (or `EACCES` when the
+  /// submission runs as another user), so the program sees an ordinary IO error, and it usually
+  /// exits with non-zero status without any signal, i.e. a runtime error.
   pub fn mount_read<SP: Into<PathBuf>, DP: Into<PathBuf>>(mut self, src: SP, dst: DP) -> Self {
     self
       .option
@@ -350,6 +972,23 @@ impl CatBoxOptionBuilder {
     self
   }
 
+  /// Mount read directory, and verify it against the expected [`crate::hash_tree`] before running,
+  /// so that the run fails if the host directory has changed. Verification is skipped without hash.
+  pub fn mount_read_verified<SP: Into<PathBuf>, DP: Into<PathBuf>>(
+    mut self,
+    src: SP,
+    dst: DP,
+    expected_hash: Option<&str>,
+  ) -> Self {
+    let mount_point = MountPoint::read(src.into(), dst.into());
+    let mount_point = match expected_hash {
+      Some(hash) => mount_point.with_expected_hash(hash),
+      None => mount_point,
+    };
+    self.option.mounts.push(mount_point);
+    self
+  }
+
   /// Mount write directory
   pub fn mount_write<SP: Into<PathBuf>, DP: Into<PathBuf>>(mut self, src: SP, dst: DP) -> Self {
     self
@@ -359,6 +998,53 @@ impl CatBoxOptionBuilder {
     self
   }
 
+  /// Mount the input, output and answer files read-only into the jail at `/checker/input`,
+  /// `/checker/output` and `/checker/answer`, and pass these paths to the checker as the arguments
+  /// in testlib order. It enables chroot if it is not enabled yet.
+  pub fn checker_files<IP: Into<PathBuf>, OP: Into<PathBuf>, AP: Into<PathBuf>>(
+    mut self,
+    input: IP,
+    output: OP,
+    answer: AP,
+  ) -> Self {
+    let files = [input.into(), output.into(), answer.into()];
+    for (file, name) in files.into_iter().zip(["input", "output", "answer"]) {
+      let file = file.absolutize().unwrap().to_path_buf();
+      let dst = Path::new("/checker").join(name);
+      self
+        .option
+        .arguments
+        .push(dst.to_string_lossy().to_string());
+      self.option.mounts.push(MountPoint::read(file, dst));
+    }
+    if self.option.chroot.is_none() {
+      self.chroot()
+    } else {
+      self
+    }
+  }
+
+  /// Mount the directory of the current executable read-only at the same path, which is useful to
+  /// run helper binaries shipped alongside
+  pub fn mount_self_dir(self) -> Self {
+    match env::current_exe() {
+      Ok(exe) => match exe.parent() {
+        Some(dir) => self.mount_read(dir, dir),
+        None => self,
+      },
+      Err(err) => {
+        error!("Get current executable fails: {}", err);
+        self
+      }
+    }
+  }
+
+  /// Retry mount calls failing with `EAGAIN` or `EBUSY` at most `retries` times (default: 3)
+  pub fn mount_retries(mut self, retries: u32) -> Self {
+    self.option.mount_retries = retries;
+    self
+  }
+
   /// Parse read mount points
   pub fn parse_mount_read(mut self, list: Vec<String>) -> Result<Self, CatBoxError> {
     for text in list {
@@ -382,12 +1068,51 @@ impl CatBoxOptionBuilder {
     self.option.env.push((key.into(), value.into()));
     self
   }
+
+  /// Mount the bin directory read-only at the same path, and append it to the PATH of the child
+  /// (and the jail PATH if it is set), so that programs in it can be searched in the jail
+  pub fn add_bin_dir<P: Into<PathBuf>>(mut self, path: P) -> Self {
+    let path: PathBuf = path.into();
+    let path = path.absolutize().unwrap().to_path_buf();
+    let dir = path.to_string_lossy().to_string();
+    let append = |value: &str| match value.is_empty() {
+      true => dir.clone(),
+      false => format!("{}:{}", value, dir),
+    };
+
+    if let Some(jail_path) = &self.option.jail_path {
+      self.option.jail_path = Some(append(jail_path));
+    }
+    match self
+      .option
+      .env
+      .iter_mut()
+      .rev()
+      .find(|(key, _)| key == "PATH")
+    {
+      Some((_, value)) => *value = append(value),
+      None => self.option.env.push(("PATH".to_string(), dir.clone())),
+    }
+    self.mount_read(&path, &path)
+  }
+
+  /// Set PATH inside the chroot jail, which is used both for searching the program and as the
+  /// PATH env of the child. By default, the PATH entries not mounted in the jail are dropped.
+  pub fn jail_path<PS: Into<String>>(mut self, path: PS) -> Self {
+    self.option.jail_path = Some(path.into());
+    self
+  }
+
+  /// Pass dynamic linker env (e.g. `LD_PRELOAD`), which is scrubbed by default
+  pub fn allow_ld_env(mut self, flag: bool) -> Self {
+    self.option.ld_env = flag;
+    self
+  }
 }
 
 impl CatBoxOption {
   pub fn default<PS: Into<String>, AS: Into<String>>(program: PS, arguments: Vec<AS>) -> Self {
-    let current_user = User::from_uid(Uid::current()).unwrap().unwrap();
-    let cgroup = env::var("CATJ_CGROUP").unwrap_or(current_user.name);
+    let cgroup = default_cgroup();
 
     let catbox_user = User::from_name("nobody").unwrap().unwrap();
     let catbox_group = Group::from_gid(catbox_user.gid).unwrap().unwrap();
@@ -396,24 +1121,70 @@ impl CatBoxOption {
       label: "catbox".to_string(),
       time_limit: 1000,
       memory_limit: 262144,
+      swap_accounting: true,
+      oom_kill_disable: false,
+      strict_cpu_limit: false,
+      wall_time_basis: false,
+      wall_time_limit: None,
+      timeout_signal: None,
+      kill_signal: Signal::SIGKILL,
       program: program.into(),
+      display_name: None,
       arguments: arguments.into_iter().map(|a| a.into()).collect(),
+      launcher: None,
       uid: catbox_user.uid,
       gid: catbox_group.gid,
+      drop_capabilities: None,
       cgroup,
+      persist_cgroup: false,
+      diagnose_usage: false,
+      no_cgroup: false,
       process: 1,
+      process_group: false,
+      cpu_affinity: vec![],
+      cpu_burst: None,
+      disable_aslr: false,
+      tolerate_status: vec![],
       ptrace: Some(SyscallFilter::default()),
+      net_policy: NetPolicy::Allow,
+      seccomp_default_deny: false,
+      signal_history: false,
+      record_syscalls: false,
+      inherit_signals: false,
+      rss_sample_interval: None,
+      sustained_memory_limit: None,
       stack_size: u64::MAX,
+      address_space_limit: None,
+      address_space_slack: None,
+      output_limit: 256 * 1024 * 1024,
       chroot: None,
+      hermetic: false,
+      max_files_created: None,
+      tmpfs_workdir: None,
+      pre_exec: None,
       cwd: env::current_dir().unwrap(),
       mounts: MountPoint::defaults(),
+      mount_retries: 3,
       env: vec![(
         "PATH".to_string(),
         env::var("PATH").unwrap_or("".to_string()),
       )],
       stdin: None,
+      stdin_bytes: None,
       stdout: None,
       stderr: None,
+      answer: None,
+      require_empty_stderr: false,
+      jail_path: None,
+      ld_env: false,
+      pty: None,
+      socketpair: None,
+      capture_stdout: false,
+      capture_stderr: false,
+      forward_fds: vec![],
+      debug_stderr: false,
+      report_command_line: false,
+      error_message_limit: 4096,
       force: false,
       debug: false,
     }
@@ -431,18 +1202,96 @@ impl CatBoxOption {
     self.memory_limit
   }
 
-  pub fn program(&self) -> CString {
+  pub fn swap_accounting(&self) -> bool {
+    self.swap_accounting
+  }
+
+  pub fn oom_kill_disable(&self) -> bool {
+    self.oom_kill_disable
+  }
+
+  pub fn strict_cpu_limit(&self) -> bool {
+    self.strict_cpu_limit
+  }
+
+  pub fn wall_time_basis(&self) -> bool {
+    self.wall_time_basis
+  }
+
+  /// Wall time limit (unit: ms), which is the time limit when based on wall time, or `None` for
+  /// the loose default of the time limit + 1 second
+  pub fn wall_time_limit(&self) -> Option<TimeLimitType> {
+    match self.wall_time_limit {
+      Some(value) => Some(value),
+      None if self.wall_time_basis => Some(self.time_limit),
+      None => None,
+    }
+  }
+
+  pub fn timeout_signal(&self) -> Option<Signal> {
+    self.timeout_signal
+  }
+
+  pub fn kill_signal(&self) -> Signal {
+    self.kill_signal
+  }
+
+  /// Deadline (unit: ms) of the parent-side watchdog, which kills the child by SIGKILL even if it
+  /// blocks or ignores the timer signal. It is the wall time limit, or 3 times the time limit
+  /// (at least the loose alarm default), plus a grace period so that the timer fires first.
+  pub fn watchdog_time_limit(&self) -> TimeLimitType {
+    let limit = match self.wall_time_limit() {
+      Some(value) => value,
+      None => {
+        let alarm = ((self.time_limit as f64 / 1000.0_f64).ceil() as TimeLimitType + 1) * 1000;
+        max(self.time_limit.saturating_mul(3), alarm)
+      }
+    };
+    limit.saturating_add(WATCHDOG_GRACE)
+  }
+
+  /// Program name shown in reports, which defaults to the program
+  pub fn display_name(&self) -> &str {
+    self.display_name.as_ref().unwrap_or(&self.program)
+  }
+
+  /// Program as C string, or [`CatBoxError::Cli`] if it contains NUL byte
+  pub fn program(&self) -> Result<CString, CatBoxError> {
     into_c_string(&self.program)
   }
 
-  pub fn arguments(&self) -> Vec<CString> {
+  /// Arguments as C strings, or [`CatBoxError::Cli`] if any of them contains NUL byte
+  pub fn arguments(&self) -> Result<Vec<CString>, CatBoxError> {
     self.arguments.iter().map(|p| into_c_string(p)).collect()
   }
 
+  /// Launcher program and its arguments, see [`CatBoxOptionBuilder::launcher`]
+  pub fn launcher(&self) -> Result<Option<(CString, Vec<CString>)>, CatBoxError> {
+    match &self.launcher {
+      Some((program, arguments)) => Ok(Some((
+        into_c_string(program)?,
+        arguments
+          .iter()
+          .map(|p| into_c_string(p))
+          .collect::<Result<_, _>>()?,
+      ))),
+      None => Ok(None),
+    }
+  }
+
   pub fn uid(&self) -> Uid {
     self.uid
   }
 
+  /// Numbers of the capabilities dropped before exec, see [`CatBoxOptionBuilder::drop_capabilities`]
+  pub fn drop_capabilities(&self) -> Vec<u32> {
+    match &self.drop_capabilities {
+      Some(capabilities) => capabilities.ids(),
+      None if self.uid.is_root() => vec![],
+      None => DropCapabilities::All.ids(),
+    }
+  }
+
   pub fn gid(&self) -> Gid {
     self.gid
   }
@@ -451,14 +1300,74 @@ impl CatBoxOption {
     &self.cgroup
   }
 
+  pub fn no_cgroup(&self) -> bool {
+    self.no_cgroup
+  }
+
+  pub fn persist_cgroup(&self) -> bool {
+    self.persist_cgroup
+  }
+
+  pub fn diagnose_usage(&self) -> bool {
+    self.diagnose_usage
+  }
+
   pub fn process(&self) -> u64 {
     self.process
   }
 
+  pub fn process_group(&self) -> bool {
+    self.process_group
+  }
+
+  pub fn cpu_affinity(&self) -> &Vec<usize> {
+    &self.cpu_affinity
+  }
+
+  pub fn cpu_burst(&self) -> Option<u64> {
+    self.cpu_burst
+  }
+
+  pub fn disable_aslr(&self) -> bool {
+    self.disable_aslr
+  }
+
+  pub fn tolerate_status(&self) -> &Vec<i32> {
+    &self.tolerate_status
+  }
+
   pub fn ptrace(&self) -> &Option<SyscallFilter> {
     &self.ptrace
   }
 
+  pub fn net_policy(&self) -> NetPolicy {
+    self.net_policy
+  }
+
+  pub fn seccomp_default_deny(&self) -> bool {
+    self.seccomp_default_deny
+  }
+
+  pub fn signal_history(&self) -> bool {
+    self.signal_history
+  }
+
+  pub fn record_syscalls(&self) -> bool {
+    self.record_syscalls
+  }
+
+  pub fn inherit_signals(&self) -> bool {
+    self.inherit_signals
+  }
+
+  pub fn rss_sample_interval(&self) -> Option<u64> {
+    self.rss_sample_interval
+  }
+
+  pub fn sustained_memory_limit(&self) -> Option<(MemoryLimitType, u64)> {
+    self.sustained_memory_limit
+  }
+
   pub fn stack_size(&self) -> libc::rlim_t {
     if self.stack_size == u64::MAX {
       libc::RLIM_INFINITY
@@ -467,10 +1376,39 @@ impl CatBoxOption {
     }
   }
 
+  /// `RLIMIT_AS` of the child, either set explicitly or derived from the memory limit
+  pub fn address_space_limit(&self) -> Option<u64> {
+    self.address_space_limit.or_else(|| {
+      self
+        .address_space_slack
+        .map(|slack| (self.memory_limit * 1024).saturating_add(slack))
+    })
+  }
+
+  pub fn output_limit(&self) -> u64 {
+    self.output_limit
+  }
+
   pub fn chroot(&self) -> &Option<PathBuf> {
     &self.chroot
   }
 
+  pub fn hermetic(&self) -> bool {
+    self.hermetic
+  }
+
+  pub fn max_files_created(&self) -> Option<u64> {
+    self.max_files_created
+  }
+
+  pub fn tmpfs_workdir(&self) -> &Option<(PathBuf, u64)> {
+    &self.tmpfs_workdir
+  }
+
+  pub fn pre_exec(&self) -> &Option<PreExecHook> {
+    &self.pre_exec
+  }
+
   pub fn cwd(&self) -> &PathBuf {
     &self.cwd
   }
@@ -479,6 +1417,10 @@ impl CatBoxOption {
     &self.mounts
   }
 
+  pub fn mount_retries(&self) -> u32 {
+    self.mount_retries
+  }
+
   pub fn env(&self) -> &Vec<(String, String)> {
     &self.env
   }
@@ -487,6 +1429,10 @@ impl CatBoxOption {
     &self.stdin
   }
 
+  pub fn stdin_bytes(&self) -> Option<&[u8]> {
+    self.stdin_bytes.as_deref()
+  }
+
   pub fn stdout(&self) -> &Option<String> {
     &self.stdout
   }
@@ -495,6 +1441,71 @@ impl CatBoxOption {
     &self.stderr
   }
 
+  pub fn answer(&self) -> &Option<(PathBuf, Comparator)> {
+    &self.answer
+  }
+
+  pub fn debug_stderr_to_terminal(&self) -> bool {
+    self.debug_stderr
+  }
+
+  pub fn report_command_line(&self) -> bool {
+    self.report_command_line
+  }
+
+  pub fn error_message_limit(&self) -> usize {
+    self.error_message_limit
+  }
+
+  pub fn require_empty_stderr(&self) -> bool {
+    self.require_empty_stderr
+  }
+
+  /// Master fd of the allocated pseudo-terminal
+  pub fn pty_master(&self) -> Option<RawFd> {
+    self.pty().map(|(master, _)| master)
+  }
+
+  pub(crate) fn pty(&self) -> Option<(RawFd, RawFd)> {
+    self
+      .pty
+      .as_deref()
+      .map(|(master, slave)| (master.as_raw_fd(), slave.as_raw_fd()))
+  }
+
+  /// Caller end of the stdio socket pair
+  pub fn stdio_socket(&self) -> Option<RawFd> {
+    self.socketpair().map(|(caller, _)| caller)
+  }
+
+  pub(crate) fn socketpair(&self) -> Option<(RawFd, RawFd)> {
+    self
+      .socketpair
+      .as_deref()
+      .map(|(caller, child)| (caller.as_raw_fd(), child.as_raw_fd()))
+  }
+
+  pub fn capture_stdout(&self) -> bool {
+    self.capture_stdout
+  }
+
+  pub fn capture_stderr(&self) -> bool {
+    self.capture_stderr
+  }
+
+  /// Forwarded fds as (parent fd, child fd)
+  pub fn forward_fds(&self) -> &Vec<(RawFd, RawFd)> {
+    &self.forward_fds
+  }
+
+  pub fn jail_path(&self) -> &Option<String> {
+    &self.jail_path
+  }
+
+  pub fn allow_ld_env(&self) -> bool {
+    self.ld_env
+  }
+
   pub fn force(&self) -> bool {
     self.force
   }
@@ -517,14 +1528,49 @@ impl CatBoxOption {
   //   self
   // }
 
-  pub fn close(self: Self) {
+  /// Clone this option with another stdin redirection.
+  ///
+  /// The clone shares the same chroot directory, so the mount points are set up by the first run
+  /// and reused by the following runs. Only call [`CatBoxOption::close`] once on the original
+  /// option after all the runs finish, which tears down the shared mount points.
+  pub fn with_stdin<PS: Into<String>>(&self, path: PS) -> Self {
+    let mut option = self.clone();
+    option.stdin = Some(path.into());
+    option
+  }
+
+  /// Clone this option with another stdout redirection, see [`CatBoxOption::with_stdin`]
+  pub fn with_stdout<PS: Into<String>>(&self, path: PS) -> Self {
+    let mut option = self.clone();
+    option.stdout = Some(path.into());
+    option
+  }
+
+  /// Clone this option with another stderr redirection, see [`CatBoxOption::with_stdin`]
+  pub fn with_stderr<PS: Into<String>>(&self, path: PS) -> Self {
+    let mut option = self.clone();
+    option.stderr = Some(path.into());
+    option
+  }
+
+  /// Reset the allowed times of the syscall filter.
+  /// Note that [`crate::run`] works on a copy of the filter, so it is only needed after calling
+  /// [`SyscallFilter::filter`] by yourself.
+  pub fn reset(&mut self) {
+    if let Some(filter) = &mut self.ptrace {
+      filter.reset();
+    }
+  }
+
+  pub fn close(self) {
     if let Some(new_root) = self.chroot {
       if self.debug {
         debug!("Persist new root: {}", new_root.to_string_lossy());
       } else {
         let mut has_mount = false;
-        for mount_point in &self.mounts {
-          let target = mount_point.dst().strip_prefix(Path::new("/")).unwrap();
+        let tmpfs_workdir = self.tmpfs_workdir.iter().map(|(dst, _)| dst);
+        for dst in self.mounts.iter().map(|p| p.dst()).chain(tmpfs_workdir) {
+          let target = dst.strip_prefix(Path::new("/")).unwrap();
           let target = new_root.join(target);
           if target.exists() {
             debug!("Unmount directory {:?}", &target);
@@ -535,26 +1581,24 @@ impl CatBoxOption {
             }
           }
         }
-        if new_root.exists() {
-          if has_mount {
-            if let Err(err) = umount2(&new_root, MntFlags::MNT_FORCE | MntFlags::MNT_DETACH) {
-              error!("Fails umount {}: {}", new_root.to_string_lossy(), err);
-            }
+        if new_root.exists() && (has_mount || is_mount_point(&new_root)) {
+          if let Err(err) = umount2(&new_root, MntFlags::MNT_FORCE | MntFlags::MNT_DETACH) {
+            error!("Fails umount {}: {}", new_root.to_string_lossy(), err);
           }
-
-          // match remove_dir_all(&new_root) {
-          //   Ok(_) => {
-          //     info!("Remove new root: {}", new_root.to_string_lossy());
-          //   }
-          //   Err(err) => {
-          //     error!(
-          //       "Fails removing new root: {} ({})",
-          //       new_root.to_string_lossy(),
-          //       err
-          //     );
-          //   }
-          // }
         }
+
+        // match remove_dir_all(&new_root) {
+        //   Ok(_) => {
+        //     info!("Remove new root: {}", new_root.to_string_lossy());
+        //   }
+        //   Err(err) => {
+        //     error!(
+        //       "Fails removing new root: {} ({})",
+        //       new_root.to_string_lossy(),
+        //       err
+        //     );
+        //   }
+        // }
       }
     }
   }
@@ -1,46 +1,224 @@
 //! CatBox Context
 
-use std::cmp::max;
-use std::path::PathBuf;
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{stdout, Write};
+use std::os::unix::io::{OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
 use std::slice::Iter;
+use std::sync::Arc;
+use std::time::Instant;
 
-use log::info;
-use nix::libc::STDOUT_FILENO;
+use log::{error, info};
+use nix::libc::{self, STDOUT_FILENO};
 use nix::sys::signal::Signal;
-use nix::unistd::{isatty, Gid, Uid};
+use nix::unistd::{fork, isatty, setsid, ForkResult, Gid, Pid, Uid};
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
 
-pub use builder::{CatBoxBuilder, CatBoxOptionBuilder};
+pub use builder::CatBoxBuilder;
 
-use crate::cgroup::CatBoxUsage;
-use crate::syscall::SyscallFilter;
+use crate::cgroup::{CatBoxUsage, UsageDiagnostics};
+use crate::compare::Comparator;
+use crate::judge::JudgeVerdict;
+use crate::syscall::{NetPolicy, SyscallFilter};
+use crate::utils::capability::DropCapabilities;
 use crate::utils::mount::MountPoint;
-use crate::utils::{MemoryLimitType, TimeLimitType};
+use crate::utils::{strip_ansi, MemoryLimitType, TimeLimitType};
 use crate::CatBoxError;
 
-mod builder;
+pub(crate) mod builder;
+
+/// Grace period (unit: ms) of the wall time watchdog after the timer, and before escalating the
+/// kill signal to SIGKILL
+pub(crate) const WATCHDOG_GRACE: TimeLimitType = 200;
+
+/// Callback invoked after a command finishes, returns whether to run the subsequent commands
+pub type CompleteCallback = Box<dyn FnOnce(&CatBoxResult) -> bool>;
+
+/// Hook invoked in the child right before exec, see [`crate::CatBoxOptionBuilder::pre_exec`]
+#[derive(Clone)]
+pub struct PreExecHook(Arc<dyn Fn() -> Result<(), CatBoxError> + Send + Sync>);
+
+impl PreExecHook {
+  pub(crate) fn new<F: Fn() -> Result<(), CatBoxError> + Send + Sync + 'static>(hook: F) -> Self {
+    PreExecHook(Arc::new(hook))
+  }
+
+  pub(crate) fn call(&self) -> Result<(), CatBoxError> {
+    (self.0)()
+  }
+}
+
+impl std::fmt::Debug for PreExecHook {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("PreExecHook")
+  }
+}
 
 /// CatBox top structure for context and multiple commands
 pub struct CatBox {
   context: Box<dyn CatBoxContext>,
   options: Vec<CatBoxOption>,
+  /// Completion callback of each command
+  callbacks: Vec<Option<CompleteCallback>>,
+  total_wall_budget: Option<TimeLimitType>,
+  pipeline_timeout: bool,
+  color: ColorChoice,
+  report_options: ReportOptions,
+}
+
+/// Iterator running the commands of CatBox one by one, see [`CatBox::results_iter`]
+pub struct CatBoxResults<'a> {
+  catbox: &'a mut CatBox,
+  index: usize,
+  start: Instant,
+  stopped: bool,
+}
+
+/// Report output format
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportFormat {
+  /// Human readable format with ANSI colors
+  Human,
+  /// JSON format
+  Json,
+}
+
+/// Whether to use ANSI colors in the human readable report
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorChoice {
+  /// Use colors only if stdout is a TTY
+  Auto,
+  Always,
+  Never,
+}
+
+impl ColorChoice {
+  pub fn parse<S: AsRef<str>>(text: S) -> Result<Self, CatBoxError> {
+    match text.as_ref().trim().to_ascii_lowercase().as_str() {
+      "auto" => Ok(ColorChoice::Auto),
+      "always" => Ok(ColorChoice::Always),
+      "never" => Ok(ColorChoice::Never),
+      _ => Err(CatBoxError::cli("Parse color choice string fails")),
+    }
+  }
+
+  /// Whether to write ANSI colors to stdout
+  pub fn enabled(&self) -> bool {
+    match self {
+      ColorChoice::Auto => isatty(STDOUT_FILENO).unwrap_or(false),
+      ColorChoice::Always => true,
+      ColorChoice::Never => false,
+    }
+  }
+}
+
+/// Options of the human readable report
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+  /// Overridden labels keyed by the default English labels
+  labels: HashMap<String, String>,
+}
+
+impl ReportOptions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Override the label of a field, keyed by its default English label, e.g. `Time user`
+  pub fn label<K: Into<String>, V: Into<String>>(mut self, field: K, label: V) -> Self {
+    self.labels.insert(field.into(), label.into());
+    self
+  }
+
+  /// Label of the field, or the default English label if it is not overridden
+  pub fn label_of<'a>(&'a self, field: &'a str) -> &'a str {
+    self.labels.get(field).map_or(field, |label| label.as_str())
+  }
+
+  /// Write a bold label padded to the value column, and then the value
+  fn write_field(
+    &self,
+    w: &mut dyn Write,
+    field: &str,
+    value: std::fmt::Arguments,
+  ) -> Result<(), CatBoxError> {
+    let label = self.label_of(field);
+    let padding = max(11, label.chars().count() + 1) - label.chars().count();
+    writeln!(
+      w,
+      "\x1b[1m{}\x1b[22m{}{}",
+      label,
+      " ".repeat(padding),
+      value
+    )?;
+    Ok(())
+  }
 }
 
 /// CatBoxContext for storing running result
 pub trait CatBoxContext {
-  fn add_result(&mut self, label: &String, result: CatBoxResult) -> bool;
+  fn add_result(&mut self, option: &CatBoxOption, result: CatBoxResult) -> bool;
+
+  /// Mark that the total real time budget is exhausted, see [`CatBoxBuilder::total_wall_budget`]
+  fn set_pipeline_timeout(&mut self) {}
+
+  /// Write human readable report
+  fn write_human(&self, w: &mut dyn Write, options: &ReportOptions) -> Result<(), CatBoxError>;
+
+  /// Write JSON format report
+  fn write_json(&self, w: &mut dyn Write) -> Result<(), CatBoxError>;
 
-  fn report(&self) {
+  /// Write report to any sink
+  fn report_to(
+    &self,
+    w: &mut dyn Write,
+    format: ReportFormat,
+    options: &ReportOptions,
+  ) -> Result<(), CatBoxError> {
+    match format {
+      ReportFormat::Human => self.write_human(w, options),
+      ReportFormat::Json => self.write_json(w),
+    }
+  }
+
+  /// Report in human readable format on TTY or if the color is set explicitly, otherwise in JSON
+  fn report(&self, color: ColorChoice, options: &ReportOptions) {
     let is_tty = isatty(STDOUT_FILENO).unwrap_or(false);
-    if is_tty {
-      self.report_human();
+    if is_tty || color != ColorChoice::Auto {
+      self.report_human(color, options);
     } else {
       self.report_json();
     }
   }
 
-  fn report_human(&self);
+  /// Report in human readable format, and strip the ANSI colors if they are disabled
+  fn report_human(&self, color: ColorChoice, options: &ReportOptions) {
+    let mut buf = vec![];
+    let report = self.write_human(&mut buf, options).and_then(|_| {
+      let mut text = String::from_utf8_lossy(&buf).to_string();
+      if !color.enabled() {
+        text = strip_ansi(&text);
+      }
+      if !text.ends_with('\n') {
+        text.push('\n');
+      }
+      stdout().write_all(text.as_bytes())?;
+      Ok(())
+    });
+    if let Err(err) = report {
+      error!("Report fails: {}", err);
+    }
+  }
 
-  fn report_json(&self);
+  fn report_json(&self) {
+    if let Err(err) = self.write_json(&mut stdout()) {
+      error!("Report fails: {}", err);
+    }
+  }
 }
 
 pub struct CatBoxRunContext {
@@ -48,15 +226,51 @@ pub struct CatBoxRunContext {
   max_memory: MemoryLimitType,
   sum_time: TimeLimitType,
   sum_memory: MemoryLimitType,
-  results: Vec<CatBoxResult>,
+  /// Label and result of each executed command
+  results: Vec<(String, CatBoxResult)>,
+  pipeline_timeout: bool,
 }
 
 pub struct CatBoxCompileContext {
   ok: bool,
+  /// Label and result of each executed command, the last one is the failed one when compilation fails
+  results: Vec<(String, CatBoxResult)>,
+  pipeline_timeout: bool,
 }
 
 pub struct CatBoxJudgeContext {}
 
+/// Resource limits applied at once by [`crate::CatBoxOptionBuilder::limits`], which can be shared by
+/// commands as a profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+  /// CPU time limit (unit: ms)
+  pub cpu_time_ms: TimeLimitType,
+  /// Wall time limit (unit: ms), which defaults to the CPU time limit + 1 second
+  pub wall_time_ms: Option<TimeLimitType>,
+  /// Memory limit (unit: KB)
+  pub memory_kb: MemoryLimitType,
+  /// Stack size limit (unit: byte), which is unlimited by default
+  pub stack_bytes: Option<u64>,
+  /// Output file size limit (unit: byte)
+  pub output_bytes: u64,
+  /// The number of processes
+  pub processes: u64,
+}
+
+impl Default for Limits {
+  fn default() -> Self {
+    Limits {
+      cpu_time_ms: 1000,
+      wall_time_ms: None,
+      memory_kb: 262144,
+      stack_bytes: None,
+      output_bytes: 256 * 1024 * 1024,
+      processes: 1,
+    }
+  }
+}
+
 /// CatBox running params that can config its behavior
 #[derive(Debug, Clone)]
 pub struct CatBoxOption {
@@ -66,53 +280,246 @@ pub struct CatBoxOption {
   time_limit: TimeLimitType,
   /// Memory limit
   memory_limit: MemoryLimitType,
+  swap_accounting: bool,
+  oom_kill_disable: bool,
+  strict_cpu_limit: bool,
+  wall_time_basis: bool,
+  /// Wall time limit, which defaults to the time limit + 1 second
+  wall_time_limit: Option<TimeLimitType>,
+  timeout_signal: Option<Signal>,
+  /// Signal to terminate the child before escalating to SIGKILL
+  kill_signal: Signal,
   program: String,
+  /// Program name shown in reports, which defaults to the program
+  display_name: Option<String>,
   arguments: Vec<String>,
+  /// Launcher program and its arguments prepended to the command
+  launcher: Option<(String, Vec<String>)>,
   uid: Uid,
   gid: Gid,
+  /// Capabilities dropped before exec, which defaults to all for non-root users
+  drop_capabilities: Option<DropCapabilities>,
   cgroup: String,
+  /// Skip creating cgroup and account usage by getrusage
+  no_cgroup: bool,
+  /// Keep the cgroup after the run for external inspection
+  persist_cgroup: bool,
+  diagnose_usage: bool,
   process: u64,
+  process_group: bool,
+  cpu_affinity: Vec<usize>,
+  cpu_burst: Option<u64>,
+  disable_aslr: bool,
+  tolerate_status: Vec<i32>,
   ptrace: Option<SyscallFilter>,
+  net_policy: NetPolicy,
+  seccomp_default_deny: bool,
+  signal_history: bool,
+  record_syscalls: bool,
+  inherit_signals: bool,
+  rss_sample_interval: Option<u64>,
+  /// Kill the child once its RSS stays above (KB, for ms), see sustained_memory_limit
+  sustained_memory_limit: Option<(MemoryLimitType, u64)>,
   stack_size: u64,
+  /// Explicit `RLIMIT_AS` (unit: byte)
+  address_space_limit: Option<u64>,
+  /// Slack over the memory limit used as `RLIMIT_AS` (unit: byte)
+  address_space_slack: Option<u64>,
+  /// Output file size limit (unit: byte)
+  output_limit: u64,
   chroot: Option<PathBuf>,
+  hermetic: bool,
+  max_files_created: Option<u64>,
+  /// Destination in the jail and size (unit: byte) of the tmpfs working directory
+  tmpfs_workdir: Option<(PathBuf, u64)>,
+  pre_exec: Option<PreExecHook>,
   cwd: PathBuf,
   mounts: Vec<MountPoint>,
+  mount_retries: u32,
   env: Vec<(String, String)>,
+  jail_path: Option<String>,
+  ld_env: bool,
   stdin: Option<String>,
+  /// In-memory stdin written through a pipe, which takes precedence over the stdin path
+  stdin_bytes: Option<Vec<u8>>,
   stdout: Option<String>,
   stderr: Option<String>,
+  /// Answer file and comparator for the stdout
+  answer: Option<(PathBuf, Comparator)>,
+  require_empty_stderr: bool,
+  /// Pseudo-terminal (master, slave) pair, shared by the clones and closed when the last one drops
+  pty: Option<Arc<(OwnedFd, OwnedFd)>>,
+  /// Unix socket pair (caller, child) used as stdin and stdout, shared by the clones like `pty`
+  socketpair: Option<Arc<(OwnedFd, OwnedFd)>>,
+  /// Capture stdout and stderr into the result if they are not redirected
+  capture_stdout: bool,
+  capture_stderr: bool,
+  /// Parent fds forwarded to the child as (parent fd, child fd)
+  forward_fds: Vec<(RawFd, RawFd)>,
+  debug_stderr: bool,
+  report_command_line: bool,
+  /// Max size of the exec error message sent from the child
+  error_message_limit: usize,
   force: bool,
   debug: bool,
 }
 
+/// Which mechanism kills the child for exceeding time limit
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimeLimitReason {
+  /// CPU time limit by `RLIMIT_CPU` (SIGXCPU)
+  Cpu,
+  /// Wall time limit by `alarm` (SIGALRM) or the custom timeout signal
+  Wall,
+}
+
+/// Verdict of running a command, classified by its limits and termination
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Verdict {
+  Ok,
+  TimeLimitExceeded,
+  MemoryLimitExceeded,
+  RuntimeError,
+  /// Killed by `SIGXFSZ` for exceeding the output limit
+  OutputLimitExceeded,
+  /// Killed by other signals
+  Killed,
+}
+
+impl Verdict {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Verdict::Ok => "OK",
+      Verdict::TimeLimitExceeded => "TLE",
+      Verdict::MemoryLimitExceeded => "MLE",
+      Verdict::RuntimeError => "RE",
+      Verdict::OutputLimitExceeded => "OLE",
+      Verdict::Killed => "KILLED",
+    }
+  }
+}
+
 /// CatBox running result
 #[allow(unused)]
 #[derive(Debug, Clone)]
 pub struct CatBoxResult {
+  program: String,
   status: Option<i32>,
   signal: Option<Signal>,
+  core_dumped: bool,
+  time_limit_reason: Option<TimeLimitReason>,
+  stderr_violated: bool,
+  signal_history: Vec<Signal>,
+  /// Sorted distinct syscall numbers invoked by the child
+  distinct_syscalls: Vec<u64>,
+  /// First syscall number rejected by the ptrace filter
+  forbidden_syscall: Option<u64>,
+  rss_samples: Vec<(u64, MemoryLimitType)>,
+  verdict: Option<JudgeVerdict>,
+  /// Name of the persisted cgroup
+  cgroup: Option<String>,
+  command_line: Vec<String>,
+  /// Env of the executed command in `KEY=VALUE` format
+  env: Vec<String>,
+  report_command_line: bool,
+  /// Both cgroup and getrusage usage in diagnostic mode
+  diagnostics: Option<UsageDiagnostics>,
+  /// Captured stdout and stderr of the child
+  stdout_bytes: Vec<u8>,
+  stderr_bytes: Vec<u8>,
+  mle: bool,
   time: TimeLimitType,
   time_user: TimeLimitType,
   time_sys: TimeLimitType,
+  /// Elapsed real time from fork to the child terminates
+  wall_time: TimeLimitType,
   memory: MemoryLimitType,
+  /// Time limit and memory limit of the command, used to compute the ratios
+  limits: (TimeLimitType, MemoryLimitType),
 }
 
 impl CatBox {
   /// Run all the commands
   pub fn start(&mut self) -> Result<(), CatBoxError> {
-    for option in self.options.iter() {
-      info!("Run catbox with options: {:?}", &option);
+    for result in self.results_iter() {
+      result?;
+    }
+    Ok(())
+  }
+
+  /// Run the commands lazily, yielding the result of each command as soon as it finishes.
+  /// The results are also collected by the context for reporting.
+  pub fn results_iter(&mut self) -> CatBoxResults<'_> {
+    CatBoxResults {
+      catbox: self,
+      index: 0,
+      start: Instant::now(),
+      stopped: false,
+    }
+  }
 
-      let result = crate::run(&option)?;
-      if !self.context.add_result(&option.label.clone(), result) {
-        break;
+  /// Fork a detached supervisor process to run all the commands in background, and return its pid
+  /// immediately. The supervisor writes the JSON report (or `{"ok": false, "message": ...}` on
+  /// error) to `result` atomically by renaming, so pollers never see a partial file, then closes the
+  /// CatBoxes and exits. Wait for the pid to reap it, otherwise it is left as a zombie until the
+  /// caller exits.
+  pub fn start_detached<P: AsRef<Path>>(mut self, result: P) -> Result<Pid, CatBoxError> {
+    let result = result.as_ref().to_path_buf();
+    match unsafe { fork() } {
+      Ok(ForkResult::Parent { child }) => {
+        info!("Start detached supervisor #{}", child);
+        Ok(child)
       }
+      Ok(ForkResult::Child) => {
+        // 脱离调用者的会话，调用者退出后仍继续运行
+        setsid().ok();
+        let mut report = vec![];
+        let ok = match self
+          .start()
+          .and_then(|_| self.report_to(&mut report, ReportFormat::Json))
+        {
+          Ok(_) => true,
+          Err(err) => {
+            error!("Detached run fails: {}", err);
+            let body = json!({ "ok": false, "message": err.to_string() });
+            report = format!("{:#}\n", body).into_bytes();
+            false
+          }
+        };
+        self.close();
+
+        let temp = result.with_extension("tmp");
+        let written = fs::write(&temp, report).and_then(|_| fs::rename(&temp, &result));
+        if let Err(err) = written {
+          error!("Write detached result fails: {}", err);
+        }
+        unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+      }
+      Err(err) => Err(CatBoxError::fork(err.to_string())),
     }
-    Ok(())
+  }
+
+  /// Mark pipeline timeout if the total real time budget is exhausted
+  fn check_wall_budget(&mut self, start: Instant) {
+    if let Some(budget) = self.total_wall_budget {
+      if start.elapsed().as_millis() as TimeLimitType >= budget {
+        self.mark_pipeline_timeout();
+      }
+    }
+  }
+
+  fn mark_pipeline_timeout(&mut self) {
+    self.pipeline_timeout = true;
+    self.context.set_pipeline_timeout();
+  }
+
+  /// Whether the total real time budget is exhausted
+  pub fn pipeline_timeout(&self) -> bool {
+    self.pipeline_timeout
   }
 
   /// List all the commands
-  pub fn commands(&self) -> Iter<CatBoxOption> {
+  pub fn commands(&self) -> Iter<'_, CatBoxOption> {
     self.options.iter()
   }
 
@@ -127,7 +534,12 @@ impl CatBox {
 
   /// Report usage
   pub fn report(&self) {
-    self.context.report();
+    self.context.report(self.color, &self.report_options);
+  }
+
+  /// Report usage in human readable format, see [`CatBoxBuilder::color`]
+  pub fn report_human(&self) {
+    self.context.report_human(self.color, &self.report_options);
   }
 
   /// Report json format usage
@@ -135,6 +547,16 @@ impl CatBox {
     self.context.report_json();
   }
 
+  /// Write report to any sink
+  pub fn report_to(&self, w: &mut dyn Write, format: ReportFormat) -> Result<(), CatBoxError> {
+    self.context.report_to(w, format, &self.report_options)
+  }
+
+  /// Delete leftover sandbox cgroups under parent, and return the deleted cgroup names
+  pub fn cleanup_stale(parent: &str) -> Result<Vec<String>, CatBoxError> {
+    crate::cgroup::cleanup_stale_cgroups(parent)
+  }
+
   /// Close all the CatBoxes
   pub fn close(self) {
     for option in self.options.into_iter() {
@@ -144,17 +566,121 @@ impl CatBox {
 }
 
 impl CatBoxResult {
-  pub(crate) fn new(status: Option<i32>, signal: Option<Signal>, usage: CatBoxUsage) -> Self {
+  pub(crate) fn new(
+    status: Option<i32>,
+    signal: Option<Signal>,
+    core_dumped: bool,
+    time_limit_reason: Option<TimeLimitReason>,
+    stderr_violated: bool,
+    signal_history: Vec<Signal>,
+    usage: CatBoxUsage,
+  ) -> Self {
     CatBoxResult {
+      program: String::new(),
       status,
       signal,
+      core_dumped,
+      time_limit_reason,
+      stderr_violated,
+      signal_history,
+      distinct_syscalls: vec![],
+      forbidden_syscall: None,
+      rss_samples: vec![],
+      verdict: None,
+      cgroup: None,
+      command_line: vec![],
+      env: vec![],
+      report_command_line: false,
+      diagnostics: usage.diagnostics(),
+      stdout_bytes: vec![],
+      stderr_bytes: vec![],
+      mle: usage.memory_limit_exceeded(status != Some(0)),
       time: usage.time(),
       time_user: usage.time_user(),
       time_sys: usage.time_sys(),
+      wall_time: usage.wall_time(),
       memory: usage.memory(),
+      limits: (0, 0),
     }
   }
 
+  /// Program name, see [`crate::CatBoxOptionBuilder::display_name`]
+  pub fn program(&self) -> &str {
+    &self.program
+  }
+
+  pub(crate) fn with_program<S: Into<String>>(mut self, program: S) -> Self {
+    self.program = program.into();
+    self
+  }
+
+  /// Captured stdout, see [`crate::CatBoxOptionBuilder::capture_stdout`]
+  pub fn stdout_bytes(&self) -> &[u8] {
+    &self.stdout_bytes
+  }
+
+  /// Captured stderr, see [`crate::CatBoxOptionBuilder::capture_stderr`]
+  pub fn stderr_bytes(&self) -> &[u8] {
+    &self.stderr_bytes
+  }
+
+  pub(crate) fn with_captured_output(mut self, stdout: Vec<u8>, stderr: Vec<u8>) -> Self {
+    self.stdout_bytes = stdout;
+    self.stderr_bytes = stderr;
+    self
+  }
+
+  /// Verdict of comparing stdout with the answer, see [`crate::CatBoxOptionBuilder::answer`]
+  pub fn judge_verdict(&self) -> Option<JudgeVerdict> {
+    self.verdict
+  }
+
+  pub(crate) fn with_verdict(mut self, verdict: Option<JudgeVerdict>) -> Self {
+    self.verdict = verdict;
+    self
+  }
+
+  /// Path of the persisted cgroup relative to each controller, e.g. `/sys/fs/cgroup/memory/<path>`,
+  /// see [`crate::CatBoxOptionBuilder::persist_cgroup`]
+  pub fn cgroup(&self) -> Option<&str> {
+    self.cgroup.as_deref()
+  }
+
+  pub(crate) fn with_cgroup(mut self, cgroup: Option<String>) -> Self {
+    self.cgroup = cgroup;
+    self
+  }
+
+  /// Usage measured by both cgroup and getrusage, only recorded if it is enabled by
+  /// [`crate::CatBoxOptionBuilder::diagnose_usage`]
+  pub fn diagnostics(&self) -> Option<UsageDiagnostics> {
+    self.diagnostics
+  }
+
+  /// The executed argv, including the launcher, which is only recorded with
+  /// [`crate::CatBoxOptionBuilder::report_command_line`]
+  pub fn command_line(&self) -> &Vec<String> {
+    &self.command_line
+  }
+
+  /// The env passed to the executed command, in `KEY=VALUE` format, which is only recorded with
+  /// [`crate::CatBoxOptionBuilder::report_command_line`]
+  pub fn env(&self) -> &Vec<String> {
+    &self.env
+  }
+
+  pub(crate) fn with_command_line(
+    mut self,
+    command_line: Vec<String>,
+    env: Vec<String>,
+    report: bool,
+  ) -> Self {
+    self.command_line = command_line;
+    self.env = env;
+    self.report_command_line = report;
+    self
+  }
+
   pub fn status(&self) -> &Option<i32> {
     &self.status
   }
@@ -163,21 +689,267 @@ impl CatBoxResult {
     &self.signal
   }
 
+  /// Exit code in the shell convention like `$?`, i.e. the status if the child exits, or
+  /// `128 + signal number` if it is killed by signal, e.g. 139 for SIGSEGV (`-1` if neither is known)
+  pub fn shell_exit_code(&self) -> i32 {
+    match (self.status, self.signal) {
+      (Some(status), _) => status,
+      (None, Some(signal)) => 128 + signal as i32,
+      (None, None) => -1,
+    }
+  }
+
+  /// Whether the child dumped core when it is killed by signal
+  pub fn core_dumped(&self) -> bool {
+    self.core_dumped
+  }
+
+  /// Which mechanism kills the child for exceeding time limit
+  pub fn time_limit_reason(&self) -> Option<TimeLimitReason> {
+    self.time_limit_reason
+  }
+
+  /// Signals stopping the child in sequence, only recorded if it is enabled by
+  /// [`crate::CatBoxOptionBuilder::record_signal_history`]
+  pub fn signal_history(&self) -> &Vec<Signal> {
+    &self.signal_history
+  }
+
+  /// Sorted distinct syscall numbers invoked by the child, only recorded if it is enabled by
+  /// [`crate::CatBoxOptionBuilder::record_syscalls`]
+  pub fn distinct_syscalls(&self) -> &Vec<u64> {
+    &self.distinct_syscalls
+  }
+
+  pub(crate) fn with_distinct_syscalls(mut self, syscalls: Vec<u64>) -> Self {
+    self.distinct_syscalls = syscalls;
+    self
+  }
+
+  /// Number of the first syscall rejected by the ptrace filter, after which the child is killed
+  pub fn forbidden_syscall(&self) -> Option<u64> {
+    self.forbidden_syscall
+  }
+
+  pub(crate) fn with_forbidden_syscall(mut self, syscall: Option<u64>) -> Self {
+    self.forbidden_syscall = syscall;
+    self
+  }
+
+  /// Resident set size samples of the child as (elapsed ms, KB), only recorded if it is enabled by
+  /// [`crate::CatBoxOptionBuilder::sample_rss`]
+  pub fn rss_samples(&self) -> &Vec<(u64, MemoryLimitType)> {
+    &self.rss_samples
+  }
+
+  pub(crate) fn with_rss_samples(mut self, samples: Vec<(u64, MemoryLimitType)>) -> Self {
+    self.rss_samples = samples;
+    self
+  }
+
+  /// Mark MLE if the child is killed by [`crate::CatBoxOptionBuilder::sustained_memory_limit`]
+  pub(crate) fn with_sustained_mle(mut self, exceeded: bool) -> Self {
+    self.mle = self.mle || exceeded;
+    self
+  }
+
+  /// Whether the child writes to stderr while it is required to be empty
+  pub fn stderr_violated(&self) -> bool {
+    self.stderr_violated
+  }
+
+  /// Whether the child exceeds memory limit, either killed by the cgroup OOM killer or
+  /// failing to allocate after reaching the limit
+  pub fn mle(&self) -> bool {
+    self.mle
+  }
+
+  /// Total CPU time (unit: ms), which is never less than `time_user + time_sys`
   pub fn time(&self) -> TimeLimitType {
     self.time
   }
 
+  /// CPU time in user mode (unit: ms)
   pub fn time_user(&self) -> TimeLimitType {
     self.time_user
   }
 
+  /// CPU time in kernel mode (unit: ms)
   pub fn time_sys(&self) -> TimeLimitType {
     self.time_sys
   }
 
+  /// Elapsed real time (unit: ms) from fork to the child terminates, including sleeping on IO
+  pub fn wall_time(&self) -> TimeLimitType {
+    self.wall_time
+  }
+
   pub fn memory(&self) -> MemoryLimitType {
     self.memory
   }
+
+  pub(crate) fn with_limits(
+    mut self,
+    time_limit: TimeLimitType,
+    memory_limit: MemoryLimitType,
+  ) -> Self {
+    self.limits = (time_limit, memory_limit);
+    self
+  }
+
+  /// Fraction of the time limit used, i.e. `time / time_limit`, which exceeds 1 on TLE
+  pub fn time_ratio(&self) -> f64 {
+    ratio(self.time, self.limits.0)
+  }
+
+  /// Fraction of the memory limit used, i.e. `memory / memory_limit`
+  pub fn memory_ratio(&self) -> f64 {
+    ratio(self.memory, self.limits.1)
+  }
+
+  /// Classify the result by comparing the time and memory with the limits of the option, and by
+  /// [`CatBoxResult::mle`] and its termination signal. Unlike [`CatBoxResult::judge_verdict`], it
+  /// does not compare the output with the answer.
+  pub fn verdict(&self, option: &CatBoxOption) -> Verdict {
+    self.classify(option.time_limit(), option.memory_limit())
+  }
+
+  /// Verdict by the limits recorded in the result, which is used in reports, judge and metrics
+  pub(crate) fn limits_verdict(&self) -> Verdict {
+    self.classify(self.limits.0, self.limits.1)
+  }
+
+  fn classify(&self, time_limit: TimeLimitType, memory_limit: MemoryLimitType) -> Verdict {
+    let signal = self.signal;
+    if self.time_limit_reason.is_some()
+      || matches!(signal, Some(Signal::SIGXCPU) | Some(Signal::SIGALRM))
+      || self.time > time_limit
+    {
+      Verdict::TimeLimitExceeded
+    } else if self.mle || self.memory > memory_limit {
+      Verdict::MemoryLimitExceeded
+    } else if signal == Some(Signal::SIGXFSZ) {
+      Verdict::OutputLimitExceeded
+    } else if matches!(
+      signal,
+      Some(
+        Signal::SIGSEGV
+          | Signal::SIGFPE
+          | Signal::SIGABRT
+          | Signal::SIGBUS
+          | Signal::SIGILL
+          | Signal::SIGSYS
+      )
+    ) {
+      // 与 ptrace 中记为 RE 的信号一致
+      Verdict::RuntimeError
+    } else if signal.is_some() {
+      Verdict::Killed
+    } else if self.status != Some(0) {
+      Verdict::RuntimeError
+    } else {
+      Verdict::Ok
+    }
+  }
+}
+
+fn ratio(used: u64, limit: u64) -> f64 {
+  if limit == 0 {
+    0.0
+  } else {
+    used as f64 / limit as f64
+  }
+}
+
+impl Iterator for CatBoxResults<'_> {
+  type Item = Result<CatBoxResult, CatBoxError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.stopped {
+      return None;
+    }
+    let catbox = &mut *self.catbox;
+    let option = match catbox.options.get(self.index) {
+      Some(option) => option,
+      None => {
+        self.stopped = true;
+        catbox.check_wall_budget(self.start);
+        return None;
+      }
+    };
+    self.index += 1;
+    info!("Run catbox with options: {:?}", option);
+
+    let result = match catbox.total_wall_budget {
+      Some(budget) => {
+        let elapsed = self.start.elapsed().as_millis() as TimeLimitType;
+        if elapsed >= budget {
+          info!(
+            "Pipeline timeout: {} ms elapsed, skip the remaining commands",
+            elapsed
+          );
+          catbox.mark_pipeline_timeout();
+          self.stopped = true;
+          return None;
+        }
+        // 限制单个命令的 CPU 时间和墙上时间都不超过剩余时间
+        let remaining = budget - elapsed;
+        let mut option = option.clone();
+        option.time_limit = min(option.time_limit, remaining);
+        option.wall_time_limit = Some(
+          option
+            .wall_time_limit()
+            .map_or(remaining, |limit| min(limit, remaining)),
+        );
+        crate::run(&option)
+      }
+      None => crate::run(option),
+    };
+    let result = match result {
+      Ok(result) => result,
+      Err(err) => {
+        self.stopped = true;
+        return Some(Err(err));
+      }
+    };
+
+    let next = match catbox
+      .callbacks
+      .get_mut(self.index - 1)
+      .and_then(Option::take)
+    {
+      Some(callback) => callback(&result),
+      None => true,
+    };
+    if !catbox.context.add_result(option, result.clone()) || !next {
+      self.stopped = true;
+      catbox.check_wall_budget(self.start);
+    }
+    Some(Ok(result))
+  }
+}
+
+impl TimeLimitReason {
+  pub(crate) fn from_signal(signal: Signal) -> Option<Self> {
+    match signal {
+      Signal::SIGXCPU => Some(TimeLimitReason::Cpu),
+      Signal::SIGALRM | Signal::SIGVTALRM => Some(TimeLimitReason::Wall),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TimeLimitReason::Cpu => "cpu",
+      TimeLimitReason::Wall => "wall",
+    }
+  }
+}
+
+impl Default for CatBoxRunContext {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl CatBoxRunContext {
@@ -188,109 +960,430 @@ impl CatBoxRunContext {
       sum_time: 0,
       sum_memory: 0,
       results: vec![],
+      pipeline_timeout: false,
     }
   }
 }
 
 impl CatBoxContext for CatBoxRunContext {
-  fn add_result(&mut self, _label: &String, result: CatBoxResult) -> bool {
+  fn add_result(&mut self, option: &CatBoxOption, result: CatBoxResult) -> bool {
     self.max_time = max(self.max_time, result.time);
     self.max_memory = max(self.max_memory, result.memory);
     self.sum_time += result.time;
     self.sum_memory += result.memory;
-    self.results.push(result);
+    self.results.push((option.label().clone(), result));
     true
   }
 
-  fn report_human(&self) {
+  fn set_pipeline_timeout(&mut self) {
+    self.pipeline_timeout = true;
+  }
+
+  fn write_human(&self, w: &mut dyn Write, options: &ReportOptions) -> Result<(), CatBoxError> {
     if self.results.len() == 1 {
-      let result = self.results.first().unwrap();
-      let status = result.status().map_or_else(
-        || "\x1b[91m×\x1b[39m".to_string(),
-        |v| format!("\x1b[9{}m{}\x1b[39m", if v == 0 { 2 } else { 1 }, v),
-      );
-      let signal = result.signal().map_or_else(
-        || "\x1b[92m✓\x1b[39m".to_string(),
-        |v| format!("\x1b[91m{}\x1b[39m", v),
-      );
-
-      println!();
-      println!("\x1b[1mStatus\x1b[22m     {}", status);
-      println!("\x1b[1mSignal\x1b[22m     {}", signal);
-      println!("\x1b[1mTime\x1b[22m       {} ms", result.time());
-      println!("\x1b[1mTime user\x1b[22m  {} ms", result.time_user());
-      println!("\x1b[1mTime sys\x1b[22m   {} ms", result.time_sys());
-      println!("\x1b[1mMemory\x1b[22m     {} KB", result.memory());
-      println!();
+      let (_, result) = self.results.first().unwrap();
+
+      writeln!(w)?;
+      write_result_human(w, result, options)?;
+      write_pipeline_timeout_human(w, self.pipeline_timeout, options)?;
+      writeln!(w)?;
+      Ok(())
     } else {
-      todo!()
+      // 每条命令一行，单元格为 (文本, 颜色)
+      let header = ["Command", "Status", "Signal", "Time", "Memory"]
+        .map(|field| (format!("\x1b[1m{}\x1b[22m", options.label_of(field)), None));
+      let rows = self
+        .results
+        .iter()
+        .map(|(label, result)| {
+          let status = match result.status() {
+            Some(status) => (status.to_string(), Some(if *status == 0 { 2 } else { 1 })),
+            None => ("×".to_string(), Some(1)),
+          };
+          let signal = match result.signal() {
+            Some(signal) => (signal.to_string(), Some(1)),
+            None => ("✓".to_string(), Some(2)),
+          };
+          [
+            (label.clone(), None),
+            status,
+            signal,
+            (format!("{} ms", result.time()), None),
+            (format!("{} KB", result.memory()), None),
+          ]
+        })
+        .collect::<Vec<_>>();
+
+      // 按去除 ANSI 后的字符数对齐各列
+      let mut widths = [0; 5];
+      for row in [&header].into_iter().chain(rows.iter()) {
+        for (width, (text, _)) in widths.iter_mut().zip(row.iter()) {
+          *width = max(*width, strip_ansi(text).chars().count());
+        }
+      }
+
+      writeln!(w)?;
+      for row in [&header].into_iter().chain(rows.iter()) {
+        let mut line = String::new();
+        for (i, (text, color)) in row.iter().enumerate() {
+          match color {
+            Some(color) => line.push_str(&format!("\x1b[9{}m{}\x1b[39m", color, text)),
+            None => line.push_str(text),
+          }
+          if i + 1 < row.len() {
+            let padding = widths[i] - strip_ansi(text).chars().count() + 2;
+            line.push_str(&" ".repeat(padding));
+          }
+        }
+        writeln!(w, "{}", line)?;
+      }
+      writeln!(w)?;
+      options.write_field(w, "Max time", format_args!("{} ms", self.max_time))?;
+      options.write_field(w, "Max memory", format_args!("{} KB", self.max_memory))?;
+      options.write_field(w, "Sum time", format_args!("{} ms", self.sum_time))?;
+      options.write_field(w, "Sum memory", format_args!("{} KB", self.sum_memory))?;
+      write_pipeline_timeout_human(w, self.pipeline_timeout, options)?;
+      writeln!(w)?;
+      Ok(())
     }
   }
 
-  fn report_json(&self) {
+  fn write_json(&self, w: &mut dyn Write) -> Result<(), CatBoxError> {
     if self.results.len() == 1 {
-      let result = self.results.first().unwrap();
-      let status = result
-        .status()
-        .map_or_else(|| "null".to_string(), |v| v.to_string());
-      let signal = result
-        .signal()
-        .map_or_else(|| "null".to_string(), |v| format!("\"{}\"", v));
-
-      println!("{{");
-      println!("  \"ok\": true,");
-      println!("  \"status\": {},", status);
-      println!("  \"signal\": {},", signal);
-      println!("  \"time\": {},", result.time());
-      println!("  \"time_user\": {},", result.time_user());
-      println!("  \"time_sys\": {},", result.time_sys());
-      println!("  \"memory\": {}", result.memory());
-      println!("}}");
+      let (_, result) = self.results.first().unwrap();
+      let report = ResultReport {
+        ok: true,
+        pipeline_timeout: self.pipeline_timeout,
+        result,
+      };
+      write_pretty_json(w, &report)
     } else {
-      todo!()
+      let report = RunReport {
+        ok: true,
+        pipeline_timeout: self.pipeline_timeout,
+        aggregate: AggregateReport {
+          max_time: self.max_time,
+          max_memory: self.max_memory,
+          sum_time: self.sum_time,
+          sum_memory: self.sum_memory,
+        },
+        // 每条命令各自的结果
+        results: self
+          .results
+          .iter()
+          .map(|(label, result)| LabeledResult { label, result })
+          .collect(),
+      };
+      write_pretty_json(w, &report)
     }
   }
 }
 
 impl CatBoxCompileContext {
   fn new() -> Self {
-    CatBoxCompileContext { ok: true }
+    CatBoxCompileContext {
+      ok: true,
+      results: vec![],
+      pipeline_timeout: false,
+    }
   }
 }
 
 impl CatBoxContext for CatBoxCompileContext {
-  fn add_result(&mut self, _label: &String, result: CatBoxResult) -> bool {
-    if self.ok {
-      if result.status.unwrap_or(1) == 0 {
-        self.ok = true;
-        true
-      } else {
-        false
-      }
-    } else {
-      false
-    }
+  fn add_result(&mut self, option: &CatBoxOption, result: CatBoxResult) -> bool {
+    // 编译失败后不再运行后续命令
+    self.ok = self.ok
+      && result
+        .status
+        .is_some_and(|status| status == 0 || option.tolerate_status().contains(&status));
+    self.results.push((option.label().clone(), result));
+    self.ok
   }
 
-  fn report_human(&self) {
-    todo!()
+  fn set_pipeline_timeout(&mut self) {
+    self.pipeline_timeout = true;
   }
 
-  fn report_json(&self) {
-    todo!()
+  fn write_human(&self, w: &mut dyn Write, options: &ReportOptions) -> Result<(), CatBoxError> {
+    let compile = if self.ok {
+      "\x1b[92m✓\x1b[39m".to_string()
+    } else {
+      "\x1b[91m×\x1b[39m".to_string()
+    };
+
+    writeln!(w)?;
+    options.write_field(w, "Compile", format_args!("{}", compile))?;
+    for (label, result) in &self.results {
+      writeln!(w)?;
+      options.write_field(w, "Command", format_args!("{}", label))?;
+      write_result_human(w, result, options)?;
+    }
+    write_pipeline_timeout_human(w, self.pipeline_timeout, options)?;
+    writeln!(w)?;
+    Ok(())
+  }
+
+  fn write_json(&self, w: &mut dyn Write) -> Result<(), CatBoxError> {
+    let report = CompileReport {
+      ok: true,
+      compiled: self.ok,
+      pipeline_timeout: self.pipeline_timeout,
+      command: self.results.last().map(|(label, _)| label.as_str()),
+      // 每条编译命令各自的结果
+      commands: self
+        .results
+        .iter()
+        .map(|(command, result)| CommandResult { command, result })
+        .collect(),
+      result: self.results.last().map(|(_, result)| result),
+    };
+    write_pretty_json(w, &report)
   }
 }
 
 impl CatBoxContext for CatBoxJudgeContext {
-  fn add_result(&mut self, _label: &String, result: CatBoxResult) -> bool {
+  fn add_result(&mut self, _option: &CatBoxOption, _result: CatBoxResult) -> bool {
     todo!()
   }
 
-  fn report_human(&self) {
+  fn write_human(&self, _w: &mut dyn Write, _options: &ReportOptions) -> Result<(), CatBoxError> {
     todo!()
   }
 
-  fn report_json(&self) {
+  fn write_json(&self, _w: &mut dyn Write) -> Result<(), CatBoxError> {
     todo!()
   }
 }
+
+/// Write result fields in human format
+fn write_result_human(
+  w: &mut dyn Write,
+  result: &CatBoxResult,
+  options: &ReportOptions,
+) -> Result<(), CatBoxError> {
+  let status = result.status().map_or_else(
+    || "\x1b[91m×\x1b[39m".to_string(),
+    |v| format!("\x1b[9{}m{}\x1b[39m", if v == 0 { 2 } else { 1 }, v),
+  );
+  let signal = result.signal().map_or_else(
+    || "\x1b[92m✓\x1b[39m".to_string(),
+    |v| format!("\x1b[91m{}\x1b[39m", v),
+  );
+
+  options.write_field(w, "Program", format_args!("{}", result.program()))?;
+  options.write_field(w, "Status", format_args!("{}", status))?;
+  options.write_field(w, "Signal", format_args!("{}", signal))?;
+  if result.core_dumped() {
+    options.write_field(w, "Core dump", format_args!("\x1b[91m✓\x1b[39m"))?;
+  }
+  if let Some(reason) = result.time_limit_reason() {
+    options.write_field(
+      w,
+      "TLE",
+      format_args!("\x1b[91m{}\x1b[39m", reason.as_str()),
+    )?;
+  }
+  if !result.signal_history().is_empty() {
+    let history = result
+      .signal_history()
+      .iter()
+      .map(|s| s.to_string())
+      .collect::<Vec<String>>();
+    options.write_field(w, "Signals", format_args!("{}", history.join(" -> ")))?;
+  }
+  if let Some(peak) = result.rss_samples().iter().map(|(_, rss)| *rss).max() {
+    options.write_field(
+      w,
+      "RSS",
+      format_args!("{} samples, peak {} KB", result.rss_samples().len(), peak),
+    )?;
+  }
+  if !result.distinct_syscalls().is_empty() {
+    options.write_field(
+      w,
+      "Syscalls",
+      format_args!("{} distinct", result.distinct_syscalls().len()),
+    )?;
+  }
+  if let Some(syscall) = result.forbidden_syscall() {
+    options.write_field(
+      w,
+      "Forbidden",
+      format_args!("\x1b[91msyscall {}\x1b[39m", syscall),
+    )?;
+  }
+  if result.mle() {
+    options.write_field(w, "MLE", format_args!("\x1b[91m✓\x1b[39m"))?;
+  }
+  if result.stderr_violated() {
+    options.write_field(w, "Stderr", format_args!("\x1b[91mnot empty\x1b[39m"))?;
+  }
+  options.write_field(w, "Time", format_args!("{} ms", result.time()))?;
+  options.write_field(w, "Time user", format_args!("{} ms", result.time_user()))?;
+  options.write_field(w, "Time sys", format_args!("{} ms", result.time_sys()))?;
+  options.write_field(w, "Wall time", format_args!("{} ms", result.wall_time()))?;
+  options.write_field(w, "Memory", format_args!("{} KB", result.memory()))?;
+  let run_verdict = result.limits_verdict();
+  options.write_field(
+    w,
+    "Run verdict",
+    format_args!(
+      "\x1b[9{}m{}\x1b[39m",
+      if run_verdict == Verdict::Ok { 2 } else { 1 },
+      run_verdict.as_str()
+    ),
+  )?;
+  if let Some(verdict) = result.judge_verdict() {
+    let color = if verdict == JudgeVerdict::Accepted {
+      2
+    } else {
+      1
+    };
+    options.write_field(
+      w,
+      "Verdict",
+      format_args!("\x1b[9{}m{}\x1b[39m", color, verdict.as_str()),
+    )?;
+  }
+  Ok(())
+}
+
+/// Write the pipeline timeout line in human format, only when the total real time budget is exhausted
+fn write_pipeline_timeout_human(
+  w: &mut dyn Write,
+  pipeline_timeout: bool,
+  options: &ReportOptions,
+) -> Result<(), CatBoxError> {
+  if pipeline_timeout {
+    options.write_field(w, "Pipeline", format_args!("\x1b[91mtimeout\x1b[39m"))?;
+  }
+  Ok(())
+}
+
+/// JSON report of a single result
+#[derive(Serialize)]
+struct ResultReport<'a> {
+  ok: bool,
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pipeline_timeout: bool,
+  #[serde(flatten)]
+  result: &'a CatBoxResult,
+}
+
+/// JSON report of multiple results
+#[derive(Serialize)]
+struct RunReport<'a> {
+  ok: bool,
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pipeline_timeout: bool,
+  aggregate: AggregateReport,
+  results: Vec<LabeledResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct AggregateReport {
+  max_time: TimeLimitType,
+  max_memory: MemoryLimitType,
+  sum_time: TimeLimitType,
+  sum_memory: MemoryLimitType,
+}
+
+#[derive(Serialize)]
+struct LabeledResult<'a> {
+  label: &'a str,
+  #[serde(flatten)]
+  result: &'a CatBoxResult,
+}
+
+/// JSON report of compilation, flattened with the result of the last command
+#[derive(Serialize)]
+struct CompileReport<'a> {
+  ok: bool,
+  compiled: bool,
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pipeline_timeout: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  command: Option<&'a str>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  commands: Vec<CommandResult<'a>>,
+  #[serde(flatten)]
+  result: Option<&'a CatBoxResult>,
+}
+
+#[derive(Serialize)]
+struct CommandResult<'a> {
+  command: &'a str,
+  #[serde(flatten)]
+  result: &'a CatBoxResult,
+}
+
+/// Write the report as pretty printed JSON
+fn write_pretty_json<T: Serialize>(w: &mut dyn Write, report: &T) -> Result<(), CatBoxError> {
+  writeln!(w, "{}", serde_json::to_string_pretty(report)?)?;
+  Ok(())
+}
+
+impl Serialize for CatBoxResult {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let fields = result_json_fields(self);
+    let mut map = serializer.serialize_map(Some(fields.len()))?;
+    for (key, value) in &fields {
+      map.serialize_entry(key, value)?;
+    }
+    map.end()
+  }
+}
+
+/// Result fields as JSON key value pairs
+pub(crate) fn result_json_fields(result: &CatBoxResult) -> Vec<(&'static str, Value)> {
+  let signal_name = |s: &Signal| s.as_str();
+  // 比例保留 4 位小数
+  let round = |ratio: f64| (ratio * 10000.0).round() / 10000.0;
+
+  let mut fields = vec![
+    ("status", json!(result.status())),
+    ("signal", json!(result.signal().as_ref().map(signal_name))),
+    ("core_dumped", json!(result.core_dumped())),
+    (
+      "time_limit_reason",
+      json!(result.time_limit_reason().map(|v| v.as_str())),
+    ),
+    ("mle", json!(result.mle())),
+    ("stderr_violated", json!(result.stderr_violated())),
+    (
+      "signal_history",
+      json!(result
+        .signal_history()
+        .iter()
+        .map(signal_name)
+        .collect::<Vec<_>>()),
+    ),
+    ("rss_samples", json!(result.rss_samples())),
+    ("distinct_syscalls", json!(result.distinct_syscalls())),
+    ("forbidden_syscall", json!(result.forbidden_syscall())),
+    ("time", json!(result.time())),
+    ("time_user", json!(result.time_user())),
+    ("time_sys", json!(result.time_sys())),
+    ("wall_time", json!(result.wall_time())),
+    ("memory", json!(result.memory())),
+    ("program", json!(result.program())),
+    ("time_ratio", json!(round(result.time_ratio()))),
+    ("memory_ratio", json!(round(result.memory_ratio()))),
+    ("run_verdict", json!(result.limits_verdict().as_str())),
+  ];
+  if let Some(verdict) = result.judge_verdict() {
+    fields.push(("verdict", json!(verdict.as_str())));
+  }
+  // 命令行和环境变量可能包含敏感信息，需要显式开启
+  if result.report_command_line {
+    fields.push(("command_line", json!(result.command_line())));
+    fields.push(("env", json!(result.env())));
+  }
+  if let Some(diagnostics) = result.diagnostics() {
+    fields.push(("cgroup_time", json!(diagnostics.cgroup_time)));
+    fields.push(("cgroup_memory", json!(diagnostics.cgroup_memory)));
+    fields.push(("rusage_time", json!(diagnostics.rusage_time)));
+    fields.push(("rusage_memory", json!(diagnostics.rusage_memory)));
+  }
+  fields
+}
@@ -8,6 +8,8 @@ use flexi_logger::FlexiLoggerError;
 use nix::{errno::Errno, libc::STDERR_FILENO, unistd::isatty};
 
 /// CatBox Error
+///
+/// The `catj` CLI exits with a distinct code for each kind of error, see [`CatBoxError::exit_code`].
 pub enum CatBoxError {
   /// Fork child process failed.
   Fork(String),
@@ -21,6 +23,8 @@ pub enum CatBoxError {
   Fs(String),
   /// Parse CLI arguements failed.
   Cli(String),
+  /// Required sandbox features are unavailable in force mode.
+  Unavailable(String),
   /// Logger creation failed.
   Logger(FlexiLoggerError),
   /// Unknown error
@@ -49,6 +53,34 @@ impl CatBoxError {
   pub fn cli<MS: Into<String>>(msg: MS) -> CatBoxError {
     CatBoxError::Cli(msg.into())
   }
+
+  /// Exit code of the `catj` CLI for this error, while 0 is for success
+  ///
+  /// | Error | Code |
+  /// | --- | --- |
+  /// | Unknown | 1 |
+  /// | Cli | 2 |
+  /// | Fork | 3 |
+  /// | Cgroup | 4 |
+  /// | Exec | 5 |
+  /// | Nix | 6 |
+  /// | Fs | 7 |
+  /// | Logger | 8 |
+  /// | Unavailable | 9 |
+  pub fn exit_code(&self) -> u8 {
+    match self {
+      CatBoxError::Unknown(_) => 1,
+      // 与 clap 解析参数失败的退出码一致
+      CatBoxError::Cli(_) => 2,
+      CatBoxError::Fork(_) => 3,
+      CatBoxError::Cgroup(_) => 4,
+      CatBoxError::Exec(_) => 5,
+      CatBoxError::Nix(_) => 6,
+      CatBoxError::Fs(_) => 7,
+      CatBoxError::Logger(_) => 8,
+      CatBoxError::Unavailable(_) => 9,
+    }
+  }
 }
 
 impl Debug for CatBoxError {
@@ -66,6 +98,9 @@ impl Display for CatBoxError {
       CatBoxError::Nix(errno) => f.write_fmt(format_args!("CatBox Nix Error: {}", errno)),
       CatBoxError::Fs(msg) => f.write_fmt(format_args!("CatBox File System Error: {}", msg)),
       CatBoxError::Cli(msg) => f.write_fmt(format_args!("CLI Error: {}", msg)),
+      CatBoxError::Unavailable(msg) => {
+        f.write_fmt(format_args!("CatBox Unavailable Error: {}", msg))
+      }
       CatBoxError::Logger(err) => f.write_fmt(format_args!("Logger Error: {}", err)),
       CatBoxError::Unknown(msg) => f.write_fmt(format_args!("Unknown Error: {}", msg)),
     }
@@ -96,6 +131,12 @@ impl From<String> for CatBoxError {
   }
 }
 
+impl From<serde_json::Error> for CatBoxError {
+  fn from(err: serde_json::Error) -> Self {
+    CatBoxError::Unknown(err.to_string())
+  }
+}
+
 impl Error for CatBoxError {}
 
 impl Termination for CatBoxExit {
@@ -121,7 +162,7 @@ impl Termination for CatBoxExit {
           }
         };
         eprintln!("{}", text);
-        ExitCode::FAILURE.report()
+        ExitCode::from(err.exit_code()).report()
       }
     }
   }
@@ -0,0 +1,158 @@
+//! Judge an executable against a directory of testcases
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use path_absolutize::Absolutize;
+use tempfile::tempdir;
+
+use crate::compare::Comparator;
+use crate::context::{CatBox, CatBoxBuilder, CatBoxResult, Verdict};
+use crate::error::CatBoxError;
+use crate::utils::{MemoryLimitType, TimeLimitType};
+
+/// Verdict of a testcase
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JudgeVerdict {
+  Accepted,
+  WrongAnswer,
+  TimeLimitExceeded,
+  MemoryLimitExceeded,
+  RuntimeError,
+}
+
+/// Result of a testcase
+#[derive(Debug, Clone)]
+pub struct JudgeCase {
+  name: String,
+  verdict: JudgeVerdict,
+  result: CatBoxResult,
+}
+
+impl JudgeVerdict {
+  /// Map the run verdict, and compare the output only if it runs successfully
+  pub(crate) fn classify<F: FnOnce() -> Result<bool, CatBoxError>>(
+    verdict: Verdict,
+    compare: F,
+  ) -> Result<Self, CatBoxError> {
+    let verdict = match verdict {
+      Verdict::TimeLimitExceeded => JudgeVerdict::TimeLimitExceeded,
+      Verdict::MemoryLimitExceeded => JudgeVerdict::MemoryLimitExceeded,
+      Verdict::RuntimeError | Verdict::OutputLimitExceeded | Verdict::Killed => {
+        JudgeVerdict::RuntimeError
+      }
+      Verdict::Ok if compare()? => JudgeVerdict::Accepted,
+      Verdict::Ok => JudgeVerdict::WrongAnswer,
+    };
+    Ok(verdict)
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      JudgeVerdict::Accepted => "AC",
+      JudgeVerdict::WrongAnswer => "WA",
+      JudgeVerdict::TimeLimitExceeded => "TLE",
+      JudgeVerdict::MemoryLimitExceeded => "MLE",
+      JudgeVerdict::RuntimeError => "RE",
+    }
+  }
+}
+
+impl JudgeCase {
+  /// Testcase name, i.e. the file stem of `<name>.in`
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn verdict(&self) -> JudgeVerdict {
+    self.verdict
+  }
+
+  pub fn result(&self) -> &CatBoxResult {
+    &self.result
+  }
+}
+
+impl CatBox {
+  /// Run the executable on each `<name>.in` in the testcases directory, and compare its output with
+  /// `<name>.ans` (or `<name>.out`). Testcases are sorted by name, numerically if possible.
+  ///
+  /// The executable runs in the default chroot jail, with its directory mounted read-only.
+  pub fn judge_against_dir<EP: AsRef<Path>, TP: AsRef<Path>>(
+    executable: EP,
+    testcases_dir: TP,
+    time_limit: TimeLimitType,
+    memory_limit: MemoryLimitType,
+    comparator: &Comparator,
+  ) -> Result<Vec<JudgeCase>, CatBoxError> {
+    let executable = executable.as_ref().absolutize()?.to_path_buf();
+    let executable_dir = executable
+      .parent()
+      .ok_or(CatBoxError::cli("Executable should be a file"))?
+      .to_path_buf();
+    let output_dir = tempdir()?;
+    let output = output_dir.path().join("sub.out");
+
+    let mut cases = vec![];
+    for (name, input, answer) in list_testcases(testcases_dir.as_ref())? {
+      info!("Judge testcase {}", name);
+      let catbox = CatBoxBuilder::run()
+        .command::<String, String>(executable.to_string_lossy().to_string(), vec![])
+        .label(name.clone())
+        .time_limit(time_limit)
+        .memory_limit(memory_limit)
+        .stdin(input.to_string_lossy())
+        .stdout(output.to_string_lossy())
+        .stderr("/dev/null")
+        .chroot()
+        .cwd("/")
+        .mount_read(&executable_dir, &executable_dir)
+        .build();
+      let result = crate::run(catbox.single().unwrap());
+      catbox.close();
+      let result = result?;
+
+      let verdict = JudgeVerdict::classify(result.limits_verdict(), || {
+        Ok(comparator.compare_files(&output, &answer)?.same())
+      })?;
+      info!("Testcase {} is {}", name, verdict.as_str());
+      cases.push(JudgeCase {
+        name,
+        verdict,
+        result,
+      });
+    }
+    Ok(cases)
+  }
+}
+
+/// List (name, input, answer) of testcases in the directory
+fn list_testcases(dir: &Path) -> Result<Vec<(String, PathBuf, PathBuf)>, CatBoxError> {
+  let mut testcases = vec![];
+  for entry in fs::read_dir(dir)? {
+    let input = entry?.path();
+    if input.extension().is_none_or(|ext| ext != "in") {
+      continue;
+    }
+    let name = match input.file_stem() {
+      Some(stem) => stem.to_string_lossy().to_string(),
+      None => continue,
+    };
+    let answer = ["ans", "out"]
+      .iter()
+      .map(|ext| input.with_extension(ext))
+      .find(|answer| answer.is_file())
+      .ok_or(CatBoxError::Fs(format!(
+        "Answer of testcase {} is not found",
+        name
+      )))?;
+    testcases.push((name, input, answer));
+  }
+  // 按编号排序，无法解析为数字的排在后面
+  testcases.sort_by_key(|(name, _, _)| {
+    let number = name.parse::<u64>().map_or((1, 0), |number| (0, number));
+    (number, name.clone())
+  });
+  Ok(testcases)
+}
@@ -25,22 +25,49 @@
 //! $ catj --report run --stdin ./fixtures/aplusb/testcases/1.in --stdout ./sub.out --read . -- ./a.out
 //! # or
 //! $ catj -r run -i ./fixtures/aplusb/testcases/1.in -o ./sub.out -R . -- ./a.out
+//! Program    ./a.out
 //! Status     0
 //! Signal     ✓
 //! Time       1 ms
 //! Time user  1 ms
 //! Time sys   0 ms
 //! Memory     0 KB
+//!
+//! # Compare output with answer
+//! $ catj compare ./sub.out ./fixtures/aplusb/testcases/1.ans
+//!
+//! # Run a.out and compare its output with answer, which reports the verdict (AC or WA)
+//! $ catj -r run -i ./fixtures/aplusb/testcases/1.in --answer ./fixtures/aplusb/testcases/1.ans -R . -- ./a.out
 //! ```
 
+pub use bench::BenchReport;
 pub use catbox::run;
-pub use context::{CatBox, CatBoxBuilder, CatBoxOption, CatBoxOptionBuilder, CatBoxResult};
+pub use cgroup::UsageDiagnostics;
+pub use checker::{check, CheckResult, CheckVerdict, CheckerFormat};
+pub use compare::{Comparator, CompareDifference, CompareMode, CompareResult};
+pub use context::builder::CatBoxOptionBuilder;
+pub use context::{
+  CatBox, CatBoxBuilder, CatBoxOption, CatBoxResult, CatBoxResults, ColorChoice, CompleteCallback,
+  Limits, PreExecHook, ReportFormat, ReportOptions, TimeLimitReason, Verdict,
+};
 pub use error::CatBoxError;
-pub use syscall::{RestrictedSyscall, SyscallFilter};
+pub use judge::{JudgeCase, JudgeVerdict};
+pub use metrics::Metrics;
+pub use serve::serve;
+pub use syscall::{NetPolicy, RestrictedSyscall, SyscallFilter};
+pub use utils::capability::DropCapabilities;
+pub use utils::default_format;
+pub use utils::mount::{hash_tree, retry_mount, MountOrigin, MountPoint};
 
+mod bench;
 mod catbox;
 mod cgroup;
+mod checker;
+mod compare;
 pub mod context;
 mod error;
+mod judge;
+mod metrics;
+mod serve;
 mod syscall;
 mod utils;
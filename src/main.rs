@@ -1,24 +1,39 @@
 #![allow(dead_code)]
 
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use flexi_logger::{FileSpec, Logger};
 use log::{error, info};
+use path_absolutize::Absolutize;
+use serde_json::json;
 
 use crate::catbox::run;
-use crate::context::{CatBox, CatBoxBuilder, CatBoxOption};
+use crate::cgroup::default_cgroup;
+use crate::checker::{check, CheckerFormat};
+use crate::compare::{Comparator, CompareMode};
+use crate::context::{CatBox, CatBoxBuilder, CatBoxOption, ColorChoice, ReportFormat};
 use crate::error::{CatBoxError, CatBoxExit};
 use crate::preset::make_compile_params;
+use crate::serve::serve;
+use crate::syscall::SyscallFilter;
 // use crate::preset::make_compile_params;
-use crate::utils::{default_format, GidType, MemoryLimitType, TimeLimitType, UidType};
+use crate::utils::{
+  default_format, read_env_file, GidType, MemoryLimitType, TimeLimitType, UidType,
+};
 
+mod bench;
 mod catbox;
 mod cgroup;
+mod checker;
+mod compare;
 mod context;
 mod error;
+mod judge;
+mod metrics;
 mod preset;
+mod serve;
 mod syscall;
 mod utils;
 
@@ -31,15 +46,36 @@ struct Cli {
   #[arg(long, requires = "report", help = "Output JSON format report")]
   json: bool,
 
-  #[arg(short, long, help = "Time limit (unit: ms) [default: 1000]")]
+  #[arg(
+    long,
+    value_name = "WHEN",
+    help = "Color the report, which is human readable even if piped once set [support: auto|always|never] [default: auto]"
+  )]
+  color: Option<String>,
+
+  #[arg(
+    short,
+    long,
+    visible_alias = "cpu-time",
+    help = "CPU time limit (unit: ms) [default: 1000]"
+  )]
   time: Option<TimeLimitType>,
 
+  #[arg(long, help = "Wall time limit (unit: ms) [default: CPU time limit + 1000]")]
+  wall_time: Option<TimeLimitType>,
+
   #[arg(short, long, help = "Memory limit (unit: KB) [default: 262144]")]
   memory: Option<MemoryLimitType>,
 
+  #[arg(long, help = "Output file size limit (unit: byte) [default: 268435456]")]
+  output_limit: Option<u64>,
+
   #[arg(long, value_name = "KEY=VALUE", help = "Pass environment variables [default: PATH]")]
   env: Vec<String>,
 
+  #[arg(long, value_name = "PATH", help = "Read environment variables from file")]
+  env_file: Vec<PathBuf>,
+
   #[arg(long, help = "Current working directory [default: ./]")]
   cwd: Option<PathBuf>,
 
@@ -78,10 +114,20 @@ pub(crate) enum Commands {
     #[arg(short = 'e', long, help = "Redirect stderr [default: PIPE]")]
     stderr: Option<String>,
 
-    #[arg(short = 'R', long, value_name = "SRC:DST", help = "Mount read-only directory")]
+    #[arg(
+      short = 'R',
+      long,
+      value_name = "SRC:DST",
+      help = "Mount read-only directory (or src=SRC,dst=DST)"
+    )]
     read: Vec<String>,
 
-    #[arg(short = 'W', long, value_name = "SRC:DST", help = "Mount read-write directory")]
+    #[arg(
+      short = 'W',
+      long,
+      value_name = "SRC:DST",
+      help = "Mount read-write directory (or src=SRC,dst=DST)"
+    )]
     write: Vec<String>,
 
     #[arg(long, help = "The number of processes [default: 1]")]
@@ -94,8 +140,28 @@ pub(crate) enum Commands {
     )]
     ptrace: Option<Vec<String>>,
 
+    #[arg(
+      long,
+      value_name = "POLICY",
+      help = "Network policy [support: allow|seccomp] [default: allow]"
+    )]
+    net: Option<String>,
+
     #[arg(long, help = "Disable chroot [default: false]")]
     no_chroot: bool,
+
+    #[arg(long, help = "Report a violation if stderr is not empty [default: false]")]
+    require_empty_stderr: bool,
+
+    #[arg(long, help = "Compare stdout with the answer file, and report AC or WA")]
+    answer: Option<String>,
+
+    #[arg(
+      long,
+      requires = "answer",
+      help = "Compare mode [support: strict|token|lenient] [default: token]"
+    )]
+    compare_mode: Option<String>,
   },
 
   #[command(about = "Compile user code")]
@@ -114,6 +180,56 @@ pub(crate) enum Commands {
 
     #[arg(long, default_value = "/dev/null", help = "Redirect stderr")]
     stderr: String,
+
+    #[arg(
+      long,
+      value_name = "USER",
+      help = "Compile as this user [support: nobody|current|root|NAME] [default: preset user]"
+    )]
+    build_user: Option<String>,
+  },
+
+  #[command(about = "Compare output with answer")]
+  Compare {
+    #[arg(help = "Output file (- for stdin)")]
+    output: String,
+
+    #[arg(help = "Answer file (- for stdin)")]
+    answer: String,
+
+    #[arg(long, help = "Compare mode [support: strict|token|lenient] [default: token]")]
+    mode: Option<String>,
+
+    #[arg(long, help = "Only compare the first bytes of each file (unit: B)")]
+    max_bytes: Option<u64>,
+  },
+
+  #[command(about = "Print the ptrace syscall policy")]
+  Policy {
+    #[arg(
+      long,
+      value_name = "PRESET",
+      help = "Enable ptrace presets [support: none|net|process|all] [default: all]"
+    )]
+    ptrace: Option<Vec<String>>,
+  },
+
+  #[command(about = "Delete leftover sandbox cgroups")]
+  Gc {
+    #[arg(long, help = "Parent cgroup name [default: $CATJ_CGROUP or current user]")]
+    cgroup: Option<String>,
+  },
+
+  #[command(about = "Serve newline-delimited JSON requests from stdin")]
+  Serve,
+
+  #[command(about = "Measure the sandbox overhead by running a no-op program")]
+  Bench {
+    #[arg(short = 'n', long, default_value_t = 20, help = "The number of iterations")]
+    iterations: usize,
+
+    #[arg(long, help = "Disable chroot [default: false]")]
+    no_chroot: bool,
   },
 
   #[command(about = "Run validator")]
@@ -125,30 +241,56 @@ pub(crate) enum Commands {
   #[command(about = "Run checker")]
   Check {
     #[arg(help = "Checker")]
-    checker: PathBuf,
+    checker: String,
+
+    #[arg(help = "Input file")]
+    input: String,
+
+    #[arg(help = "Output file")]
+    output: String,
+
+    #[arg(help = "Answer file")]
+    answer: String,
+
+    #[arg(
+      long,
+      help = "How to read the checker verdict [support: exitcode|kv] [default: exitcode]"
+    )]
+    checker_format: Option<String>,
   },
 }
 
 impl Cli {
   fn resolve(self) -> Result<CatBox, CatBoxError> {
+    let env = self.env_list()?;
     let builder = match self.command {
       Commands::Run { .. } => CatBoxBuilder::run(),
       Commands::Compile { .. } => CatBoxBuilder::compile(),
-      Commands::Validate { .. } => {
-        unimplemented!()
+      Commands::Compare { .. }
+      | Commands::Gc { .. }
+      | Commands::Policy { .. }
+      | Commands::Serve => {
+        unreachable!()
       }
-      Commands::Check { .. } => {
+      Commands::Validate { .. } => {
         unimplemented!()
       }
+      Commands::Check { .. } | Commands::Bench { .. } => CatBoxBuilder::run(),
     }
-    .set_default_time_limit(self.time)
+    .set_default_time_limit(self.time.or(self.wall_time))
+    .set_default_wall_time_limit(self.wall_time)
     .set_default_memory_limit(self.memory)
+    .set_default_output_limit(self.output_limit)
     .set_default_force(self.force)
+    .color(match self.color {
+      Some(color) => ColorChoice::parse(color)?,
+      None => ColorChoice::Auto,
+    })
     .set_current_user(self.user)
     .set_default_uid(self.uid)
     .set_default_gid(self.gid)
     .set_default_cwd(self.cwd)
-    .parse_env_list(self.env)?;
+    .parse_env_list(env)?;
 
     let catbox = match self.command {
       Commands::Run {
@@ -161,7 +303,11 @@ impl Cli {
         write,
         process,
         ptrace,
+        net,
         no_chroot,
+        require_empty_stderr,
+        answer,
+        compare_mode,
       } => builder
         .command(program, arguments)
         .set_process(process)
@@ -169,7 +315,10 @@ impl Cli {
         .set_stdout(stdout)
         .set_stderr(stderr)
         .set_chroot(!no_chroot)
+        .require_empty_stderr(require_empty_stderr)
+        .parse_answer(answer, compare_mode)?
         .parse_ptrace_presets(ptrace)?
+        .parse_net_policy(net)?
         .parse_mount_read(read)?
         .parse_mount_write(write)?
         .done(),
@@ -177,16 +326,197 @@ impl Cli {
         // make_compile_params(language, submission, output)?
         make_compile_params(builder, self.command)?
       }
+      Commands::Compare { .. }
+      | Commands::Gc { .. }
+      | Commands::Policy { .. }
+      | Commands::Serve => {
+        unreachable!()
+      }
       Commands::Validate { .. } => {
         unimplemented!()
       }
-      Commands::Check { .. } => {
-        unimplemented!()
+      Commands::Check {
+        checker,
+        input,
+        output,
+        answer,
+        ..
+      } => {
+        let builder = if Path::new(&checker).is_file() {
+          // 检查器为本地文件时，使用绝对路径并只读挂载到 jail 中
+          let checker = Path::new(&checker).absolutize()?.to_path_buf();
+          builder
+            .command::<String, String>(checker.to_string_lossy().to_string(), vec![])
+            .mount_read(&checker, &checker)
+        } else {
+          builder.command::<String, String>(checker, vec![])
+        };
+        builder.checker_files(input, output, answer).done()
       }
+      Commands::Bench { no_chroot, .. } => builder
+        .command::<&str, &str>("true", vec![])
+        .stdin("/dev/null")
+        .stdout("/dev/null")
+        .stderr("/dev/null")
+        .set_chroot(!no_chroot)
+        .done(),
     };
 
     Ok(catbox.build())
   }
+
+  fn compare(self) -> Result<(), CatBoxError> {
+    if let Commands::Compare {
+      output,
+      answer,
+      mode,
+      max_bytes,
+    } = self.command
+    {
+      let mode = match mode {
+        Some(mode) => CompareMode::parse(mode)?,
+        None => CompareMode::Token,
+      };
+      let comparator = Comparator::new(mode).set_max_bytes(max_bytes);
+      let result = comparator.compare_files(output, answer)?;
+
+      if !self.json {
+        match result.difference() {
+          None => println!("\x1b[1mSame\x1b[22m       \x1b[92m✓\x1b[39m"),
+          Some(diff) => {
+            println!("\x1b[1mSame\x1b[22m       \x1b[91m×\x1b[39m");
+            println!("\x1b[1mLine\x1b[22m       {}", diff.line());
+            println!("\x1b[1mToken\x1b[22m      {}", diff.token());
+            println!("\x1b[1mByte\x1b[22m       {}", diff.byte());
+          }
+        }
+      } else {
+        let report = match result.difference() {
+          None => json!({ "ok": true, "same": true }),
+          Some(diff) => json!({
+            "ok": true,
+            "same": false,
+            "line": diff.line(),
+            "token": diff.token(),
+            "byte": diff.byte(),
+          }),
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+      }
+      Ok(())
+    } else {
+      Err(CatBoxError::cli("unreachable"))
+    }
+  }
+
+  fn check(self) -> Result<(), CatBoxError> {
+    let format = match &self.command {
+      Commands::Check {
+        checker_format: Some(format),
+        ..
+      } => CheckerFormat::parse(format)?,
+      _ => CheckerFormat::ExitCode,
+    };
+    let json_format = self.json;
+    let catbox = self.resolve()?;
+    let result = check(catbox.single().unwrap(), format);
+    catbox.close();
+    let result = result?;
+
+    if !json_format {
+      println!("\x1b[1mVerdict\x1b[22m    {}", result.verdict().as_str());
+      if let Some(score) = result.score() {
+        println!("\x1b[1mScore\x1b[22m      {}", score);
+      }
+      println!("\x1b[1mMessage\x1b[22m    {}", result.message());
+    } else {
+      let report = json!({
+        "ok": true,
+        "verdict": result.verdict().as_str(),
+        "score": result.score(),
+        "message": result.message(),
+      });
+      println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    Ok(())
+  }
+
+  fn policy(self) -> Result<(), CatBoxError> {
+    if let Commands::Policy { ptrace } = self.command {
+      let filter = match ptrace {
+        Some(presets) => SyscallFilter::parse_presets(presets)?,
+        None => Some(SyscallFilter::default()),
+      };
+      let format = if self.json {
+        ReportFormat::Json
+      } else {
+        ReportFormat::Human
+      };
+      match filter {
+        Some(filter) => print!("{}", filter.export(format)),
+        None => println!("No syscall filter"),
+      }
+      Ok(())
+    } else {
+      Err(CatBoxError::cli("unreachable"))
+    }
+  }
+
+  /// Env from files, then env from flags
+  fn env_list(&self) -> Result<Vec<String>, CatBoxError> {
+    let mut list = vec![];
+    for path in self.env_file.iter() {
+      list.extend(read_env_file(path)?);
+    }
+    list.extend(self.env.iter().cloned());
+    Ok(list)
+  }
+
+  fn serve(self) -> Result<(), CatBoxError> {
+    let env = self.env_list()?;
+    let make_builder = || {
+      CatBoxBuilder::run()
+        .set_default_time_limit(self.time.or(self.wall_time))
+        .set_default_wall_time_limit(self.wall_time)
+        .set_default_memory_limit(self.memory)
+        .set_default_output_limit(self.output_limit)
+        .set_default_force(self.force)
+        .set_current_user(self.user)
+        .set_default_uid(self.uid)
+        .set_default_gid(self.gid)
+        .set_default_cwd(self.cwd.clone())
+        .parse_env_list(env.clone())
+    };
+    let stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    serve(make_builder, stdin, &mut stdout)
+  }
+
+  fn bench(self) -> Result<(), CatBoxError> {
+    let iterations = match self.command {
+      Commands::Bench { iterations, .. } => iterations,
+      _ => return Err(CatBoxError::cli("unreachable")),
+    };
+    let catbox = self.resolve()?;
+    let report = CatBox::bench(catbox.single().unwrap(), iterations);
+    catbox.close();
+    println!("{}", report?.to_json()?);
+    Ok(())
+  }
+
+  fn gc(self) -> Result<(), CatBoxError> {
+    if let Commands::Gc { cgroup } = self.command {
+      let parent = cgroup.unwrap_or_else(default_cgroup);
+      let removed = CatBox::cleanup_stale(&parent)?;
+      for name in removed.iter() {
+        println!("\x1b[1mDelete\x1b[22m     {}", name);
+      }
+      println!("\x1b[1mTotal\x1b[22m      {}", removed.len());
+      Ok(())
+    } else {
+      Err(CatBoxError::cli("unreachable"))
+    }
+  }
 }
 
 fn bootstrap() -> Result<(), CatBoxError> {
@@ -209,6 +539,25 @@ fn bootstrap() -> Result<(), CatBoxError> {
   info!("Start running catj");
 
   let cli = Cli::parse();
+  if let Commands::Compare { .. } = cli.command {
+    return cli.compare();
+  }
+  if let Commands::Gc { .. } = cli.command {
+    return cli.gc();
+  }
+  if let Commands::Policy { .. } = cli.command {
+    return cli.policy();
+  }
+  if let Commands::Check { .. } = cli.command {
+    return cli.check();
+  }
+  if let Commands::Serve = cli.command {
+    return cli.serve();
+  }
+  if let Commands::Bench { .. } = cli.command {
+    return cli.bench();
+  }
+
   let report = cli.report;
   let json_format = cli.json;
   let mut catbox = cli.resolve()?;
@@ -0,0 +1,144 @@
+//! Aggregated run metrics in Prometheus text exposition format
+
+use crate::context::{CatBoxResult, Verdict};
+
+/// Upper bounds of the time histogram buckets (unit: ms)
+const TIME_BUCKETS: [u64; 6] = [100, 250, 500, 1000, 2000, 5000];
+
+/// Upper bounds of the memory histogram buckets (unit: KB)
+const MEMORY_BUCKETS: [u64; 5] = [16384, 65536, 262144, 524288, 1048576];
+
+/// Run metrics accumulated across runs
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+  runs: u64,
+  ok: u64,
+  tle: u64,
+  mle: u64,
+  re: u64,
+  ole: u64,
+  killed: u64,
+  errors: u64,
+  time: Histogram,
+  memory: Histogram,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+  /// Count of each bucket, the last one is `+Inf`
+  buckets: Vec<u64>,
+  sum: u64,
+  count: u64,
+}
+
+impl Metrics {
+  pub fn new() -> Self {
+    Metrics {
+      time: Histogram::new(TIME_BUCKETS.len()),
+      memory: Histogram::new(MEMORY_BUCKETS.len()),
+      ..Default::default()
+    }
+  }
+
+  /// Record the result of a finished run
+  pub fn record(&mut self, result: &CatBoxResult) {
+    self.runs += 1;
+    match result.limits_verdict() {
+      Verdict::Ok => self.ok += 1,
+      Verdict::TimeLimitExceeded => self.tle += 1,
+      Verdict::MemoryLimitExceeded => self.mle += 1,
+      Verdict::RuntimeError => self.re += 1,
+      Verdict::OutputLimitExceeded => self.ole += 1,
+      Verdict::Killed => self.killed += 1,
+    }
+    self.time.observe(&TIME_BUCKETS, result.time());
+    self.memory.observe(&MEMORY_BUCKETS, result.memory());
+  }
+
+  /// Record a request failing to run
+  pub fn record_error(&mut self) {
+    self.errors += 1;
+  }
+
+  /// Export metrics in Prometheus text exposition format
+  pub fn export(&self) -> String {
+    let mut text = String::new();
+
+    text.push_str("# HELP catj_runs_total Total number of finished runs.\n");
+    text.push_str("# TYPE catj_runs_total counter\n");
+    text.push_str(&format!("catj_runs_total {}\n", self.runs));
+
+    text.push_str("# HELP catj_verdicts_total Number of finished runs by verdict.\n");
+    text.push_str("# TYPE catj_verdicts_total counter\n");
+    for (verdict, count) in [
+      ("ok", self.ok),
+      ("tle", self.tle),
+      ("mle", self.mle),
+      ("re", self.re),
+      ("ole", self.ole),
+      ("killed", self.killed),
+    ] {
+      text.push_str(&format!(
+        "catj_verdicts_total{{verdict=\"{}\"}} {}\n",
+        verdict, count
+      ));
+    }
+
+    text.push_str("# HELP catj_errors_total Number of requests failing to run.\n");
+    text.push_str("# TYPE catj_errors_total counter\n");
+    text.push_str(&format!("catj_errors_total {}\n", self.errors));
+
+    self.time.export(
+      &mut text,
+      "catj_time_ms",
+      "CPU time of finished runs in milliseconds.",
+      &TIME_BUCKETS,
+    );
+    self.memory.export(
+      &mut text,
+      "catj_memory_kb",
+      "Peak memory of finished runs in kilobytes.",
+      &MEMORY_BUCKETS,
+    );
+    text
+  }
+}
+
+impl Histogram {
+  fn new(size: usize) -> Self {
+    Histogram {
+      buckets: vec![0; size + 1],
+      sum: 0,
+      count: 0,
+    }
+  }
+
+  fn observe(&mut self, bounds: &[u64], value: u64) {
+    let index = bounds
+      .iter()
+      .position(|bound| value <= *bound)
+      .unwrap_or(bounds.len());
+    self.buckets[index] += 1;
+    self.sum += value;
+    self.count += 1;
+  }
+
+  fn export(&self, text: &mut String, name: &str, help: &str, bounds: &[u64]) {
+    text.push_str(&format!("# HELP {} {}\n", name, help));
+    text.push_str(&format!("# TYPE {} histogram\n", name));
+    // Prometheus 的桶是累计计数
+    let mut cumulative = 0;
+    for (i, count) in self.buckets.iter().enumerate() {
+      cumulative += count;
+      let le = bounds
+        .get(i)
+        .map_or_else(|| "+Inf".to_string(), |bound| bound.to_string());
+      text.push_str(&format!(
+        "{}_bucket{{le=\"{}\"}} {}\n",
+        name, le, cumulative
+      ));
+    }
+    text.push_str(&format!("{}_sum {}\n", name, self.sum));
+    text.push_str(&format!("{}_count {}\n", name, self.count));
+  }
+}
@@ -23,6 +23,7 @@ lazy_static! {
       .command(
         // Use bash to expand *.class
         ExecuteCommand::new("bash", vec!["-c", "jar -cvf ${executable} *.class"])
+          .default_display_name("jar")
           .default_time_limit(10 * 1000)
           .default_memory_limit(1024 * 1024)
           .default_user(UserType::Current)
@@ -34,6 +35,7 @@ lazy_static! {
       ),
     execute: ExecuteOption::new().command(
       ExecuteCommand::new("java", vec!["-Xmx512M", "-Xss64M", "-DONLINE_JUDGE=true", "-cp", "${executable}", "Main"])
+        .default_display_name("Java")
         .default_ptrace(vec![RestrictedSyscall::Net])
         .default_process(20)
         .append_read_mount("/proc", "/proc")
@@ -12,6 +12,7 @@ use crate::preset::preset::UserType;
 use crate::Commands;
 
 mod default;
+#[allow(clippy::module_inception)]
 mod preset;
 
 lazy_static! {
@@ -31,7 +32,7 @@ lazy_static! {
   };
 }
 
-fn detect_language(language: &Option<String>, submission: &String) -> Option<String> {
+fn detect_language(language: &Option<String>, submission: &str) -> Option<String> {
   if let Some(language) = language {
     return if let Some(language) = DETECT_LANGUAGE_MAP.get(language.as_str()) {
       Some(language.to_string())
@@ -56,6 +57,7 @@ pub(crate) fn make_compile_params(
     language,
     submission,
     output,
+    build_user,
     ..
   } = command
   {
@@ -82,30 +84,39 @@ pub(crate) fn make_compile_params(
     let output_dir = output.parent().unwrap();
 
     for command in preset.compile.commands.iter() {
+      let program = command.apply_program(submission.to_str().unwrap(), output.to_str().unwrap());
+      let display_name =
+        command.apply_display_name(submission.to_str().unwrap(), output.to_str().unwrap());
       let option_builder = builder
         .command(
-          command.apply_program(submission.to_str().unwrap(), output.to_str().unwrap()),
+          program,
           command.apply_arguments(submission.to_str().unwrap(), output.to_str().unwrap()),
         )
+        .label(display_name.clone())
+        .display_name(display_name)
         .time_limit(command.time_limit)
         .memory_limit(command.memory_limit)
         .set_process(Some(command.process))
         .set_chroot(command.chroot)
         .mount_read(submission_dir, submission_dir)
         .mount_write(output_dir, output_dir)
-        .cwd(&output_dir)
+        .cwd(output_dir)
+        .kill_process_group(true)
         .disable_ptrace();
 
-      let mut option_builder = match command.user {
+      // 命令行指定的编译用户优先于预设
+      let user = build_user
+        .as_deref()
+        .map_or(command.user.clone(), UserType::parse);
+      let mut option_builder = match user {
         UserType::Nobody => option_builder,
         UserType::Current => option_builder.current_user(),
-        UserType::Root => {
-          unimplemented!()
-        }
+        UserType::Root => option_builder.uid(0).gid(0),
+        UserType::Named(name) => option_builder.user(name)?,
       };
 
       for feat in command.ptrace.iter() {
-        option_builder = option_builder.ptrace(feat.clone())
+        option_builder = option_builder.ptrace(*feat)
       }
       for mount_point in command.mounts.iter() {
         option_builder = option_builder.mount(mount_point.clone())
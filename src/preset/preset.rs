@@ -1,5 +1,5 @@
 use crate::syscall::RestrictedSyscall;
-use crate::utils::mount::MountPoint;
+use crate::utils::mount::{MountOrigin, MountPoint};
 use crate::utils::{MemoryLimitType, TimeLimitType};
 use std::path::PathBuf;
 
@@ -25,11 +25,28 @@ pub(crate) enum UserType {
   Nobody,
   Current,
   Root,
+  /// User in the user database by name
+  Named(String),
+}
+
+impl UserType {
+  /// Parse `nobody`, `current`, `root` or a user name
+  pub(crate) fn parse(text: &str) -> Self {
+    match text.trim() {
+      "nobody" => UserType::Nobody,
+      "current" => UserType::Current,
+      "root" => UserType::Root,
+      name => UserType::Named(name.to_string()),
+    }
+  }
 }
 
 #[derive(Debug, Clone)]
 pub struct ExecuteCommand {
+  /// Path or name of the binary to exec
   pub(crate) program: String,
+  /// Program name shown in reports, which defaults to the program
+  pub(crate) display_name: Option<String>,
   pub(crate) arguments: Vec<String>,
   pub(crate) time_limit: TimeLimitType,
   pub(crate) memory_limit: MemoryLimitType,
@@ -70,6 +87,7 @@ impl ExecuteCommand {
   pub(crate) fn new<PS: Into<String>, AS: Into<String>>(program: PS, arguments: Vec<AS>) -> Self {
     ExecuteCommand {
       program: program.into(),
+      display_name: None,
       arguments: arguments.into_iter().map(|a| a.into()).collect(),
       time_limit: 1000,
       memory_limit: 262144,
@@ -92,6 +110,12 @@ impl ExecuteCommand {
     Self::apply(self.program.as_str(), source, executable)
   }
 
+  /// Display name with placeholders applied
+  pub(crate) fn apply_display_name(&self, source: &str, executable: &str) -> String {
+    let name = self.display_name.as_ref().unwrap_or(&self.program);
+    Self::apply(name.as_str(), source, executable)
+  }
+
   pub(crate) fn apply_arguments(&self, source: &str, executable: &str) -> Vec<String> {
     self
       .arguments
@@ -100,6 +124,11 @@ impl ExecuteCommand {
       .collect()
   }
 
+  pub(crate) fn default_display_name<S: Into<String>>(mut self, name: S) -> Self {
+    self.display_name = Some(name.into());
+    self
+  }
+
   pub(crate) fn default_time_limit(mut self, value: TimeLimitType) -> Self {
     self.time_limit = value;
     self
@@ -135,12 +164,12 @@ impl ExecuteCommand {
     src: impl Into<PathBuf>,
     dst: impl Into<PathBuf>,
   ) -> Self {
-    let point = MountPoint::read(src.into(), dst.into());
+    let point = MountPoint::read(src.into(), dst.into()).with_origin(MountOrigin::Preset);
     self.mounts.push(point);
     self
   }
 
-  pub(crate) fn append_write_mount(mut self) -> Self {
+  pub(crate) fn append_write_mount(self) -> Self {
     self
   }
 
@@ -0,0 +1,150 @@
+//! Newline-delimited JSON protocol over stdio
+//!
+//! Each input line is a request object, for example
+//! `{"id": 1, "program": "./a.out", "arguments": [], "time": 1000, "stdin": "1.in", "stdout": "1.out"}`,
+//! and each output line is the result object of that request.
+//!
+//! A request `{"id": 2, "metrics": true}` answers aggregated metrics of the served runs
+//! in Prometheus text format, as the `metrics` field of the output line.
+
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use log::{error, info};
+use serde_json::{json, Map, Value};
+
+use crate::context::{result_json_fields, CatBoxBuilder, CatBoxResult};
+use crate::error::CatBoxError;
+use crate::metrics::Metrics;
+
+/// Serve requests from input until EOF, writing one result line per request.
+/// The builder factory provides the default options shared by all requests.
+pub fn serve<F: Fn() -> Result<CatBoxBuilder, CatBoxError>>(
+  make_builder: F,
+  input: impl BufRead,
+  output: &mut dyn Write,
+) -> Result<(), CatBoxError> {
+  let mut metrics = Metrics::new();
+  for line in input.lines() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+
+    let request = serde_json::from_str::<Value>(&line).map_err(CatBoxError::from);
+    let id = request
+      .as_ref()
+      .ok()
+      .and_then(|r| r.get("id"))
+      .cloned()
+      .unwrap_or(Value::Null);
+
+    let is_metrics = request
+      .as_ref()
+      .ok()
+      .and_then(|r| r.get("metrics"))
+      .and_then(Value::as_bool)
+      .unwrap_or(false);
+    let response = if is_metrics {
+      json!({ "id": id, "ok": true, "metrics": metrics.export() })
+    } else {
+      match request.and_then(|r| handle(&make_builder, &r)) {
+        Ok(result) => {
+          metrics.record(&result);
+          let mut response = Map::new();
+          response.insert("id".to_string(), id);
+          response.insert("ok".to_string(), json!(true));
+          for (key, value) in result_json_fields(&result) {
+            response.insert(key.to_string(), value);
+          }
+          Value::Object(response)
+        }
+        Err(err) => {
+          metrics.record_error();
+          error!("Serve request fails: {}", err);
+          json!({ "id": id, "ok": false, "error": err.to_string() })
+        }
+      }
+    };
+    writeln!(output, "{}", serde_json::to_string(&response)?)?;
+    output.flush()?;
+  }
+  Ok(())
+}
+
+/// Build and run one request
+fn handle<F: Fn() -> Result<CatBoxBuilder, CatBoxError>>(
+  make_builder: &F,
+  request: &Value,
+) -> Result<CatBoxResult, CatBoxError> {
+  let program = request
+    .get("program")
+    .and_then(Value::as_str)
+    .ok_or(CatBoxError::cli("Request should have program"))?;
+  let arguments = string_list(request, "arguments")?;
+  info!("Serve request: {} {:?}", program, arguments);
+
+  let builder = make_builder()?.parse_env_list(string_list(request, "env")?)?;
+  let mut command = builder.command(program, arguments);
+
+  if let Some(time) = request.get("time").and_then(Value::as_u64) {
+    command = command.time_limit(time);
+  }
+  if let Some(memory) = request.get("memory").and_then(Value::as_u64) {
+    command = command.memory_limit(memory);
+  }
+  if let Some(process) = request.get("process").and_then(Value::as_u64) {
+    command = command.process(process);
+  }
+  if let Some(cwd) = request.get("cwd").and_then(Value::as_str) {
+    command = command.cwd(PathBuf::from(cwd));
+  }
+  let chroot = request.get("chroot").and_then(Value::as_bool);
+  command = command.set_chroot(chroot.unwrap_or(true));
+  if request.get("ptrace").is_some() {
+    command = command.parse_ptrace_presets(Some(string_list(request, "ptrace")?))?;
+  }
+
+  // 服务模式下 stdin 和 stdout 用于协议通信，默认重定向到 /dev/null
+  let catbox = command
+    .stdin(string_field(request, "stdin"))
+    .stdout(string_field(request, "stdout"))
+    .stderr(string_field(request, "stderr"))
+    .parse_mount_read(string_list(request, "read")?)?
+    .parse_mount_write(string_list(request, "write")?)?
+    .build();
+
+  let result = crate::run(catbox.single().unwrap());
+  catbox.close();
+  result
+}
+
+fn string_field(request: &Value, key: &str) -> String {
+  request
+    .get(key)
+    .and_then(Value::as_str)
+    .unwrap_or("/dev/null")
+    .to_string()
+}
+
+fn string_list(request: &Value, key: &str) -> Result<Vec<String>, CatBoxError> {
+  match request.get(key) {
+    None | Some(Value::Null) => Ok(vec![]),
+    Some(value) => value
+      .as_array()
+      .ok_or(CatBoxError::cli(format!(
+        "Request {} should be an array",
+        key
+      )))?
+      .iter()
+      .map(|v| {
+        v.as_str()
+          .map(str::to_string)
+          .ok_or(CatBoxError::cli(format!(
+            "Request {} should be strings",
+            key
+          )))
+      })
+      .collect(),
+  }
+}
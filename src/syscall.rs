@@ -4,13 +4,13 @@ use std::ffi::{c_long, c_ulonglong};
 use std::fmt::{Debug, Formatter};
 
 use nix::libc::{
-  user_regs_struct, SYS_accept, SYS_accept4, SYS_bind, SYS_clone, SYS_clone3,
+  self, user_regs_struct, SYS_accept, SYS_accept4, SYS_bind, SYS_clone, SYS_clone3, SYS_connect,
   SYS_execve, SYS_execveat, SYS_fork, SYS_getpeername, SYS_getsockname, SYS_getsockopt, SYS_listen,
-  SYS_setsockopt, SYS_shutdown, SYS_socketpair, SYS_vfork,
+  SYS_mmap, SYS_setsockopt, SYS_shutdown, SYS_socket, SYS_socketpair, SYS_vfork,
 };
 use nix::unistd::Pid;
 
-use crate::CatBoxError;
+use crate::{CatBoxError, ReportFormat};
 
 type SyscallId = c_ulonglong;
 
@@ -23,6 +23,8 @@ pub enum SyscallPerm {
   FilterFn(fn(pid: &Pid, regs: &user_regs_struct) -> bool),
   /// Allow a few times
   Allow(i32),
+  /// Allow only if the argument at the index (from 0) is not greater than the max value
+  MaxArg(usize, u64),
 }
 
 /// Syscall filter
@@ -30,6 +32,8 @@ pub enum SyscallPerm {
 #[derive(Debug, Clone)]
 pub struct SyscallFilter {
   map: HashMap<SyscallId, SyscallPerm>,
+  /// Configured allowed times, used to reset the filter
+  allowance: HashMap<SyscallId, i32>,
 }
 
 /// Syscall filter preset category
@@ -40,26 +44,138 @@ pub enum RestrictedSyscall {
   Thread,
 }
 
+/// How network is restricted
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetPolicy {
+  /// No restriction besides the ptrace `net` preset
+  Allow,
+  /// Make socket syscalls fail with `EPERM` by a seccomp filter, which is lighter than ptrace and
+  /// works on hosts without net namespaces. The program is not run if the filter fails to install.
+  Seccomp,
+}
+
+/// Socket syscalls denied by [`NetPolicy::Seccomp`]
+pub(crate) const NET_SYSCALLS: [c_long; 7] = [
+  SYS_socket,
+  SYS_socketpair,
+  SYS_connect,
+  SYS_bind,
+  SYS_listen,
+  SYS_accept,
+  SYS_accept4,
+];
+
+/// Syscalls allowed by the default-deny seccomp filter, see
+/// [`CatBoxOptionBuilder::seccomp_default_deny`](crate::CatBoxOptionBuilder::seccomp_default_deny).
+/// They cover file IO, memory, signals, time and the startup of static and dynamic programs, while
+/// network, process creation, mount and other syscalls are not included.
+pub(crate) const DEFAULT_ALLOWED_SYSCALLS: [c_long; 83] = [
+  libc::SYS_read,
+  libc::SYS_write,
+  libc::SYS_readv,
+  libc::SYS_writev,
+  libc::SYS_pread64,
+  libc::SYS_pwrite64,
+  libc::SYS_lseek,
+  libc::SYS_close,
+  libc::SYS_open,
+  libc::SYS_openat,
+  libc::SYS_stat,
+  libc::SYS_fstat,
+  libc::SYS_lstat,
+  libc::SYS_newfstatat,
+  libc::SYS_statx,
+  libc::SYS_access,
+  libc::SYS_faccessat,
+  libc::SYS_faccessat2,
+  libc::SYS_readlink,
+  libc::SYS_readlinkat,
+  libc::SYS_getdents64,
+  libc::SYS_getcwd,
+  libc::SYS_fcntl,
+  libc::SYS_dup,
+  libc::SYS_dup2,
+  libc::SYS_dup3,
+  libc::SYS_pipe,
+  libc::SYS_pipe2,
+  libc::SYS_ioctl,
+  libc::SYS_ftruncate,
+  libc::SYS_fsync,
+  libc::SYS_fdatasync,
+  libc::SYS_mkdir,
+  libc::SYS_mkdirat,
+  libc::SYS_unlink,
+  libc::SYS_unlinkat,
+  libc::SYS_rename,
+  libc::SYS_renameat,
+  libc::SYS_mmap,
+  libc::SYS_munmap,
+  libc::SYS_mprotect,
+  libc::SYS_mremap,
+  libc::SYS_brk,
+  libc::SYS_madvise,
+  libc::SYS_rt_sigaction,
+  libc::SYS_rt_sigprocmask,
+  libc::SYS_rt_sigreturn,
+  libc::SYS_sigaltstack,
+  libc::SYS_tgkill,
+  libc::SYS_execve,
+  libc::SYS_exit,
+  libc::SYS_exit_group,
+  libc::SYS_arch_prctl,
+  libc::SYS_set_tid_address,
+  libc::SYS_set_robust_list,
+  libc::SYS_rseq,
+  libc::SYS_prlimit64,
+  libc::SYS_getrlimit,
+  libc::SYS_getrusage,
+  libc::SYS_getrandom,
+  libc::SYS_clock_gettime,
+  libc::SYS_clock_getres,
+  libc::SYS_clock_nanosleep,
+  libc::SYS_gettimeofday,
+  libc::SYS_time,
+  libc::SYS_times,
+  libc::SYS_nanosleep,
+  libc::SYS_sysinfo,
+  libc::SYS_uname,
+  libc::SYS_getpid,
+  libc::SYS_gettid,
+  libc::SYS_getppid,
+  libc::SYS_getuid,
+  libc::SYS_geteuid,
+  libc::SYS_getgid,
+  libc::SYS_getegid,
+  libc::SYS_sched_yield,
+  libc::SYS_sched_getaffinity,
+  libc::SYS_futex,
+  libc::SYS_poll,
+  libc::SYS_ppoll,
+  libc::SYS_select,
+  libc::SYS_pselect6,
+];
+
+impl NetPolicy {
+  pub fn parse<S: AsRef<str>>(text: S) -> Result<Self, CatBoxError> {
+    match text.as_ref().trim().to_ascii_lowercase().as_str() {
+      "allow" | "none" => Ok(NetPolicy::Allow),
+      "seccomp" | "deny" => Ok(NetPolicy::Seccomp),
+      _ => Err(CatBoxError::cli("Parse net policy string fails")),
+    }
+  }
+}
+
 impl SyscallFilter {
   /// Create an empty syscall filter
   pub fn new() -> Self {
-    let filter = SyscallFilter {
+    SyscallFilter {
       map: HashMap::new(),
-    };
-    filter
-  }
-
-  /// Create a default syscall filter with all the presets open
-  pub fn default() -> Self {
-    let mut filter = Self::new();
-    filter
-      .enable(RestrictedSyscall::Net)
-      .enable(RestrictedSyscall::Process);
-    filter
+      allowance: HashMap::new(),
+    }
   }
 
   /// Enable preset
-  pub fn enable(self: &mut Self, feature: RestrictedSyscall) -> &mut Self {
+  pub fn enable(&mut self, feature: RestrictedSyscall) -> &mut Self {
     match feature {
       RestrictedSyscall::Net => {
         self
@@ -90,6 +206,15 @@ impl SyscallFilter {
     self
   }
 
+  /// Create a syscall filter with these presets enabled
+  pub fn from_presets(presets: &[RestrictedSyscall]) -> Self {
+    let mut filter = Self::new();
+    for preset in presets {
+      filter.enable(*preset);
+    }
+    filter
+  }
+
   /// Try parsing presets string
   pub fn parse_presets(presets: Vec<String>) -> Result<Option<Self>, CatBoxError> {
     let mut filter = Self::new();
@@ -97,7 +222,7 @@ impl SyscallFilter {
       .into_iter()
       .flat_map(|str| str.split(" ").map(str::to_owned).collect::<Vec<_>>())
       .map(|p| p.trim().to_ascii_lowercase())
-      .filter(|p| p.len() > 0)
+      .filter(|p| !p.is_empty())
       .collect::<Vec<String>>();
     for preset in presets {
       match preset.as_str() {
@@ -119,28 +244,62 @@ impl SyscallFilter {
     Ok(Some(filter))
   }
 
-  pub fn forbid(self: &mut Self, id: c_long) -> &mut Self {
+  pub fn forbid(&mut self, id: c_long) -> &mut Self {
     self.map.insert(id as SyscallId, SyscallPerm::forbid());
+    self.allowance.remove(&(id as SyscallId));
     self
   }
 
   pub fn add_fn(
-    self: &mut Self,
+    &mut self,
     id: c_long,
     func: fn(pid: &Pid, regs: &user_regs_struct) -> bool,
   ) -> &mut Self {
     self
       .map
       .insert(id as SyscallId, SyscallPerm::FilterFn(func));
+    self.allowance.remove(&(id as SyscallId));
     self
   }
 
-  pub fn allow(self: &mut Self, id: c_long, count: i32) -> &mut Self {
+  /// Kill the program if it mmaps more than `max_bytes` at once, checked on the length argument.
+  /// It complements `RLIMIT_AS`, which is infinite by default. Note that the mapping may still grow
+  /// by `mremap` or `brk`, and x86_64 has no `mmap2`.
+  pub fn limit_mmap(&mut self, max_bytes: u64) -> &mut Self {
+    self
+      .map
+      .insert(SYS_mmap as SyscallId, SyscallPerm::MaxArg(1, max_bytes));
+    self.allowance.remove(&(SYS_mmap as SyscallId));
+    self
+  }
+
+  pub fn allow(&mut self, id: c_long, count: i32) -> &mut Self {
     self.map.insert(id as SyscallId, SyscallPerm::allow(count));
+    self.allowance.insert(id as SyscallId, count);
+    self
+  }
+
+  /// Allow a syscall `count` more times, if it is only allowed a few times
+  pub fn extend_allowance(&mut self, id: c_long, count: i32) -> &mut Self {
+    let id = id as SyscallId;
+    if let Some(SyscallPerm::Allow(current)) = self.map.get_mut(&id) {
+      *current += count;
+    }
+    if let Some(allowance) = self.allowance.get_mut(&id) {
+      *allowance += count;
+    }
+    self
+  }
+
+  /// Restore the allowed times consumed by [`SyscallFilter::filter`]
+  pub fn reset(&mut self) -> &mut Self {
+    for (id, count) in self.allowance.iter() {
+      self.map.insert(*id, SyscallPerm::allow(*count));
+    }
     self
   }
 
-  pub fn filter(self: &mut Self, pid: &Pid, regs: &user_regs_struct) -> bool {
+  pub fn filter(&mut self, pid: &Pid, regs: &user_regs_struct) -> bool {
     let syscall_id = regs.orig_rax;
     let entry = self.map.entry(syscall_id);
     if let Occupied(mut entry) = entry {
@@ -148,6 +307,7 @@ impl SyscallFilter {
       match perm {
         SyscallPerm::Forbid => false,
         SyscallPerm::FilterFn(func) => func(pid, regs),
+        SyscallPerm::MaxArg(index, max) => syscall_arg(regs, *index) <= *max,
         SyscallPerm::Allow(ref mut count) => {
           if *count == 0 {
             false
@@ -161,6 +321,108 @@ impl SyscallFilter {
       true
     }
   }
+
+  /// Export the effective syscall policy, sorted by syscall id
+  pub fn export(&self, format: ReportFormat) -> String {
+    let mut rules = self.map.iter().collect::<Vec<_>>();
+    rules.sort_by_key(|(id, _)| **id);
+
+    match format {
+      ReportFormat::Human => {
+        let mut text = format!("{:<16}{:<8}{}\n", "Syscall", "Id", "Permission");
+        for (id, perm) in rules {
+          let perm = match perm {
+            SyscallPerm::Forbid => "forbid".to_string(),
+            SyscallPerm::FilterFn(_) => "filter function".to_string(),
+            SyscallPerm::MaxArg(index, max) => format!("arg{} <= {}", index, max),
+            SyscallPerm::Allow(count) => format!("allow {} times", count),
+          };
+          text.push_str(&format!("{:<16}{:<8}{}\n", syscall_name(*id), id, perm));
+        }
+        text
+      }
+      ReportFormat::Json => {
+        let rules = rules
+          .into_iter()
+          .map(|(id, perm)| {
+            let perm = match perm {
+              SyscallPerm::Forbid => "\"perm\": \"forbid\"".to_string(),
+              SyscallPerm::FilterFn(_) => "\"perm\": \"filter_fn\"".to_string(),
+              SyscallPerm::MaxArg(index, max) => {
+                format!(
+                  "\"perm\": \"max_arg\", \"arg\": {}, \"max\": {}",
+                  index, max
+                )
+              }
+              SyscallPerm::Allow(count) => format!("\"perm\": \"allow\", \"count\": {}", count),
+            };
+            format!(
+              "    {{ \"id\": {}, \"name\": \"{}\", {} }}",
+              id,
+              syscall_name(*id),
+              perm
+            )
+          })
+          .collect::<Vec<String>>();
+        if rules.is_empty() {
+          "{\n  \"syscalls\": []\n}\n".to_string()
+        } else {
+          format!("{{\n  \"syscalls\": [\n{}\n  ]\n}}\n", rules.join(",\n"))
+        }
+      }
+    }
+  }
+}
+
+/// Argument of the syscall by index (from 0), following the x86_64 syscall convention
+fn syscall_arg(regs: &user_regs_struct, index: usize) -> u64 {
+  match index {
+    0 => regs.rdi,
+    1 => regs.rsi,
+    2 => regs.rdx,
+    3 => regs.r10,
+    4 => regs.r8,
+    _ => regs.r9,
+  }
+}
+
+/// Names of the syscalls used in presets
+const SYSCALL_NAMES: [(c_long, &str); 17] = [
+  (SYS_accept, "accept"),
+  (SYS_accept4, "accept4"),
+  (SYS_bind, "bind"),
+  (SYS_clone, "clone"),
+  (SYS_clone3, "clone3"),
+  (SYS_execve, "execve"),
+  (SYS_execveat, "execveat"),
+  (SYS_fork, "fork"),
+  (SYS_getpeername, "getpeername"),
+  (SYS_getsockname, "getsockname"),
+  (SYS_getsockopt, "getsockopt"),
+  (SYS_listen, "listen"),
+  (SYS_mmap, "mmap"),
+  (SYS_setsockopt, "setsockopt"),
+  (SYS_shutdown, "shutdown"),
+  (SYS_socketpair, "socketpair"),
+  (SYS_vfork, "vfork"),
+];
+
+fn syscall_name(id: SyscallId) -> String {
+  SYSCALL_NAMES
+    .iter()
+    .find(|(sys, _)| *sys as SyscallId == id)
+    .map_or_else(|| format!("#{}", id), |(_, name)| name.to_string())
+}
+
+impl Default for SyscallFilter {
+  /// Create a default syscall filter with all the presets open
+  fn default() -> Self {
+    let mut filter = Self::new();
+    filter
+      .enable(RestrictedSyscall::Net)
+      .enable(RestrictedSyscall::Process);
+    filter
+  }
 }
 
 impl SyscallPerm {
@@ -179,6 +441,7 @@ impl Debug for SyscallPerm {
       SyscallPerm::Forbid => f.debug_struct("Forbid").finish(),
       SyscallPerm::FilterFn(_) => f.debug_struct("FilterFn").field("func", &"[func]").finish(),
       SyscallPerm::Allow(count) => f.debug_tuple("Allow").field(count).finish(),
+      SyscallPerm::MaxArg(index, max) => f.debug_tuple("MaxArg").field(index).field(max).finish(),
     }
   }
 }
@@ -0,0 +1,176 @@
+use std::fs::read_to_string;
+
+use log::{error, info};
+use nix::errno::Errno;
+use nix::libc::{self, c_int};
+
+use crate::CatBoxError;
+
+/// Capability names indexed by their numbers, see [capabilities(7)](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+const CAPABILITIES: [&str; 41] = [
+  "CAP_CHOWN",
+  "CAP_DAC_OVERRIDE",
+  "CAP_DAC_READ_SEARCH",
+  "CAP_FOWNER",
+  "CAP_FSETID",
+  "CAP_KILL",
+  "CAP_SETGID",
+  "CAP_SETUID",
+  "CAP_SETPCAP",
+  "CAP_LINUX_IMMUTABLE",
+  "CAP_NET_BIND_SERVICE",
+  "CAP_NET_BROADCAST",
+  "CAP_NET_ADMIN",
+  "CAP_NET_RAW",
+  "CAP_IPC_LOCK",
+  "CAP_IPC_OWNER",
+  "CAP_SYS_MODULE",
+  "CAP_SYS_RAWIO",
+  "CAP_SYS_CHROOT",
+  "CAP_SYS_PTRACE",
+  "CAP_SYS_PACCT",
+  "CAP_SYS_ADMIN",
+  "CAP_SYS_BOOT",
+  "CAP_SYS_NICE",
+  "CAP_SYS_RESOURCE",
+  "CAP_SYS_TIME",
+  "CAP_SYS_TTY_CONFIG",
+  "CAP_MKNOD",
+  "CAP_LEASE",
+  "CAP_AUDIT_WRITE",
+  "CAP_AUDIT_CONTROL",
+  "CAP_SETFCAP",
+  "CAP_MAC_OVERRIDE",
+  "CAP_MAC_ADMIN",
+  "CAP_SYSLOG",
+  "CAP_WAKE_ALARM",
+  "CAP_BLOCK_SUSPEND",
+  "CAP_AUDIT_READ",
+  "CAP_PERFMON",
+  "CAP_BPF",
+  "CAP_CHECKPOINT_RESTORE",
+];
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+#[repr(C)]
+struct CapUserHeader {
+  version: u32,
+  pid: c_int,
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+struct CapUserData {
+  effective: u32,
+  permitted: u32,
+  inheritable: u32,
+}
+
+/// Capabilities dropped before exec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DropCapabilities {
+  /// Drop all the capabilities
+  All,
+  /// Drop these capabilities by number, e.g. `CAP_SYS_ADMIN` is 21
+  List(Vec<u32>),
+}
+
+impl DropCapabilities {
+  /// Parse `all` or capability names, e.g. `CAP_CHOWN` or `chown`
+  pub fn parse<S: AsRef<str>>(names: &[S]) -> Result<Self, CatBoxError> {
+    let mut list = vec![];
+    for name in names {
+      let name = name.as_ref().trim().to_ascii_uppercase();
+      if name == "ALL" {
+        return Ok(DropCapabilities::All);
+      }
+      let name = if name.starts_with("CAP_") {
+        name
+      } else {
+        format!("CAP_{}", name)
+      };
+      match CAPABILITIES.iter().position(|cap| *cap == name) {
+        Some(id) => list.push(id as u32),
+        None => return Err(CatBoxError::cli(format!("Unknown capability {}", name))),
+      }
+    }
+    Ok(DropCapabilities::List(list))
+  }
+
+  /// Numbers of the dropped capabilities
+  pub fn ids(&self) -> Vec<u32> {
+    match self {
+      DropCapabilities::All => (0..=last_capability()).collect(),
+      DropCapabilities::List(list) => list.clone(),
+    }
+  }
+}
+
+/// The highest capability number supported by the kernel
+fn last_capability() -> u32 {
+  read_to_string("/proc/sys/kernel/cap_last_cap")
+    .ok()
+    .and_then(|text| text.trim().parse().ok())
+    .unwrap_or(CAPABILITIES.len() as u32 - 1)
+}
+
+/// Whether the capability is in the effective set of current process
+pub(crate) fn has_capability(name: &str) -> bool {
+  let id = match CAPABILITIES.iter().position(|cap| *cap == name) {
+    Some(id) => id as u32,
+    None => return false,
+  };
+  let mut header = CapUserHeader {
+    version: LINUX_CAPABILITY_VERSION_3,
+    pid: 0,
+  };
+  let mut data = [CapUserData::default(); 2];
+  if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } == -1 {
+    error!("Get capabilities fails: {}", Errno::last());
+    return false;
+  }
+  data
+    .get((id / 32) as usize)
+    .is_some_and(|data| data.effective & (1 << (id % 32)) != 0)
+}
+
+/// Drop capabilities from the bounding set, which requires `CAP_SETPCAP`, so call it before setuid
+pub(crate) fn drop_bounding_capabilities(ids: &[u32]) {
+  for id in ids {
+    let r = unsafe { libc::prctl(libc::PR_CAPBSET_DROP, *id as libc::c_ulong, 0, 0, 0) };
+    if r == -1 {
+      match Errno::last() {
+        // 非 root 用户没有 CAP_SETPCAP，无需处理
+        Errno::EPERM => info!("Skip dropping capabilities from bounding set without CAP_SETPCAP"),
+        err => error!("Drop capability {} from bounding set fails: {}", id, err),
+      }
+      return;
+    }
+  }
+  info!("Drop capabilities {:?} from bounding set", ids);
+}
+
+/// Drop capabilities from the effective, permitted and inheritable sets of current process
+pub(crate) fn drop_process_capabilities(ids: &[u32]) {
+  let mut header = CapUserHeader {
+    version: LINUX_CAPABILITY_VERSION_3,
+    pid: 0,
+  };
+  let mut data = [CapUserData::default(); 2];
+  if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } == -1 {
+    error!("Get capabilities fails: {}", Errno::last());
+    return;
+  }
+  for id in ids {
+    let (index, bit) = ((*id / 32) as usize, 1_u32 << (*id % 32));
+    if let Some(data) = data.get_mut(index) {
+      data.effective &= !bit;
+      data.permitted &= !bit;
+      data.inheritable &= !bit;
+    }
+  }
+  if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } == -1 {
+    error!("Set capabilities fails: {}", Errno::last());
+  }
+}
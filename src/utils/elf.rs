@@ -0,0 +1,229 @@
+use std::fs::{read, File};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+use crate::CatBoxError;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+
+/// Dynamic linking information read from the program headers and the dynamic section of an ELF file
+#[derive(Debug, Default)]
+pub struct ElfDynamic {
+  /// ELF class (1 for 32-bit, 2 for 64-bit) and machine, shared libraries must match them
+  pub class: u8,
+  pub machine: u16,
+  /// Path of the dynamic loader in `PT_INTERP`
+  pub interpreter: Option<String>,
+  /// Sonames in `DT_NEEDED`
+  pub needed: Vec<String>,
+  /// Search paths in `DT_RPATH` and `DT_RUNPATH`
+  pub rpath: Option<String>,
+  pub runpath: Option<String>,
+}
+
+/// Field reader which respects the class and byte order of the ELF file
+struct ElfReader {
+  is_64: bool,
+  big_endian: bool,
+}
+
+impl ElfReader {
+  fn bytes(buf: &[u8], offset: usize, size: usize) -> Result<&[u8], CatBoxError> {
+    offset
+      .checked_add(size)
+      .and_then(|end| buf.get(offset..end))
+      .ok_or_else(|| CatBoxError::Fs("Malformed ELF file".to_string()))
+  }
+
+  fn uint(&self, buf: &[u8], offset: usize, size: usize) -> Result<u64, CatBoxError> {
+    let bytes = Self::bytes(buf, offset, size)?;
+    let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+    if self.big_endian {
+      Ok(bytes.iter().fold(0, fold))
+    } else {
+      Ok(bytes.iter().rev().fold(0, fold))
+    }
+  }
+
+  fn u16(&self, buf: &[u8], offset: usize) -> Result<u16, CatBoxError> {
+    Ok(self.uint(buf, offset, 2)? as u16)
+  }
+
+  fn u32(&self, buf: &[u8], offset: usize) -> Result<u32, CatBoxError> {
+    Ok(self.uint(buf, offset, 4)? as u32)
+  }
+
+  /// Address sized field, i.e. Elf32_Addr / Elf64_Addr
+  fn word(&self, buf: &[u8], offset: usize) -> Result<u64, CatBoxError> {
+    self.uint(buf, offset, if self.is_64 { 8 } else { 4 })
+  }
+}
+
+/// Read `size` bytes at `offset`, the size is bounded by the file length
+fn read_at(file: &File, offset: u64, size: u64) -> Result<Vec<u8>, CatBoxError> {
+  let len = file.metadata()?.len();
+  if offset.checked_add(size).is_none_or(|end| end > len) {
+    return Err(CatBoxError::Fs("Malformed ELF file".to_string()));
+  }
+  let mut buf = vec![0; size as usize];
+  file.read_exact_at(&mut buf, offset)?;
+  Ok(buf)
+}
+
+/// Read the NUL terminated string at `offset`
+fn c_string(buf: &[u8], offset: usize) -> Result<String, CatBoxError> {
+  let bytes = buf
+    .get(offset..)
+    .ok_or_else(|| CatBoxError::Fs("Malformed ELF file".to_string()))?;
+  let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+  Ok(String::from_utf8_lossy(&bytes[..end]).to_string())
+}
+
+/// Read the dynamic linking information of an ELF file, without loading or running it.
+/// Returns `None` when the file is not an ELF file.
+pub fn read_dynamic(path: &Path) -> Result<Option<ElfDynamic>, CatBoxError> {
+  let file = File::open(path)?;
+  let mut header = [0_u8; 64];
+  let size = file.read_at(&mut header, 0)?;
+  if size < 52 || &header[..4] != b"\x7fELF" {
+    return Ok(None);
+  }
+  let reader = ElfReader {
+    is_64: header[4] == 2,
+    big_endian: header[5] == 2,
+  };
+  let header = &header[..size];
+
+  let mut dynamic = ElfDynamic {
+    class: header[4],
+    machine: reader.u16(header, 18)?,
+    ..Default::default()
+  };
+  let (phoff, phentsize, phnum) = if reader.is_64 {
+    (
+      reader.word(header, 32)?,
+      reader.u16(header, 54)?,
+      reader.u16(header, 56)?,
+    )
+  } else {
+    (
+      reader.word(header, 28)?,
+      reader.u16(header, 42)?,
+      reader.u16(header, 44)?,
+    )
+  };
+  let program_headers = read_at(&file, phoff, phentsize as u64 * phnum as u64)?;
+
+  // (p_offset, p_vaddr, p_filesz)
+  let mut loads = vec![];
+  let mut dynamic_segment = None;
+  for index in 0..phnum as usize {
+    let base = index * phentsize as usize;
+    let p_type = reader.u32(&program_headers, base)?;
+    let segment = if reader.is_64 {
+      (
+        reader.word(&program_headers, base + 8)?,
+        reader.word(&program_headers, base + 16)?,
+        reader.word(&program_headers, base + 32)?,
+      )
+    } else {
+      (
+        reader.word(&program_headers, base + 4)?,
+        reader.word(&program_headers, base + 8)?,
+        reader.word(&program_headers, base + 16)?,
+      )
+    };
+    match p_type {
+      PT_LOAD => loads.push(segment),
+      PT_DYNAMIC => dynamic_segment = Some(segment),
+      PT_INTERP => {
+        let (offset, _, size) = segment;
+        dynamic.interpreter = Some(c_string(&read_at(&file, offset, size)?, 0)?);
+      }
+      _ => {}
+    }
+  }
+
+  // 静态链接的程序没有 dynamic 段
+  let Some((offset, _, size)) = dynamic_segment else {
+    return Ok(Some(dynamic));
+  };
+  let entries = read_at(&file, offset, size)?;
+  let entry_size = if reader.is_64 { 16 } else { 8 };
+  let mut tags = vec![];
+  for base in (0..entries.len() / entry_size).map(|index| index * entry_size) {
+    let tag = reader.word(&entries, base)?;
+    if tag == DT_NULL {
+      break;
+    }
+    tags.push((tag, reader.word(&entries, base + entry_size / 2)?));
+  }
+
+  // DT_STRTAB 为虚拟地址，通过 PT_LOAD 段换算为文件偏移
+  let find_tag = |tag: u64| tags.iter().find(|(t, _)| *t == tag).map(|(_, v)| *v);
+  let (Some(strtab), Some(strsz)) = (find_tag(DT_STRTAB), find_tag(DT_STRSZ)) else {
+    return Ok(Some(dynamic));
+  };
+  let strtab = loads
+    .iter()
+    .find(|(_, vaddr, filesz)| *vaddr <= strtab && strtab - vaddr < *filesz)
+    .map(|(offset, vaddr, _)| offset + (strtab - vaddr))
+    .ok_or_else(|| CatBoxError::Fs("Malformed ELF file".to_string()))?;
+  let strings = read_at(&file, strtab, strsz)?;
+  for (tag, value) in tags.iter() {
+    match *tag {
+      DT_NEEDED => dynamic.needed.push(c_string(&strings, *value as usize)?),
+      DT_RPATH => dynamic.rpath = Some(c_string(&strings, *value as usize)?),
+      DT_RUNPATH => dynamic.runpath = Some(c_string(&strings, *value as usize)?),
+      _ => {}
+    }
+  }
+
+  Ok(Some(dynamic))
+}
+
+/// Read the (soname, path) entries of the dynamic linker cache, e.g. `/etc/ld.so.cache`,
+/// in the new format (`glibc-ld.so.cache1.1`), which may follow the old format (`ld.so-1.7.0`).
+pub fn read_ld_cache(path: &Path) -> Result<Vec<(String, String)>, CatBoxError> {
+  const OLD_MAGIC: &[u8] = b"ld.so-1.7.0";
+  const NEW_MAGIC: &[u8] = b"glibc-ld.so.cache1.1";
+  let bytes = read(path)?;
+  // 缓存文件由 ldconfig 在本机生成，使用本机字节序
+  let reader = ElfReader {
+    is_64: true,
+    big_endian: cfg!(target_endian = "big"),
+  };
+
+  let mut start = 0;
+  if bytes.starts_with(OLD_MAGIC) {
+    // 旧格式的头部为 magic 和 nlibs，每项 12 字节，新格式按 8 字节对齐
+    let nlibs = reader.u32(&bytes, 12)? as usize;
+    start = (16 + nlibs * 12 + 7) & !7;
+  }
+  let cache = bytes.get(start..).unwrap_or_default();
+  if !cache.starts_with(NEW_MAGIC) {
+    return Err(CatBoxError::Fs(format!(
+      "Unknown format of {}",
+      path.to_string_lossy()
+    )));
+  }
+
+  // 头部 48 字节，每项 24 字节 (flags, key, value, osversion, hwcap)，字符串偏移相对于新格式的头部
+  let nlibs = reader.u32(cache, 20)? as usize;
+  let mut entries = vec![];
+  for base in (0..nlibs).map(|index| 48 + index * 24) {
+    let key = reader.u32(cache, base + 4)? as usize;
+    let value = reader.u32(cache, base + 8)? as usize;
+    entries.push((c_string(cache, key)?, c_string(cache, value)?));
+  }
+  Ok(entries)
+}
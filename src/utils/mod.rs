@@ -1,16 +1,28 @@
+use std::cell::RefCell;
 use std::env;
 use std::ffi::CString;
+use std::fs;
+use std::os::unix::io::{FromRawFd, IntoRawFd, OwnedFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use flexi_logger::DeferredNow;
 use log::{error, info, Record};
+use nix::fcntl::{open, OFlag};
 use nix::libc::{gid_t, uid_t};
+use nix::pty::{grantpt, posix_openpt, ptsname_r, unlockpt};
+use nix::sys::stat::Mode;
 
-pub use pipe::{CatBoxPipe, CatBoxReadPipe, CatBoxWritePipe};
+pub use pipe::CatBoxPipe;
 
 use crate::CatBoxError;
 
+pub mod capability;
+pub mod elf;
 pub mod mount;
 pub mod pipe;
+pub mod probe;
+pub mod seccomp;
 
 pub type TimeLimitType = u64;
 
@@ -20,8 +32,55 @@ pub type UidType = uid_t;
 
 pub type GidType = gid_t;
 
+static RUN_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+  /// Run id and label of the running command in current thread
+  static RUN_CONTEXT: RefCell<Option<(u64, String)>> = const { RefCell::new(None) };
+}
+
+/// Attach a new run id and the label to the log lines of current thread until it is dropped
+pub(crate) struct RunLogContext;
+
+impl RunLogContext {
+  pub(crate) fn enter(label: &str) -> Self {
+    let id = RUN_ID.fetch_add(1, Ordering::Relaxed);
+    RUN_CONTEXT.with(|context| *context.borrow_mut() = Some((id, label.to_string())));
+    RunLogContext
+  }
+}
+
+impl Drop for RunLogContext {
+  fn drop(&mut self) {
+    RUN_CONTEXT.with(|context| *context.borrow_mut() = None);
+  }
+}
+
+/// Remove ANSI escape sequences, e.g. `\x1b[1m`
+pub(crate) fn strip_ansi(text: &str) -> String {
+  let mut result = String::with_capacity(text.len());
+  let mut chars = text.chars();
+  while let Some(c) = chars.next() {
+    if c == '\x1b' {
+      // 跳过 CSI 序列直到结束字母
+      if chars.next() == Some('[') {
+        for c in chars.by_ref() {
+          if c.is_ascii_alphabetic() {
+            break;
+          }
+        }
+      }
+    } else {
+      result.push(c);
+    }
+  }
+  result
+}
+
 /// A logline-formatter that produces log lines like <br>
-/// ```[datetime: INFO] Task successfully read from conf.json```
+/// ```[datetime: INFO] Task successfully read from conf.json``` <br>
+/// or with the run id and label inside [`crate::run`] <br>
+/// ```[datetime: INFO] [run#1 catbox1] Child process is running```
 #[allow(unused)]
 pub fn default_format(
   w: &mut dyn std::io::Write,
@@ -30,31 +89,72 @@ pub fn default_format(
 ) -> Result<(), std::io::Error> {
   write!(
     w,
-    "[{}: {:5}] {}",
+    "[{}: {:5}] ",
     now.format("%Y-%m-%d %H:%M:%S"),
     record.level(),
-    record.args()
-  )
+  )?;
+  RUN_CONTEXT.with(|context| match &*context.borrow() {
+    Some((id, label)) => write!(w, "[run#{} {}] ", id, label),
+    None => Ok(()),
+  })?;
+  write!(w, "{}", record.args())
 }
 
-pub(crate) fn into_c_string(string: &String) -> CString {
-  let string = string.as_str();
-  CString::new(string).expect("Convert &str to CString should work")
+/// 打开伪终端的 (master, slave)，两端都设置 CLOEXEC，避免泄漏到之后 fork 的子进程
+pub(crate) fn open_pty() -> Result<(OwnedFd, OwnedFd), CatBoxError> {
+  let flags = OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_CLOEXEC;
+  let master = posix_openpt(flags)?;
+  grantpt(&master)?;
+  unlockpt(&master)?;
+  let slave_path = ptsname_r(&master)?;
+  let slave = open(slave_path.as_str(), flags, Mode::empty())?;
+  let master = unsafe { OwnedFd::from_raw_fd(master.into_raw_fd()) };
+  let slave = unsafe { OwnedFd::from_raw_fd(slave) };
+  Ok((master, slave))
+}
+
+pub(crate) fn into_c_string(string: &str) -> Result<CString, CatBoxError> {
+  CString::new(string).map_err(|_| CatBoxError::cli(format!("{:?} contains NUL byte", string)))
+}
+
+/// Read `KEY=VALUE` (or `KEY` for the value from host) lines of an env file, ignoring blank lines
+/// and `#` comments. Values quoted by `"` or `'` are unquoted.
+pub(crate) fn read_env_file(path: &Path) -> Result<Vec<String>, CatBoxError> {
+  let text = fs::read_to_string(path)?;
+  let mut list = vec![];
+  for line in text.lines() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let line = match line.split_once('=') {
+      Some((key, value)) => {
+        let value = value.trim();
+        let unquoted = ['"', '\'']
+          .iter()
+          .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+          .filter(|_| value.len() >= 2);
+        format!("{}={}", key.trim(), unquoted.unwrap_or(value))
+      }
+      None => line.to_string(),
+    };
+    list.push(line);
+  }
+  Ok(list)
 }
 
 pub(crate) fn parse_env(text: String) -> Result<(String, String), CatBoxError> {
-  let arr = text.split("=").collect::<Vec<&str>>();
-  if arr.len() == 2 {
-    let key = arr.get(0).unwrap();
-    let value = arr.get(1).unwrap();
-    Ok((key.to_string(), value.to_string()))
-  } else if arr.len() == 1 {
-    let key = arr.get(0).unwrap();
-    let value = env::var(key).unwrap_or("".to_string());
-    info!("Read environment variable {} = {}", key, value);
-    Ok((key.to_string(), value.to_string()))
-  } else {
-    error!("Wrong environment variable string ({}) format", &text);
-    Err(CatBoxError::cli("Wrong environment variable string format"))
+  // 只按第一个 = 分割，值中可以包含 =
+  match text.split_once('=') {
+    Some(("", _)) => {
+      error!("Wrong environment variable string ({}) format", &text);
+      Err(CatBoxError::cli("Wrong environment variable string format"))
+    }
+    Some((key, value)) => Ok((key.to_string(), value.to_string())),
+    None => {
+      let value = env::var(&text).unwrap_or("".to_string());
+      info!("Read environment variable {} = {}", text, value);
+      Ok((text, value))
+    }
   }
 }
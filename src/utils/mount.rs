@@ -1,16 +1,37 @@
-use std::fs::canonicalize;
-use std::path::PathBuf;
+use std::collections::{HashSet, VecDeque};
+use std::fs::{canonicalize, read, read_dir, read_link, read_to_string};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::Duration;
 
-use log::error;
+use log::{debug, error, info, warn};
+use nix::errno::Errno;
+use path_absolutize::Absolutize;
 
+use crate::utils::elf::{read_dynamic, read_ld_cache};
 use crate::CatBoxError;
 
+/// Where the mount point comes from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MountOrigin {
+  /// Default system directories, see [`MountPoint::defaults`]
+  Default,
+  /// Added by user through builder methods or CLI arguments
+  User,
+  /// Added by language presets
+  Preset,
+}
+
 /// Mount point
 #[derive(Debug, Clone)]
 pub struct MountPoint {
   write: bool,
   src: PathBuf,
   dst: PathBuf,
+  origin: MountOrigin,
+  /// Expected [`hash_tree`] of the source, verified before running
+  expected_hash: Option<String>,
 }
 
 impl MountPoint {
@@ -23,6 +44,9 @@ impl MountPoint {
       Self::read(PathBuf::from("/lib"), PathBuf::from("/lib")),
       Self::read(PathBuf::from("/lib64"), PathBuf::from("/lib64")),
     ]
+    .into_iter()
+    .map(|p| p.with_origin(MountOrigin::Default))
+    .collect()
   }
 
   fn canonicalize<PS: Into<PathBuf>>(path: PS) -> Result<PathBuf, String> {
@@ -30,26 +54,79 @@ impl MountPoint {
     if path.is_absolute() {
       Ok(path)
     } else {
-      canonicalize(path).or_else(|e| Err(e.to_string()))
+      canonicalize(path).map_err(|e| e.to_string())
+    }
+  }
+
+  /// Split text by unescaped `:`, and `\:` is unescaped to a literal `:`
+  fn split_colon(text: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+      match c {
+        '\\' if chars.peek() == Some(&':') => {
+          current.push(':');
+          chars.next();
+        }
+        ':' => parts.push(std::mem::take(&mut current)),
+        _ => current.push(c),
+      }
+    }
+    parts.push(current);
+    parts
+  }
+
+  /// Parse key-value format like `src=/a,dst=/b`
+  fn parse_key_value(write: bool, text: &str) -> Result<Self, CatBoxError> {
+    let mut src = None;
+    let mut dst = None;
+    for pair in text.split(',').filter(|p| !p.is_empty()) {
+      match pair.split_once('=') {
+        Some(("src", value)) | Some(("source", value)) => src = Some(value),
+        Some(("dst", value)) | Some(("destination", value)) => dst = Some(value),
+        _ => {
+          error!("Parse mount input string ({}) fails", text);
+          return Err(CatBoxError::cli("Wrong mount string format"));
+        }
+      }
     }
+    let src = src.ok_or(CatBoxError::cli("Mount string should contain src"))?;
+    let dst = dst.unwrap_or(src);
+    Ok(MountPoint {
+      write,
+      src: Self::canonicalize(src)?,
+      dst: Self::canonicalize(dst)?,
+      origin: MountOrigin::User,
+      expected_hash: None,
+    })
   }
 
+  /// Parse mount string, support `src=SRC,dst=DST` and legacy `SRC:DST` (use `\:` for a literal colon)
   fn parse(write: bool, text: String) -> Result<Self, CatBoxError> {
-    let arr = text.split(":").collect::<Vec<&str>>();
+    if text.starts_with("src=") || text.starts_with("source=") {
+      return Self::parse_key_value(write, &text);
+    }
+
+    let arr = Self::split_colon(&text);
     if arr.len() == 1 {
-      let p = arr.get(0).unwrap();
+      let p = arr.first().unwrap();
       Ok(MountPoint {
         write,
         src: Self::canonicalize(p)?,
         dst: Self::canonicalize(p)?,
+        origin: MountOrigin::User,
+        expected_hash: None,
       })
     } else if arr.len() == 2 {
-      let src = arr.get(0).unwrap();
+      let src = arr.first().unwrap();
       let dst = arr.get(1).unwrap();
       Ok(MountPoint {
         write,
-        src: Self::canonicalize(*src)?,
-        dst: Self::canonicalize(*dst)?,
+        src: Self::canonicalize(src)?,
+        dst: Self::canonicalize(dst)?,
+        origin: MountOrigin::User,
+        expected_hash: None,
       })
     } else {
       error!("Parse mount input string ({}) fails", &text);
@@ -70,6 +147,8 @@ impl MountPoint {
       write: false,
       src: Self::canonicalize(src).unwrap(),
       dst: Self::canonicalize(dst).unwrap(),
+      origin: MountOrigin::User,
+      expected_hash: None,
     }
   }
 
@@ -78,7 +157,38 @@ impl MountPoint {
       write: true,
       src: Self::canonicalize(src).unwrap(),
       dst: Self::canonicalize(dst).unwrap(),
+      origin: MountOrigin::User,
+      expected_hash: None,
+    }
+  }
+
+  /// Set where the mount point comes from
+  pub fn with_origin(mut self, origin: MountOrigin) -> Self {
+    self.origin = origin;
+    self
+  }
+
+  /// Verify the source against the expected [`hash_tree`] before running
+  pub fn with_expected_hash<S: Into<String>>(mut self, hash: S) -> Self {
+    self.expected_hash = Some(hash.into());
+    self
+  }
+
+  /// Check whether the source still matches the expected hash, skipped if there is no expected hash
+  pub fn verify(&self) -> Result<(), CatBoxError> {
+    if let Some(expected) = &self.expected_hash {
+      let hash = hash_tree(&self.src)?;
+      if !hash.eq_ignore_ascii_case(expected) {
+        return Err(CatBoxError::Fs(format!(
+          "Mount source {} has changed: expected hash {}, found {}",
+          self.src.to_string_lossy(),
+          expected,
+          hash
+        )));
+      }
+      info!("Verify mount source {} ok", self.src.to_string_lossy());
     }
+    Ok(())
   }
 
   pub fn read_only(&self) -> bool {
@@ -92,4 +202,214 @@ impl MountPoint {
   pub fn dst(&self) -> &PathBuf {
     &self.dst
   }
+
+  pub fn origin(&self) -> MountOrigin {
+    self.origin
+  }
+}
+
+/// Hash a directory tree (or a single file) by 64-bit FNV-1a in hex, covering the relative paths,
+/// file contents and symlink targets in sorted order. Permissions and timestamps are ignored.
+pub fn hash_tree(path: &Path) -> Result<String, CatBoxError> {
+  let mut hash = FNV_OFFSET_BASIS;
+  hash_entry(path, Path::new(""), &mut hash)?;
+  Ok(format!("{:016x}", hash))
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a(hash: &mut u64, bytes: &[u8]) {
+  for byte in bytes {
+    *hash ^= *byte as u64;
+    *hash = hash.wrapping_mul(FNV_PRIME);
+  }
+}
+
+fn hash_entry(path: &Path, relative: &Path, hash: &mut u64) -> Result<(), CatBoxError> {
+  let metadata = path.symlink_metadata()?;
+  fnv1a(hash, relative.as_os_str().as_bytes());
+  fnv1a(hash, &[0]);
+  if metadata.is_symlink() {
+    fnv1a(hash, b"l");
+    fnv1a(hash, read_link(path)?.as_os_str().as_bytes());
+  } else if metadata.is_dir() {
+    fnv1a(hash, b"d");
+    let mut entries = read_dir(path)?
+      .map(|entry| entry.map(|entry| entry.file_name()))
+      .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for name in entries {
+      hash_entry(&path.join(&name), &relative.join(&name), hash)?;
+    }
+  } else {
+    fnv1a(hash, b"f");
+    fnv1a(hash, &read(path)?);
+  }
+  fnv1a(hash, &[0]);
+  Ok(())
+}
+
+/// Default search directories of the dynamic loader after the cache
+const DEFAULT_LIBRARY_DIRS: [&str; 4] = ["/lib64", "/usr/lib64", "/lib", "/usr/lib"];
+
+/// Resolve the shared library dependencies (including the dynamic loader) of an executable.
+/// The `PT_INTERP` and `DT_NEEDED` entries are read from the ELF file directly (and recursively for
+/// the libraries), then resolved against `DT_RPATH` / `DT_RUNPATH`, `/etc/ld.so.cache` and the
+/// default library directories. The executable is never run. Static executables have no dependency.
+pub fn resolve_dependencies(executable: &Path) -> Result<Vec<PathBuf>, CatBoxError> {
+  let dynamic = match read_dynamic(executable)? {
+    Some(dynamic) if dynamic.interpreter.is_some() || !dynamic.needed.is_empty() => dynamic,
+    _ => {
+      info!("{} is a static executable", executable.to_string_lossy());
+      return Ok(vec![]);
+    }
+  };
+  let cache = match read_ld_cache(Path::new("/etc/ld.so.cache")) {
+    Ok(cache) => cache,
+    Err(err) => {
+      warn!("Read /etc/ld.so.cache fails: {}", err);
+      vec![]
+    }
+  };
+
+  let mut deps = vec![];
+  let mut resolved = HashSet::new();
+  if let Some(interpreter) = &dynamic.interpreter {
+    let interpreter = PathBuf::from(interpreter);
+    if !interpreter.is_file() {
+      return Err(CatBoxError::Fs(format!(
+        "Dependency is not found: {}",
+        interpreter.to_string_lossy()
+      )));
+    }
+    // libc 依赖的动态链接器即为已加载的 PT_INTERP
+    if let Some(name) = interpreter.file_name() {
+      resolved.insert(name.to_string_lossy().to_string());
+    }
+    deps.push(interpreter);
+  }
+
+  let (class, machine) = (dynamic.class, dynamic.machine);
+  let mut queue = VecDeque::from([(executable.to_path_buf(), dynamic)]);
+  while let Some((object, dynamic)) = queue.pop_front() {
+    let origin = object.parent().unwrap_or(Path::new("/"));
+    // 与 ld.so 一致：存在 DT_RUNPATH 时忽略 DT_RPATH
+    let search_path = match (&dynamic.runpath, &dynamic.rpath) {
+      (Some(runpath), _) => runpath.as_str(),
+      (None, Some(rpath)) => rpath.as_str(),
+      (None, None) => "",
+    };
+    let search_dirs = search_path
+      .split(':')
+      .filter(|dir| !dir.is_empty())
+      .map(|dir| {
+        dir
+          .replace("${ORIGIN}", &origin.to_string_lossy())
+          .replace("$ORIGIN", &origin.to_string_lossy())
+      })
+      // 不支持 $LIB 和 $PLATFORM 等其它替换
+      .filter(|dir| !dir.contains('$'))
+      .collect::<Vec<String>>();
+
+    for name in dynamic.needed {
+      if !resolved.insert(name.clone()) {
+        continue;
+      }
+      let candidates = if name.contains('/') {
+        vec![PathBuf::from(&name)]
+      } else {
+        search_dirs
+          .iter()
+          .map(|dir| Path::new(dir).join(&name))
+          .chain(
+            cache
+              .iter()
+              .filter(|(soname, _)| soname == &name)
+              .map(|(_, path)| PathBuf::from(path)),
+          )
+          .chain(
+            DEFAULT_LIBRARY_DIRS
+              .iter()
+              .map(|dir| Path::new(dir).join(&name)),
+          )
+          .collect()
+      };
+      // 只接受与可执行文件架构相同的 ELF 文件，避免挂载其它文件
+      let found = candidates.into_iter().find_map(|path| {
+        let dynamic = read_dynamic(&path).ok()??;
+        (dynamic.class == class && dynamic.machine == machine).then_some((path, dynamic))
+      });
+      let Some((path, dynamic)) = found else {
+        return Err(CatBoxError::Fs(format!(
+          "Dependency is not found: {}",
+          name
+        )));
+      };
+      debug!("Resolve dependency {} -> {}", name, path.to_string_lossy());
+      deps.push(path.clone());
+      queue.push_back((path, dynamic));
+    }
+  }
+  Ok(deps)
+}
+
+/// Resolve the chain of symlink targets starting from the path, excluding the path itself.
+/// Relative targets are resolved against the parent directory of the link.
+pub fn symlink_chain(path: &Path) -> Vec<PathBuf> {
+  let mut chain: Vec<PathBuf> = vec![];
+  let mut current = path.to_path_buf();
+  // 与内核一致，最多跟随 40 层符号链接
+  while chain.len() < 40 {
+    let target = match read_link(&current) {
+      Ok(target) => target,
+      Err(_) => break,
+    };
+    let target = match current.parent() {
+      Some(parent) if target.is_relative() => parent.join(target),
+      _ => target,
+    };
+    let target = match target.absolutize() {
+      Ok(target) => target.to_path_buf(),
+      Err(_) => break,
+    };
+    chain.push(target.clone());
+    current = target;
+  }
+  chain
+}
+
+/// Retry the mount call on transient errors (`EAGAIN` and `EBUSY`) at most `retries` times,
+/// with exponential backoff starting from 10 ms
+pub fn retry_mount<T, F: FnMut() -> nix::Result<T>>(retries: u32, mut f: F) -> nix::Result<T> {
+  let mut attempt = 0;
+  loop {
+    match f() {
+      Err(err @ (Errno::EAGAIN | Errno::EBUSY)) if attempt < retries => {
+        attempt += 1;
+        let backoff = Duration::from_millis(10 << (attempt - 1).min(6));
+        warn!(
+          "Mount fails with {}, retry #{} after {:?}",
+          err, attempt, backoff
+        );
+        sleep(backoff);
+      }
+      result => return result,
+    }
+  }
+}
+
+/// Check whether the path is a mount point (by reading `/proc/self/mountinfo`)
+pub(crate) fn is_mount_point(path: &Path) -> bool {
+  let path = path.to_string_lossy().replace(' ', "\\040");
+  match read_to_string("/proc/self/mountinfo") {
+    Ok(text) => text
+      .lines()
+      .any(|line| line.split(' ').nth(4) == Some(path.as_str())),
+    Err(err) => {
+      error!("Read /proc/self/mountinfo fails: {}", err);
+      false
+    }
+  }
 }
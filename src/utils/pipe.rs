@@ -1,12 +1,16 @@
 use std::os::unix::prelude::RawFd;
 
 use nix::{
+  errno::Errno,
   fcntl::OFlag,
   unistd::{self, close, pipe2},
 };
 
 use crate::error::CatBoxError;
 
+/// Marker appended to the truncated message
+const TRUNCATED_MARKER: &str = "…(truncated)";
+
 pub struct CatBoxPipe(RawFd, RawFd);
 
 pub struct CatBoxReadPipe(RawFd);
@@ -31,16 +35,42 @@ impl CatBoxPipe {
 }
 
 impl CatBoxReadPipe {
-  pub fn read(self: &Self) -> Result<String, CatBoxError> {
-    let mut buf = vec![0 as u8; 128];
-    unistd::read(self.0, buf.as_mut_slice())?;
-    let buf = buf.into_iter().take_while(|b| *b != 0).collect::<Vec<u8>>();
-    // 忽略 UTF-8 parse 错误
-    let text = String::from_utf8(buf).ok().unwrap_or("".to_string());
+  /// Read the message until NUL or EOF, which is truncated to at most `limit` bytes
+  pub fn read(&self, limit: usize) -> Result<String, CatBoxError> {
+    let mut message = vec![];
+    let mut buf = [0_u8; 1024];
+    let mut truncated = false;
+    loop {
+      let size = match unistd::read(self.0, &mut buf) {
+        // 写端已关闭或暂无更多数据
+        Ok(0) | Err(Errno::EAGAIN) => break,
+        Ok(size) => size,
+        Err(Errno::EINTR) => continue,
+        Err(err) => return Err(err.into()),
+      };
+      let end = buf[..size].iter().position(|b| *b == 0);
+      message.extend_from_slice(&buf[..end.unwrap_or(size)]);
+      if message.len() > limit {
+        message.truncate(limit);
+        truncated = true;
+        break;
+      }
+      if end.is_some() {
+        break;
+      }
+    }
+    // 截断可能落在多字节字符中间，丢弃不完整的字符
+    let mut text = String::from_utf8_lossy(&message).to_string();
+    if truncated {
+      text = text
+        .trim_end_matches(char::REPLACEMENT_CHARACTER)
+        .to_string();
+      text.push_str(TRUNCATED_MARKER);
+    }
     Ok(text)
   }
 
-  pub fn close(self: Self) -> Result<(), CatBoxError> {
+  pub fn close(self) -> Result<(), CatBoxError> {
     Ok(())
   }
 }
@@ -52,15 +82,24 @@ impl Drop for CatBoxReadPipe {
 }
 
 impl CatBoxWritePipe {
-  pub fn write<S: Into<String>>(self: &Self, text: S) -> Result<usize, CatBoxError> {
+  pub fn write<S: Into<String>>(&self, text: S) -> Result<usize, CatBoxError> {
     let text: String = text.into();
     let mut bytes = text.into_bytes();
     bytes.push(0);
-    let size = unistd::write(self.0, &bytes)?;
+    let mut size = 0;
+    while size < bytes.len() {
+      match unistd::write(self.0, &bytes[size..]) {
+        Ok(written) => size += written,
+        Err(Errno::EINTR) => continue,
+        // 管道已满，剩余部分会被读端截断
+        Err(Errno::EAGAIN) => break,
+        Err(err) => return Err(err.into()),
+      }
+    }
     Ok(size)
   }
 
-  pub fn close(self: Self) -> Result<(), CatBoxError> {
+  pub fn close(self) -> Result<(), CatBoxError> {
     Ok(())
   }
 }
@@ -0,0 +1,82 @@
+use std::fs::read_to_string;
+
+use log::{debug, info};
+use nix::unistd::{access, AccessFlags, Gid, Uid};
+
+use crate::syscall::NetPolicy;
+use crate::utils::capability::has_capability;
+use crate::{CatBoxError, CatBoxOption};
+
+/// Check whether the sandbox features required by the option are available, e.g. when catj itself
+/// runs in a container which blocks mount, ptrace or cgroup writes. Returns the list of problems.
+pub(crate) fn probe_unavailable(option: &CatBoxOption) -> Vec<String> {
+  let mut problems = vec![];
+  let mut require = |name: &str, reason: &str| {
+    if !has_capability(name) {
+      problems.push(format!("{} requires {}", reason, name));
+    }
+  };
+
+  if option.chroot().is_some() {
+    require("CAP_SYS_ADMIN", "mount");
+    require("CAP_SYS_CHROOT", "chroot");
+  }
+  if option.uid() != Uid::current() {
+    require("CAP_SETUID", "setuid");
+  }
+  if option.gid() != Gid::current() {
+    require("CAP_SETGID", "setgid");
+  }
+
+  // yama ptrace_scope 为 3 时禁止所有 ptrace
+  if option.ptrace().is_some() {
+    let scope = read_to_string("/proc/sys/kernel/yama/ptrace_scope").unwrap_or_default();
+    if scope.trim() == "3" {
+      problems.push("ptrace is disabled by kernel.yama.ptrace_scope".to_string());
+    }
+  }
+
+  // 内核需要支持 seccomp filter
+  if option.net_policy() == NetPolicy::Seccomp || option.seccomp_default_deny() {
+    let status = read_to_string("/proc/self/status").unwrap_or_default();
+    if !status.lines().any(|line| line.starts_with("Seccomp:")) {
+      problems.push("seccomp is not supported".to_string());
+    }
+  }
+
+  // 显式关闭 cgroup 时无需检查
+  if !option.no_cgroup() {
+    let subsystems = cgroups_rs::hierarchies::auto().subsystems();
+    for controller in ["cpuacct", "memory"] {
+      let subsystem = subsystems
+        .iter()
+        .find(|subsystem| subsystem.controller_name() == controller);
+      match subsystem {
+        None => problems.push(format!("cgroup {} is not supported", controller)),
+        Some(subsystem) => {
+          let path = subsystem.to_controller().path().to_path_buf();
+          if access(&path, AccessFlags::W_OK).is_err() {
+            problems.push(format!("cgroup {} is not writable", path.to_string_lossy()));
+          }
+        }
+      }
+    }
+  }
+
+  debug!("Probe unavailable features: {:?}", problems);
+  problems
+}
+
+/// Probe the sandbox features in force mode, and report all the unavailable ones in one error
+pub(crate) fn check_available(option: &CatBoxOption) -> Result<(), CatBoxError> {
+  if !option.force() {
+    return Ok(());
+  }
+  let problems = probe_unavailable(option);
+  if problems.is_empty() {
+    info!("All the required sandbox features are available");
+    Ok(())
+  } else {
+    Err(CatBoxError::Unavailable(problems.join("; ")))
+  }
+}
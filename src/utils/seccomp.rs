@@ -0,0 +1,122 @@
+use std::ffi::c_long;
+
+use nix::errno::Errno;
+use nix::libc::{self, sock_filter, sock_fprog};
+
+use crate::CatBoxError;
+
+/// `AUDIT_ARCH_X86_64`, see `linux/audit.h`
+const AUDIT_ARCH_X86_64: u32 = 0xc000003e;
+
+/// `__X32_SYSCALL_BIT`, x32 syscalls share the x86_64 arch with this bit set in the number
+const X32_SYSCALL_BIT: u32 = 0x40000000;
+
+/// Offsets of the fields in `struct seccomp_data`
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+fn statement(code: u32, k: u32) -> sock_filter {
+  sock_filter {
+    code: code as u16,
+    jt: 0,
+    jf: 0,
+    k,
+  }
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+  sock_filter {
+    code: code as u16,
+    jt,
+    jf,
+    k,
+  }
+}
+
+/// Check the arch and load the syscall number, shared by all the filters
+fn prelude() -> Vec<sock_filter> {
+  vec![
+    // 其他架构的系统调用号不同，直接杀死进程
+    statement(
+      libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+      SECCOMP_DATA_ARCH,
+    ),
+    jump(
+      libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+      AUDIT_ARCH_X86_64,
+      1,
+      0,
+    ),
+    statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+    statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_NR),
+    // x32 的系统调用号会绕过下面的检查，直接杀死进程
+    jump(
+      libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+      X32_SYSCALL_BIT,
+      0,
+      1,
+    ),
+    statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+  ]
+}
+
+/// Return the action if the syscall number equals to `id`, otherwise go on
+fn match_syscall(filter: &mut Vec<sock_filter>, id: c_long, action: u32) {
+  filter.push(jump(
+    libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+    id as u32,
+    0,
+    1,
+  ));
+  filter.push(statement(libc::BPF_RET | libc::BPF_K, action));
+}
+
+fn errno_action(errno: Errno) -> u32 {
+  libc::SECCOMP_RET_ERRNO | (errno as u32 & libc::SECCOMP_RET_DATA)
+}
+
+/// Install a seccomp filter on current process, which makes the syscalls fail with errno, and
+/// allows all the others. It sets `no_new_privs`, so it works without `CAP_SYS_ADMIN`.
+pub(crate) fn deny_syscalls(ids: &[c_long], errno: Errno) -> Result<(), CatBoxError> {
+  let mut filter = prelude();
+  for id in ids {
+    match_syscall(&mut filter, *id, errno_action(errno));
+  }
+  filter.push(statement(
+    libc::BPF_RET | libc::BPF_K,
+    libc::SECCOMP_RET_ALLOW,
+  ));
+  install(filter)
+}
+
+/// Install a default-deny seccomp filter on current process, which only allows the syscalls, and
+/// makes all the others fail with errno.
+pub(crate) fn allow_syscalls(ids: &[c_long], errno: Errno) -> Result<(), CatBoxError> {
+  let mut filter = prelude();
+  for id in ids {
+    match_syscall(&mut filter, *id, libc::SECCOMP_RET_ALLOW);
+  }
+  filter.push(statement(libc::BPF_RET | libc::BPF_K, errno_action(errno)));
+  install(filter)
+}
+
+fn install(mut filter: Vec<sock_filter>) -> Result<(), CatBoxError> {
+  let program = sock_fprog {
+    len: filter.len() as u16,
+    filter: filter.as_mut_ptr(),
+  };
+  unsafe {
+    if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == -1 {
+      return Err(CatBoxError::from(Errno::last()));
+    }
+    if libc::prctl(
+      libc::PR_SET_SECCOMP,
+      libc::SECCOMP_MODE_FILTER,
+      &program as *const sock_fprog,
+    ) == -1
+    {
+      return Err(CatBoxError::from(Errno::last()));
+    }
+  }
+  Ok(())
+}
@@ -1,9 +1,14 @@
-use catj::{run, CatBoxBuilder, CatBoxResult};
+use catj::{
+  run, CatBox, CatBoxBuilder, CatBoxResult, Comparator, CompareMode, JudgeVerdict, NetPolicy,
+  TimeLimitReason,
+};
 use log::info;
+use nix::libc;
 use nix::sys::signal::Signal;
 use std::env::current_dir;
 use std::fs::{self, remove_dir_all, remove_file};
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use tempfile::tempdir;
 
 mod common;
@@ -40,7 +45,7 @@ fn compile_cpp(dir: &PathBuf, file: &String) -> String {
     .chroot()
     .cwd(current_dir().unwrap())
     .mount_read(&source_dir, &source_dir)
-    .mount_write(&dir, &dir)
+    .mount_write(dir, dir)
     .build();
   run(catbox.single().unwrap()).unwrap();
   catbox.close();
@@ -50,7 +55,7 @@ fn compile_cpp(dir: &PathBuf, file: &String) -> String {
 
 fn run_aplusb(
   dir: &PathBuf,
-  executable: &String,
+  executable: &str,
   ok: bool,
   time: u64,
   memory: u64,
@@ -62,7 +67,7 @@ fn run_aplusb(
     let sub_out = sub_out.to_string_lossy().to_string();
 
     let catbox = CatBoxBuilder::run()
-      .command::<String, String>(executable.clone(), vec![])
+      .command::<&str, String>(executable, vec![])
       .time_limit(time)
       .memory_limit(memory)
       .stdin(sub_in.clone())
@@ -71,7 +76,7 @@ fn run_aplusb(
       .env("ONLINE_JUDGE", "true")
       .chroot()
       .cwd("/")
-      .mount_read(&dir, &dir)
+      .mount_read(dir, dir)
       .build();
     let result = run(catbox.single().unwrap()).unwrap();
 
@@ -146,6 +151,93 @@ fn it_should_run_cpp_ac() {
   run_ok_cpp("ac.cpp", 1000, 262144);
 }
 
+#[test]
+fn it_should_judge_against_testcases_dir() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"ac.cpp".to_string());
+  let comparator = Comparator::new(CompareMode::Token);
+
+  let cases = CatBox::judge_against_dir(
+    &executable,
+    "./fixtures/aplusb/testcases",
+    1000,
+    262144,
+    &comparator,
+  )
+  .unwrap();
+  let names = cases.iter().map(|c| c.name()).collect::<Vec<_>>();
+  assert_eq!(names, vec!["1", "2", "3"]);
+  assert!(cases
+    .iter()
+    .all(|c| c.verdict() == JudgeVerdict::Accepted && *c.result().status() == Some(0)));
+
+  // cat echoes the input back
+  let cases = CatBox::judge_against_dir(
+    "/usr/bin/cat",
+    "./fixtures/aplusb/testcases",
+    1000,
+    262144,
+    &comparator,
+  )
+  .unwrap();
+  assert!(cases
+    .iter()
+    .all(|c| c.verdict() == JudgeVerdict::WrongAnswer));
+}
+
+fn run_with_answer(executable: &str, dir: &Path, answer: &str) -> Option<JudgeVerdict> {
+  let output = dir.join("sub.out");
+  let catbox = CatBoxBuilder::run()
+    .command::<&str, String>(executable, vec![])
+    .stdin("./fixtures/aplusb/testcases/1.in")
+    .stdout(output.to_string_lossy())
+    .answer(answer, Comparator::new(CompareMode::Token))
+    .chroot()
+    .cwd("/")
+    .mount_read(dir, dir)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  result.judge_verdict()
+}
+
+#[test]
+fn it_should_compare_with_answer_in_run() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"ac.cpp".to_string());
+
+  let verdict = run_with_answer(&executable, dir.path(), "./fixtures/aplusb/testcases/1.ans");
+  assert_eq!(verdict, Some(JudgeVerdict::Accepted));
+  let verdict = run_with_answer(&executable, dir.path(), "./fixtures/aplusb/testcases/2.ans");
+  assert_eq!(verdict, Some(JudgeVerdict::WrongAnswer));
+
+  // Stdout is captured by the cli without redirection
+  let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args([
+      "--report",
+      "--json",
+      "run",
+      "-i",
+      "./fixtures/aplusb/testcases/1.in",
+    ])
+    .args([
+      "--answer",
+      "./fixtures/aplusb/testcases/1.ans",
+      "--compare-mode",
+      "strict",
+    ])
+    .args(["--read", dir.path().to_str().unwrap(), "--", &executable])
+    .env("CATJ_LOG", dir.path())
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let report = String::from_utf8(output.stdout).unwrap();
+  assert!(report.starts_with("2\n"));
+  assert!(report.contains("\"verdict\": \"AC\""));
+}
+
 #[test]
 fn it_should_run_small_stack() {
   common::setup();
@@ -166,6 +258,7 @@ fn it_should_not_run_mle() {
   let result = run_fail_cpp("mle.cpp", 1000, 262144);
   // assert_eq!(*result.status(), None);
   assert!(result.memory() > 262144);
+  assert!(result.mle());
 }
 
 #[test]
@@ -174,6 +267,29 @@ fn it_should_not_run_malloc() {
   let result = run_fail_cpp("malloc.c", 1000, 262144);
   // assert_eq!(*result.status(), None);
   assert!(result.memory() > 262144);
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  assert!(result.mle());
+}
+
+#[test]
+fn it_should_detect_mle_by_allocation_failure() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"malloc_chunks.c".to_string());
+
+  // Limit the address space by RLIMIT_AS, so that malloc fails before the cgroup OOM killer
+  let catbox = CatBoxBuilder::run()
+    .command::<String, String>(executable, vec![])
+    .memory_limit(262144)
+    .address_space_limit(65536 * 1024)
+    .stderr("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(1));
+  assert!(result.memory() < 65536);
+  assert!(result.mle());
 }
 
 #[test]
@@ -233,11 +349,221 @@ fn it_should_not_run_env() {
   assert_eq!(*result.status(), Some(0));
 }
 
+#[test]
+fn it_should_not_report_caught_signal() {
+  common::setup();
+  let (text, result) = run_fail_cpp_stdout("catch_fpe.c", 1000, 262144);
+  assert_eq!(text.trim_end(), "1,2");
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(*result.signal(), None);
+  assert!(!result.mle());
+}
+
+#[test]
+fn it_should_record_signal_history() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"segv_abort.c".to_string());
+
+  let catbox = CatBoxBuilder::run()
+    .command::<&str, String>(&executable, vec![])
+    .stdin("/dev/null")
+    .stdout("/dev/null")
+    .stderr("/dev/null")
+    .record_signal_history(true)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.signal(), Some(Signal::SIGABRT));
+  assert_eq!(
+    *result.signal_history(),
+    vec![Signal::SIGSEGV, Signal::SIGABRT]
+  );
+}
+
+fn run_unflushed(executable: &str, dir: &Path, launcher: bool) -> String {
+  let output = dir.join("unflushed.out");
+  let builder = CatBoxBuilder::run()
+    .command::<&str, String>(executable, vec![])
+    .stdin("/dev/null")
+    .stdout(output.to_string_lossy())
+    .stderr("/dev/null")
+    .chroot()
+    .cwd("/")
+    .mount_read(dir, dir);
+  let builder = if launcher {
+    builder.launcher("stdbuf", vec!["-o0"])
+  } else {
+    builder
+  };
+  let catbox = builder.build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.signal(), Some(Signal::SIGABRT));
+  fs::read_to_string(output).unwrap()
+}
+
+#[test]
+fn it_should_wrap_with_launcher() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"unflushed.c".to_string());
+
+  // The buffered output is lost on abort, unless stdout is unbuffered by stdbuf
+  assert_eq!(run_unflushed(&executable, dir.path(), false), "");
+  assert_eq!(run_unflushed(&executable, dir.path(), true), "unflushed");
+}
+
+fn run_stack_address(executable: &str, dir: &Path, disable_aslr: bool) -> String {
+  let output = dir.join("stack_address.out");
+  let catbox = CatBoxBuilder::run()
+    .command::<&str, String>(executable, vec![])
+    .stdin("/dev/null")
+    .stdout(output.to_string_lossy())
+    .disable_aslr(disable_aslr)
+    .chroot()
+    .cwd("/")
+    .mount_read(dir, dir)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  fs::read_to_string(output).unwrap()
+}
+
+#[test]
+fn it_should_disable_aslr() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"stack_address.c".to_string());
+
+  let first = run_stack_address(&executable, dir.path(), true);
+  let second = run_stack_address(&executable, dir.path(), true);
+  assert_eq!(first, second);
+
+  // Stack address is randomized by default, unless ASLR is off on the host
+  let randomize = fs::read_to_string("/proc/sys/kernel/randomize_va_space").unwrap();
+  if randomize.trim() != "0" {
+    let first = run_stack_address(&executable, dir.path(), false);
+    let second = run_stack_address(&executable, dir.path(), false);
+    assert_ne!(first, second);
+  }
+}
+
+#[test]
+fn it_should_use_timeout_signal() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"own_alarm.c".to_string());
+
+  // The program keeps re-arming its own alarm, which would otherwise override the sandbox one
+  let catbox = CatBoxBuilder::run()
+    .command::<&str, String>(&executable, vec![])
+    .time_limit(1000)
+    .timeout_signal(Signal::SIGUSR2)
+    .stdin("/dev/null")
+    .stdout("/dev/null")
+    .stderr("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), None);
+  assert_eq!(*result.signal(), Some(Signal::SIGUSR2));
+  assert_eq!(result.time_limit_reason(), Some(TimeLimitReason::Wall));
+}
+
+#[test]
+fn it_should_sample_rss() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"rss_spike.c".to_string());
+
+  let catbox = CatBoxBuilder::run()
+    .command::<&str, String>(&executable, vec![])
+    .time_limit(2000)
+    .sample_rss(20)
+    .stdin("/dev/null")
+    .stdout("/dev/null")
+    .stderr("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  let samples = result.rss_samples();
+  assert!(samples.windows(2).all(|w| w[0].0 <= w[1].0));
+  // RSS rises by the 64 MB buffer, then falls after freeing it
+  let (peak, (_, peak_rss)) = samples
+    .iter()
+    .enumerate()
+    .max_by_key(|(_, (_, rss))| *rss)
+    .unwrap();
+  assert!(*peak_rss >= 60 * 1024);
+  assert!(samples[peak..].iter().any(|(_, rss)| *rss < peak_rss / 2));
+}
+
+#[test]
+fn it_should_kill_sustained_memory_growth() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"slow_leak.c".to_string());
+
+  let catbox = CatBoxBuilder::run()
+    .command::<&str, String>(&executable, vec![])
+    .time_limit(3000)
+    .memory_limit(262144)
+    .sustained_memory_limit(16 * 1024, 200)
+    .stdin("/dev/null")
+    .stdout("/dev/null")
+    .stderr("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  // The leak grows to 80 MB, far below the memory limit, but stays above 16 MB for long
+  assert!(result.mle());
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  assert!(result.memory() < 262144);
+  // Samples are not recorded unless sample_rss is enabled
+  assert!(result.rss_samples().is_empty());
+}
+
+#[test]
+fn it_should_limit_mmap_size() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"mmap_huge.c".to_string());
+
+  let run_mmap = |max_bytes: u64| {
+    let catbox = CatBoxBuilder::run()
+      .command::<&str, String>(&executable, vec![])
+      .limit_mmap(max_bytes)
+      .stdin("/dev/null")
+      .stdout("/dev/null")
+      .stderr("/dev/null")
+      .build();
+    let result = run(catbox.single().unwrap()).unwrap();
+    catbox.close();
+    result
+  };
+
+  // Mapping 1 GB at once is blocked, while the small mappings of the loader are allowed
+  let result = run_mmap(256 * 1024 * 1024);
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  let result = run_mmap(2 * 1024 * 1024 * 1024);
+  assert_eq!(*result.status(), Some(0));
+}
+
 #[test]
 fn it_should_not_run_re1() {
   common::setup();
   let result = run_fail_cpp("re1.c", 1000, 262144);
   assert_eq!(*result.status(), Some(42));
+  assert_eq!(result.shell_exit_code(), 42);
 }
 
 #[test]
@@ -246,6 +572,8 @@ fn it_should_not_run_re2() {
   let result = run_fail_cpp("re2.c", 1000, 262144);
   assert_eq!(*result.status(), None);
   assert_eq!(*result.signal(), Some(Signal::SIGSEGV));
+  // 128 + 11, as the shell reports in `$?`
+  assert_eq!(result.shell_exit_code(), 139);
 }
 
 #[test]
@@ -255,3 +583,55 @@ fn it_should_not_run_assert() {
   assert_eq!(*result.status(), None);
   assert_eq!(*result.signal(), Some(Signal::SIGABRT));
 }
+
+fn run_socket(executable: &str, dir: &Path, policy: NetPolicy) -> String {
+  let output = dir.join("socket.out");
+  let catbox = CatBoxBuilder::run()
+    .command::<&str, String>(executable, vec![])
+    .net_policy(policy)
+    .stdin("/dev/null")
+    .stdout(output.to_string_lossy())
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  fs::read_to_string(output).unwrap()
+}
+
+#[test]
+fn it_should_deny_network_by_seccomp() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"socket.c".to_string());
+
+  assert_eq!(
+    run_socket(&executable, dir.path(), NetPolicy::Allow),
+    "0 0\n"
+  );
+  // socket() fails with EPERM, instead of killing the program
+  assert_eq!(
+    run_socket(&executable, dir.path(), NetPolicy::Seccomp),
+    format!("-1 {}\n", libc::EPERM)
+  );
+}
+
+#[test]
+fn it_should_kill_x32_syscalls_by_seccomp() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = compile_cpp(&dir.path().to_path_buf(), &"socket_x32.c".to_string());
+
+  let catbox = CatBoxBuilder::run()
+    .command::<&str, String>(&executable, vec![])
+    .net_policy(NetPolicy::Seccomp)
+    .stdin("/dev/null")
+    .stdout("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  // socket() with the x32 bit does not match the denied numbers
+  assert_eq!(*result.status(), None);
+  assert_eq!(*result.signal(), Some(Signal::SIGSYS));
+}
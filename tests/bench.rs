@@ -0,0 +1,47 @@
+use std::process::Command;
+
+use catj::{CatBox, CatBoxBuilder};
+use tempfile::tempdir;
+
+mod common;
+
+#[test]
+fn it_should_bench_sandbox_overhead() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command::<&str, &str>("true", vec![])
+    .stdout("/dev/null")
+    .chroot()
+    .build();
+  let report = CatBox::bench(catbox.single().unwrap(), 5).unwrap();
+  catbox.close();
+
+  assert_eq!(report.iterations(), 5);
+  assert!(report.min() > 0);
+  assert!(report.min() <= report.percentile(50));
+  assert!(report.percentile(50) <= report.percentile(90));
+  assert!(report.percentile(90) <= report.max());
+  assert!(report.min() <= report.mean() && report.mean() <= report.max());
+  // A no-op run should not take seconds
+  assert!(report.percentile(50) < 5_000_000);
+}
+
+#[test]
+fn it_should_output_bench_json() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args(["bench", "--iterations", "3"])
+    .env("CATJ_LOG", dir.path())
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+
+  let text = String::from_utf8(output.stdout).unwrap();
+  let report: serde_json::Value = serde_json::from_str(&text).unwrap();
+  assert_eq!(report["ok"], true);
+  assert_eq!(report["iterations"], 3);
+  for key in ["mean_us", "min_us", "p50_us", "p90_us", "p99_us", "max_us"] {
+    assert!(report[key].is_u64(), "{}", text);
+  }
+}
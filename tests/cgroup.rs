@@ -0,0 +1,301 @@
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use catj::{run, CatBox, CatBoxBuilder, ReportFormat};
+use nix::sys::signal::Signal;
+use tempfile::tempdir;
+
+mod common;
+
+#[test]
+fn it_should_cleanup_stale_cgroups() {
+  common::setup();
+  let parent = "catj_gc_test";
+  let root = PathBuf::from("/sys/fs/cgroup/cpu").join(parent);
+  let stale = root.join(format!("{}.4194304", parent));
+  let other = root.join("other");
+  fs::create_dir_all(&stale).unwrap();
+  fs::create_dir_all(&other).unwrap();
+
+  let removed = CatBox::cleanup_stale(parent).unwrap();
+
+  assert_eq!(removed, vec![format!("{}/{}.4194304", parent, parent)]);
+  assert!(!stale.exists());
+  assert!(other.exists());
+
+  fs::remove_dir(other).unwrap();
+  fs::remove_dir(root).unwrap();
+}
+
+#[test]
+fn it_should_read_memory_without_swap_accounting() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "dd",
+      vec!["if=/dev/zero", "of=/dev/null", "bs=32M", "count=1"],
+    )
+    .memory_limit(16384)
+    .swap_accounting(false)
+    .stderr("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+
+  // Peak memory is still read from the memory controller, instead of falling back to getrusage
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  assert!(result.memory() > 16384);
+  assert!(result.mle());
+}
+
+#[test]
+fn it_should_not_mark_page_cache_reclaim_as_mle() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("zero.out");
+  // The page cache of the written and read file is charged to the cgroup, and reclaimed at the limit
+  let script = format!(
+    "sleep 0.2; dd if=/dev/zero of={0} bs=1M count=64 && cat {0} > /dev/null",
+    output.to_string_lossy()
+  );
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", &script])
+    .memory_limit(16384)
+    .process(4)
+    .set_chroot(false)
+    .current_user()
+    .disable_ptrace()
+    .stderr("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert!(!result.mle());
+}
+
+#[test]
+fn it_should_set_cpu_burst() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("sleep", vec!["1"])
+    .cpu_burst(20000)
+    .disable_ptrace()
+    .build();
+  let option = catbox.single().unwrap().clone();
+  let root = PathBuf::from("/sys/fs/cgroup/cpu").join(option.cgroup());
+  let handle = thread::spawn(move || run(&option).unwrap());
+
+  // Look for the sandbox cgroup while the child is sleeping
+  let mut burst = None;
+  for _ in 0..50 {
+    burst = fs::read_dir(&root).ok().and_then(|entries| {
+      entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| fs::read_to_string(entry.path().join("cpu.cfs_burst_us")).ok())
+        .find(|value| value.trim() == "20000")
+    });
+    if burst.is_some() {
+      break;
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+  let result = handle.join().unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert!(burst.is_some());
+}
+
+#[test]
+fn it_should_disable_oom_kill() {
+  common::setup();
+  let run_dd = |oom_kill_disable: bool| {
+    let catbox = CatBoxBuilder::run()
+      .command(
+        "sh",
+        vec![
+          "-c",
+          "sleep 0.2; exec dd if=/dev/zero of=/dev/null bs=32M count=1",
+        ],
+      )
+      .time_limit(1000)
+      .memory_limit(16384)
+      .process(5)
+      .oom_kill_disable(oom_kill_disable)
+      .disable_ptrace()
+      .stderr("/dev/null")
+      .build();
+    let result = run(catbox.single().unwrap()).unwrap();
+    catbox.close();
+    result
+  };
+
+  let result = run_dd(false);
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  assert!(result.mle());
+
+  // The child is frozen at the limit instead of being killed, until the alarm fires
+  let result = run_dd(true);
+  assert_eq!(*result.signal(), Some(Signal::SIGALRM));
+  assert!(result.mle());
+}
+
+#[test]
+fn it_should_persist_cgroup() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["persist"])
+    .persist_cgroup(true)
+    .stdout("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  let name = result.cgroup().unwrap();
+  let dir = PathBuf::from("/sys/fs/cgroup/memory").join(name);
+  assert!(dir.is_dir());
+
+  // The caller is responsible for deleting the persisted cgroup
+  for controller in ["cpu", "cpuacct", "memory", "pids"] {
+    fs::remove_dir(PathBuf::from("/sys/fs/cgroup").join(controller).join(name)).ok();
+  }
+  assert!(!dir.exists());
+}
+
+#[test]
+fn it_should_enable_controllers_on_cgroup_v2() {
+  common::setup();
+  // Only meaningful on the cgroup v2 unified hierarchy
+  let root = PathBuf::from("/sys/fs/cgroup");
+  if !root.join("cgroup.controllers").exists() {
+    return;
+  }
+
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["v2"])
+    .persist_cgroup(true)
+    .stdout("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  let dir = root.join(result.cgroup().unwrap());
+  let controllers = fs::read_to_string(dir.join("cgroup.controllers")).unwrap();
+  fs::remove_dir(&dir).unwrap();
+  for controller in ["memory", "pids"] {
+    assert!(
+      controllers.split_whitespace().any(|c| c == controller),
+      "{}",
+      controllers
+    );
+  }
+}
+
+#[test]
+fn it_should_diagnose_usage() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::run()
+    .command("echo", vec!["diagnose"])
+    .diagnose_usage(true)
+    .stdout("/dev/null")
+    .build();
+  catbox.start().unwrap();
+  let mut report = Vec::new();
+  catbox.report_to(&mut report, ReportFormat::Json).unwrap();
+  let report = String::from_utf8(report).unwrap();
+  catbox.close();
+  for key in [
+    "cgroup_time",
+    "cgroup_memory",
+    "rusage_time",
+    "rusage_memory",
+  ] {
+    assert!(report.contains(&format!("\"{}\": ", key)), "{}", report);
+  }
+
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["diagnose"])
+    .diagnose_usage(true)
+    .stdout("/dev/null")
+    .done()
+    .command("echo", vec!["normal"])
+    .stdout("/dev/null")
+    .build();
+  let results = catbox
+    .commands()
+    .map(|option| run(option).unwrap())
+    .collect::<Vec<_>>();
+  catbox.close();
+
+  // Both cgroup and getrusage numbers are collected, and the reported ones come from cgroup
+  let diagnostics = results[0].diagnostics().unwrap();
+  assert_eq!(diagnostics.cgroup_time, Some(results[0].time()));
+  assert_eq!(diagnostics.cgroup_memory, Some(results[0].memory()));
+  assert!(diagnostics.rusage_memory > 0);
+  assert!(results[1].diagnostics().is_none());
+}
+
+#[test]
+fn it_should_run_without_cgroup() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("cgroup.out");
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "echo $$; cat /proc/self/cgroup"])
+    .no_cgroup()
+    .persist_cgroup(true)
+    .current_user()
+    .disable_ptrace()
+    .stdout(output.to_string_lossy())
+    .build();
+  let option = catbox.single().unwrap();
+  let name = option.cgroup().to_string();
+  let result = run(option).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert!(result.cgroup().is_none());
+  let output = fs::read_to_string(&output).unwrap();
+  let pid = output.lines().next().unwrap();
+  // Neither the cgroup directory is created, nor the child is moved into it
+  let child_cgroup = format!("{}/{}.{}", name, name, pid);
+  for controller in ["cpu", "cpuacct", "memory", "pids"] {
+    assert!(!PathBuf::from("/sys/fs/cgroup")
+      .join(controller)
+      .join(&child_cgroup)
+      .exists());
+  }
+  assert!(!output.contains(&child_cgroup), "{}", output);
+}
+
+#[test]
+fn it_should_read_usage_of_each_child_without_cgroup() {
+  common::setup();
+  let run_no_cgroup = |command: &str, args: Vec<&str>| {
+    let catbox = CatBoxBuilder::run()
+      .command(command, args)
+      .memory_limit(16384)
+      .no_cgroup()
+      .disable_ptrace()
+      .stderr("/dev/null")
+      .build();
+    let result = run(catbox.single().unwrap()).unwrap();
+    catbox.close();
+    result
+  };
+
+  let result = run_no_cgroup(
+    "dd",
+    vec!["if=/dev/zero", "of=/dev/null", "bs=32M", "count=1"],
+  );
+  assert!(result.memory() > 16384);
+  assert!(result.mle());
+
+  // The peak memory of the previous child is not counted again
+  let result = run_no_cgroup("true", vec![]);
+  assert_eq!(*result.status(), Some(0));
+  assert!(result.memory() < 16384, "{}", result.memory());
+  assert!(!result.mle());
+}
@@ -0,0 +1,177 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+use catj::{check, CatBoxBuilder, CheckResult, CheckVerdict, CheckerFormat};
+use serde_json::Value;
+use tempfile::tempdir;
+
+mod common;
+
+#[test]
+fn it_should_parse_checker_format() {
+  let format = CheckerFormat::ExitCode;
+  let result = format.parse_result(Some(0), "ok 1 number\n").unwrap();
+  assert_eq!(result.verdict(), CheckVerdict::Accepted);
+  assert_eq!(result.message(), "ok 1 number");
+  let result = format.parse_result(Some(1), "").unwrap();
+  assert_eq!(result.verdict(), CheckVerdict::WrongAnswer);
+  let result = format.parse_result(Some(2), "").unwrap();
+  assert_eq!(result.verdict(), CheckVerdict::PresentationError);
+  let result = format.parse_result(None, "").unwrap();
+  assert_eq!(result.verdict(), CheckVerdict::Fail);
+
+  let format = CheckerFormat::Kv;
+  let result = format.parse_result(Some(0), "result=AC\n").unwrap();
+  assert_eq!(result.verdict(), CheckVerdict::Accepted);
+  let result = format
+    .parse_result(Some(0), "result=WA expected 2\n")
+    .unwrap();
+  assert_eq!(result.verdict(), CheckVerdict::WrongAnswer);
+  assert_eq!(result.message(), "expected 2");
+  assert_eq!(result.score(), None);
+  assert!(format.parse_result(Some(0), "AC").is_err());
+  assert!(format.parse_result(Some(0), "result=XX").is_err());
+}
+
+#[test]
+fn it_should_parse_partial_score() {
+  let result = CheckerFormat::ExitCode
+    .parse_result(Some(7), "points 0.5 half of the answers\n")
+    .unwrap();
+  assert_eq!(result.verdict(), CheckVerdict::Partial);
+  assert_eq!(result.score(), Some(0.5));
+  assert_eq!(result.message(), "half of the answers");
+
+  let result = CheckerFormat::Kv
+    .parse_result(Some(0), "result=PC score=0.25\n")
+    .unwrap();
+  assert_eq!(result.verdict(), CheckVerdict::Partial);
+  assert_eq!(result.score(), Some(0.25));
+  assert!(CheckerFormat::Kv
+    .parse_result(Some(0), "result=PC score=half")
+    .is_err());
+}
+
+fn run_checker(script: &str, format: CheckerFormat, output: &str) -> CheckResult {
+  let dir = tempdir().unwrap();
+  let input = dir.path().join("1.in");
+  let out = dir.path().join("1.out");
+  let ans = dir.path().join("1.ans");
+  fs::write(&input, "1 1\n").unwrap();
+  fs::write(&out, output).unwrap();
+  fs::write(&ans, "2\n").unwrap();
+
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "sh",
+      vec![
+        "-c",
+        script,
+        "checker",
+        input.to_str().unwrap(),
+        out.to_str().unwrap(),
+        ans.to_str().unwrap(),
+      ],
+    )
+    .current_user()
+    .disable_ptrace()
+    .process(4)
+    .build();
+  let result = check(catbox.single().unwrap(), format).unwrap();
+  catbox.close();
+  result
+}
+
+#[test]
+fn it_should_check_with_exit_code() {
+  common::setup();
+  let script = "cmp -s $2 $3";
+  assert_eq!(
+    run_checker(script, CheckerFormat::ExitCode, "2\n").verdict(),
+    CheckVerdict::Accepted
+  );
+  assert_eq!(
+    run_checker(script, CheckerFormat::ExitCode, "3\n").verdict(),
+    CheckVerdict::WrongAnswer
+  );
+}
+
+#[test]
+fn it_should_check_with_kv() {
+  common::setup();
+  let script = "if cmp -s $2 $3; then echo result=AC; else echo result=WA; fi";
+  assert_eq!(
+    run_checker(script, CheckerFormat::Kv, "2\n").verdict(),
+    CheckVerdict::Accepted
+  );
+  assert_eq!(
+    run_checker(script, CheckerFormat::Kv, "3\n").verdict(),
+    CheckVerdict::WrongAnswer
+  );
+}
+
+#[test]
+fn it_should_check_partial_score() {
+  common::setup();
+  let script = "echo points 0.5 partially correct; exit 7";
+  let result = run_checker(script, CheckerFormat::ExitCode, "3\n");
+  assert_eq!(result.verdict(), CheckVerdict::Partial);
+  assert_eq!(result.score(), Some(0.5));
+  assert_eq!(result.message(), "partially correct");
+}
+
+#[test]
+fn it_should_mount_checker_files() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let input = dir.path().join("1.in");
+  let out = dir.path().join("1.out");
+  let ans = dir.path().join("1.ans");
+  fs::write(&input, "1 1\n").unwrap();
+  fs::write(&out, "2\n").unwrap();
+  fs::write(&ans, "2\n").unwrap();
+
+  // The checker reads all the three files inside the jail, without any explicit mount
+  let script = "read a < $1 && read b < $2 && read c < $3 && \
+    [ \"$a\" = \"1 1\" ] && [ \"$b\" = \"$c\" ] && echo $1 $2 $3";
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", script, "checker"])
+    .checker_files(&input, &out, &ans)
+    .disable_ptrace()
+    .build();
+  let option = catbox.single().unwrap();
+  assert!(option.chroot().is_some());
+  let result = check(option, CheckerFormat::ExitCode).unwrap();
+  catbox.close();
+
+  assert_eq!(result.verdict(), CheckVerdict::Accepted);
+  assert_eq!(
+    result.message(),
+    "/checker/input /checker/output /checker/answer"
+  );
+}
+
+#[test]
+fn it_should_escape_checker_message_in_json() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let checker = dir.path().join("checker.sh");
+  fs::write(&checker, "#!/bin/sh\nprintf 'a\\033b\\n'\n").unwrap();
+  fs::set_permissions(&checker, fs::Permissions::from_mode(0o755)).unwrap();
+  let empty = dir.path().join("empty");
+  fs::write(&empty, "").unwrap();
+
+  let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args(["--report", "--json", "--user", "check"])
+    .arg(&checker)
+    .args([&empty, &empty, &empty])
+    .env("CATJ_LOG", dir.path())
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+
+  let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(report["verdict"], "AC");
+  assert_eq!(report["message"], "a\x1bb");
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use catj::{Comparator, CompareMode};
+use tempfile::tempdir;
+
+#[test]
+fn it_should_compare_same_output() {
+  let comparator = Comparator::new(CompareMode::Strict);
+  let result = comparator.compare("1 2\n3\n".as_bytes(), "1 2\n3\n".as_bytes());
+  assert!(result.unwrap().same());
+
+  let comparator = Comparator::new(CompareMode::Token);
+  let result = comparator.compare("1  2\n3".as_bytes(), "1 2\n3\n\n".as_bytes());
+  assert!(result.unwrap().same());
+}
+
+#[test]
+fn it_should_find_first_difference() {
+  let comparator = Comparator::new(CompareMode::Strict);
+  let result = comparator
+    .compare("1 2\n3 4\n".as_bytes(), "1 2\n3 5\n".as_bytes())
+    .unwrap();
+  let diff = result.difference().unwrap();
+  assert_eq!(diff.byte(), 6);
+  assert_eq!(diff.line(), 2);
+  assert_eq!(diff.token(), 4);
+
+  let comparator = Comparator::new(CompareMode::Token);
+  let result = comparator
+    .compare("1 2\n  3 45\n".as_bytes(), "1 2 3 4\n".as_bytes())
+    .unwrap();
+  let diff = result.difference().unwrap();
+  assert_eq!(diff.byte(), 8);
+  assert_eq!(diff.line(), 2);
+  assert_eq!(diff.token(), 4);
+
+  let result = comparator
+    .compare("1 2".as_bytes(), "1 2 3".as_bytes())
+    .unwrap();
+  assert_eq!(result.difference().unwrap().token(), 3);
+}
+
+#[test]
+fn it_should_ignore_trailing_whitespace_in_lenient_mode() {
+  let comparator = Comparator::new(CompareMode::Lenient);
+  let same = |output: &str, answer: &str| {
+    comparator
+      .compare(output.as_bytes(), answer.as_bytes())
+      .unwrap()
+      .same()
+  };
+  // Trailing whitespace of each line, CRLF and trailing blank lines are ignored
+  assert!(same("1 2  \n3\t\n", "1 2\n3\n"));
+  assert!(same("1 2\r\n3\r\n", "1 2\n3"));
+  assert!(same("1 2\n3", "1 2\n3\n\n  \n"));
+  assert!(same("1 2\n3\n\n\n", "1 2\n3"));
+  // while interior whitespace and blank lines are compared strictly
+  assert!(!same("1  2\n3\n", "1 2\n3\n"));
+  assert!(!same(" 1 2\n3\n", "1 2\n3\n"));
+  assert!(!same("1 2\n\n3\n", "1 2\n3\n"));
+  assert!(!same("1 2\n", "1 2\n3\n"));
+
+  let result = comparator
+    .compare("1 2\n3 45  \n".as_bytes(), "1 2\n3 4\n".as_bytes())
+    .unwrap();
+  let diff = result.difference().unwrap();
+  assert_eq!(diff.byte(), 7);
+  assert_eq!(diff.line(), 2);
+  assert_eq!(diff.token(), 4);
+}
+
+#[test]
+fn it_should_compare_large_files() {
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("large.out");
+  let answer = dir.path().join("large.ans");
+
+  let lines = 1_000_000;
+  let mut text = (0..lines).map(|i| format!("{}\n", i)).collect::<String>();
+  fs::write(&answer, &text).unwrap();
+  text.truncate(text.len() - 2);
+  text.push_str("0\n");
+  fs::write(&output, &text).unwrap();
+
+  let comparator = Comparator::new(CompareMode::Token);
+  let result = comparator.compare_files(&output, &answer).unwrap();
+  let diff = result.difference().unwrap();
+  assert_eq!(diff.line(), lines);
+  assert_eq!(diff.token(), lines);
+  assert_eq!(diff.byte(), (text.len() - 7) as u64);
+
+  let result = Comparator::new(CompareMode::Strict)
+    .compare_files(&output, &answer)
+    .unwrap();
+  assert_eq!(result.difference().unwrap().byte(), (text.len() - 2) as u64);
+
+  let result = Comparator::new(CompareMode::Strict)
+    .max_bytes((text.len() - 2) as u64)
+    .compare_files(&output, &answer)
+    .unwrap();
+  assert!(result.same());
+}
+
+fn compare_with_stdin(output: &str, answer: &str) -> String {
+  let dir = tempdir().unwrap();
+  let path = dir.path().join("sub.out");
+  fs::write(&path, output).unwrap();
+
+  let mut child = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args(["compare", path.to_str().unwrap(), "-"])
+    .env("CATJ_LOG", dir.path())
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()
+    .unwrap();
+  child
+    .stdin
+    .take()
+    .unwrap()
+    .write_all(answer.as_bytes())
+    .unwrap();
+  let result = child.wait_with_output().unwrap();
+  assert!(result.status.success());
+  String::from_utf8(result.stdout).unwrap()
+}
+
+#[test]
+fn it_should_read_answer_from_stdin() {
+  let text = compare_with_stdin("1 2\n3\n", "1 2 3\n");
+  assert!(text.contains("✓"));
+
+  let text = compare_with_stdin("1 2\n4\n", "1 2 3\n");
+  assert!(text.contains("×"));
+  assert!(text.contains("Token"));
+}
+
+#[test]
+fn it_should_output_compare_json() {
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("sub.out");
+  let answer = dir.path().join("sub.ans");
+  fs::write(&output, "1 2\n4\n").unwrap();
+  fs::write(&answer, "1 2\n3\n").unwrap();
+
+  let compare = |answer: &std::path::Path| {
+    let result = Command::new(env!("CARGO_BIN_EXE_catj"))
+      .args(["--report", "--json", "compare"])
+      .args([&output, answer])
+      .env("CATJ_LOG", dir.path())
+      .output()
+      .unwrap();
+    assert!(result.status.success());
+    serde_json::from_slice::<serde_json::Value>(&result.stdout).unwrap()
+  };
+
+  let report = compare(&output);
+  assert_eq!(report, serde_json::json!({ "ok": true, "same": true }));
+
+  let report = compare(&answer);
+  assert_eq!(report["ok"], true);
+  assert_eq!(report["same"], false);
+  assert_eq!(report["line"], 2);
+  assert_eq!(report["token"], 3);
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::process::Command;
+
+use catj::CatBoxBuilder;
+use nix::unistd::User;
+use tempfile::tempdir;
+
+mod common;
+
+fn has_process(cmdline: &str) -> bool {
+  fs::read_dir("/proc")
+    .unwrap()
+    .filter_map(|entry| entry.ok())
+    .filter_map(|entry| fs::read(entry.path().join("cmdline")).ok())
+    .any(|text| text == cmdline.as_bytes())
+}
+
+#[test]
+fn it_should_kill_slow_compile_subprocesses() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::compile()
+    .command("sh", vec!["-c", "sleep 37 & wait"])
+    .time_limit(1000)
+    .process(10)
+    .current_user()
+    .disable_ptrace()
+    .kill_process_group(true)
+    .build();
+  catbox.start().unwrap();
+  catbox.close();
+
+  assert!(!has_process("sleep\x0037\x00"));
+}
+
+#[test]
+fn it_should_stop_after_failed_compile_command() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let dir = dir.into_path();
+  let source = dir.join("Main.java");
+  let jar = dir.join("Main.jar");
+  fs::write(&source, "public class Main { syntax error }").unwrap();
+
+  let mut catbox = CatBoxBuilder::compile()
+    .command(
+      "javac",
+      vec!["-d", dir.to_str().unwrap(), source.to_str().unwrap()],
+    )
+    .label("javac".to_string())
+    .time_limit(10 * 1000)
+    .memory_limit(1024 * 1024)
+    .process(20)
+    .current_user()
+    .disable_ptrace()
+    .stderr("/dev/null")
+    .done()
+    .command(
+      "jar",
+      vec![
+        "-cvf",
+        jar.to_str().unwrap(),
+        "-C",
+        dir.to_str().unwrap(),
+        "Main.java",
+      ],
+    )
+    .label("jar".to_string())
+    .time_limit(10 * 1000)
+    .memory_limit(1024 * 1024)
+    .process(20)
+    .current_user()
+    .disable_ptrace()
+    .stdout("/dev/null")
+    .build();
+  catbox.start().unwrap();
+  catbox.close();
+
+  assert!(!jar.exists());
+  fs::remove_dir_all(dir).unwrap();
+}
+
+fn run_tolerated_compile(tolerate: Vec<i32>) -> bool {
+  let dir = tempdir().unwrap();
+  let dir = dir.into_path();
+  let marker = dir.join("compiled");
+
+  let mut catbox = CatBoxBuilder::compile()
+    .command("sh", vec!["-c", "exit 3"])
+    .current_user()
+    .disable_ptrace()
+    .compile_tolerate_status(tolerate)
+    .done()
+    .command("touch", vec![marker.to_str().unwrap()])
+    .current_user()
+    .disable_ptrace()
+    .build();
+  catbox.start().unwrap();
+  catbox.close();
+
+  let compiled = marker.exists();
+  fs::remove_dir_all(dir).unwrap();
+  compiled
+}
+
+#[test]
+fn it_should_tolerate_compile_status() {
+  common::setup();
+  assert!(run_tolerated_compile(vec![3]));
+  assert!(!run_tolerated_compile(vec![]));
+  assert!(!run_tolerated_compile(vec![1, 2]));
+}
+
+#[test]
+fn it_should_report_each_compile_command() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let source = dir.path().join("Main.java");
+  let jar = dir.path().join("Main.jar");
+  fs::write(
+    &source,
+    "public class Main { public static void main(String[] args) {} }",
+  )
+  .unwrap();
+
+  let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args(["--report", "--json", "compile", "-l", "java", "-o"])
+    .args([jar.to_str().unwrap(), source.to_str().unwrap()])
+    .env("CATJ_LOG", dir.path())
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let report = String::from_utf8(output.stdout).unwrap();
+  let report = &report[report.find('{').unwrap()..];
+
+  // The Java preset runs javac, and then jar with bash
+  let commands = &report[report.find("\"commands\"").unwrap()..];
+  let javac = commands.find("\"command\": \"javac\"").unwrap();
+  let jar_command = commands.find("\"command\": \"jar\"").unwrap();
+  assert!(javac < jar_command);
+  // Usage of both commands, followed by the top level fields of the last one
+  assert_eq!(commands.matches("\"memory\"").count(), 3);
+  assert!(jar.exists());
+}
+
+#[test]
+fn it_should_compile_and_run_as_different_users() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o777)).unwrap();
+  let source = dir.path().join("uid.c");
+  let executable = dir.path().join("uid.out");
+  fs::copy("./fixtures/aplusb/source/uid.c", &source).unwrap();
+
+  // Compile as the build user daemon (uid 1)
+  let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args(["compile", "--build-user", "daemon", "-o"])
+    .args([executable.to_str().unwrap(), source.to_str().unwrap()])
+    .env("CATJ_LOG", dir.path())
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  assert_eq!(fs::metadata(&executable).unwrap().uid(), 1);
+
+  // Run as the default user nobody
+  let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args(["run", "--read", dir.path().to_str().unwrap(), "--"])
+    .arg(executable.to_str().unwrap())
+    .env("CATJ_LOG", dir.path())
+    .output()
+    .unwrap();
+  assert!(output.status.success());
+  let nobody = User::from_name("nobody").unwrap().unwrap();
+  let stdout = String::from_utf8(output.stdout).unwrap();
+  assert!(
+    stdout.contains(&format!("uid {}", nobody.uid)),
+    "{}",
+    stdout
+  );
+
+  // Unknown build user is rejected
+  let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args(["compile", "--build-user", "no-such-user", "-o"])
+    .args([executable.to_str().unwrap(), source.to_str().unwrap()])
+    .env("CATJ_LOG", dir.path())
+    .output()
+    .unwrap();
+  assert_eq!(output.status.code(), Some(2));
+}
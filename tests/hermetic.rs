@@ -0,0 +1,124 @@
+use std::fs;
+
+use catj::{run, CatBoxBuilder, NetPolicy};
+use tempfile::tempdir;
+
+mod common;
+
+#[test]
+fn it_should_run_static_binary_hermetically() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let dir = dir.into_path();
+  let executable = dir.join("hermetic.out");
+  let output = dir.join("hermetic.txt");
+
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "gcc",
+      vec![
+        "-static",
+        "./fixtures/aplusb/source/hermetic.c",
+        "-o",
+        executable.to_str().unwrap(),
+      ],
+    )
+    .time_limit(10 * 1000)
+    .current_user()
+    .disable_ptrace()
+    .process(10)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  assert_eq!(*result.status(), Some(0));
+
+  let catbox = CatBoxBuilder::run()
+    .command("/box/hermetic.out", vec![] as Vec<String>)
+    .stdin("./fixtures/aplusb/testcases/1.in")
+    .stdout(output.to_string_lossy())
+    .hermetic()
+    .mount_read(&dir, "/box")
+    .cwd("/")
+    .build();
+  let option = catbox.single().unwrap();
+  assert!(option.env().is_empty());
+  let result = run(option).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(fs::read_to_string(&output).unwrap().trim(), "2,1,1,0,null");
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn it_should_deny_syscalls_by_default_hermetically() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let dir = dir.into_path();
+  let executable = dir.join("hermetic_deny.out");
+  let output = dir.join("hermetic_deny.txt");
+
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "gcc",
+      vec![
+        "-static",
+        "./fixtures/aplusb/source/hermetic_deny.c",
+        "-o",
+        executable.to_str().unwrap(),
+      ],
+    )
+    .time_limit(10 * 1000)
+    .current_user()
+    .disable_ptrace()
+    .process(10)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  assert_eq!(*result.status(), Some(0));
+
+  // socket and fork fail by seccomp, rather than being killed by ptrace
+  let catbox = CatBoxBuilder::run()
+    .command("/box/hermetic_deny.out", vec![] as Vec<String>)
+    .stdout(output.to_string_lossy())
+    .hermetic()
+    .disable_ptrace()
+    .mount_read(&dir, "/box")
+    .cwd("/")
+    .build();
+  let option = catbox.single().unwrap();
+  assert_eq!(option.net_policy(), NetPolicy::Seccomp);
+  assert!(option.seccomp_default_deny());
+  let result = run(option).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(fs::read_to_string(&output).unwrap().trim(), "-1,1,-1,1");
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn it_should_limit_files_created_in_tmpfs_root() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "/usr/bin/sh",
+      vec![
+        "-c",
+        "i=0; while [ $i -lt 20 ]; do i=$((i+1)); true > /file$i || exit $i; done",
+      ],
+    )
+    .hermetic()
+    .current_user()
+    .disable_ptrace()
+    .mount_read("/usr", "/usr")
+    .mount_read("/lib", "/lib")
+    .mount_read("/lib64", "/lib64")
+    .max_files_created(5)
+    .stderr("/dev/null")
+    .cwd("/")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  // The 6th file fails to be created
+  assert_eq!(*result.status(), Some(6));
+}
@@ -0,0 +1,38 @@
+use std::fs;
+
+use catj::{default_format, run, CatBoxBuilder};
+use flexi_logger::{FileSpec, Logger};
+use tempfile::tempdir;
+
+#[test]
+fn it_should_log_run_id_and_label() {
+  let dir = tempdir().unwrap();
+  let logger = Logger::try_with_str("catj=info")
+    .unwrap()
+    .log_to_file(
+      FileSpec::default()
+        .directory(dir.path())
+        .basename("catj")
+        .suppress_timestamp(),
+    )
+    .format_for_files(default_format)
+    .start()
+    .unwrap();
+
+  for label in ["first", "second"] {
+    let catbox = CatBoxBuilder::run()
+      .command("true", vec![] as Vec<String>)
+      .label(label.to_string())
+      .build();
+    run(catbox.single().unwrap()).unwrap();
+  }
+  logger.flush();
+
+  let text = fs::read_to_string(dir.path().join("catj.log")).unwrap();
+  assert!(text.lines().any(|line| line.contains("[run#1 first] ")));
+  assert!(text.lines().any(|line| line.contains("[run#2 second] ")));
+  assert!(text
+    .lines()
+    .filter(|line| line.contains("Child process"))
+    .all(|line| line.contains("[run#")));
+}
@@ -0,0 +1,396 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use catj::{hash_tree, retry_mount, run, CatBoxBuilder, CatBoxError, MountOrigin};
+use nix::errno::Errno;
+use tempfile::tempdir;
+
+mod common;
+
+#[test]
+fn it_should_parse_mount_with_colon() {
+  let catbox = CatBoxBuilder::run()
+    .command("ls", vec!["/"])
+    .parse_mount_read(vec![
+      "/tmp/a\\:b:/data".to_string(),
+      "src=/tmp/c:d,dst=/e:f".to_string(),
+    ])
+    .unwrap()
+    .build();
+  let mounts = catbox.single().unwrap().mounts();
+  let mounts = &mounts[mounts.len() - 2..];
+
+  assert_eq!(mounts[0].src(), &PathBuf::from("/tmp/a:b"));
+  assert_eq!(mounts[0].dst(), &PathBuf::from("/data"));
+  assert_eq!(mounts[1].src(), &PathBuf::from("/tmp/c:d"));
+  assert_eq!(mounts[1].dst(), &PathBuf::from("/e:f"));
+}
+
+#[test]
+fn it_should_not_parse_wrong_mount() {
+  let result = CatBoxBuilder::run()
+    .command("ls", vec!["/"])
+    .parse_mount_read(vec!["/a:/b:/c".to_string()]);
+  assert!(result.is_err());
+
+  let result = CatBoxBuilder::run()
+    .command("ls", vec!["/"])
+    .parse_mount_write(vec!["src=/a,unknown=/b".to_string()]);
+  assert!(result.is_err());
+}
+
+#[test]
+fn it_should_mount_one_source_to_two_targets() {
+  common::setup();
+  let output_path = "./mount_twice.out";
+
+  let catbox = CatBoxBuilder::run()
+    .command("ls", vec!["/first/bin", "/second/bin"])
+    .stdout(output_path)
+    .chroot()
+    .cwd("/")
+    .parse_mount_read(vec![
+      "/usr:/first".to_string(),
+      "src=/usr,dst=/second".to_string(),
+    ])
+    .unwrap()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  let output = fs::read_to_string(output_path).unwrap();
+  fs::remove_file(output_path).unwrap();
+  assert_eq!(*result.status(), Some(0));
+  assert!(output.contains("/first/bin:"));
+  assert!(output.contains("/second/bin:"));
+}
+
+#[test]
+fn it_should_tag_mount_origin() {
+  let catbox = CatBoxBuilder::run()
+    .command("ls", vec!["/"])
+    .mount_read("/tmp", "/tmp")
+    .build();
+  let mounts = catbox.single().unwrap().mounts();
+  let (user, defaults) = mounts.split_last().unwrap();
+
+  assert!(!defaults.is_empty());
+  assert!(defaults.iter().all(|m| m.origin() == MountOrigin::Default));
+  assert_eq!(user.origin(), MountOrigin::User);
+  assert_eq!(user.dst(), &PathBuf::from("/tmp"));
+}
+
+#[test]
+fn it_should_search_jail_path() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let script = dir.path().join("hello");
+  fs::write(&script, "#!/bin/sh\necho hello\n").unwrap();
+  fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+  let output = dir.path().join("hello.out");
+
+  let catbox = CatBoxBuilder::run()
+    .command("hello", vec![] as Vec<String>)
+    .stdout(output.to_string_lossy())
+    .current_user()
+    .chroot()
+    .cwd("/")
+    .mount_read(dir.path(), "/opt/tools")
+    .jail_path("/opt/tools:/usr/bin:/bin")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(fs::read_to_string(output).unwrap().trim(), "hello");
+}
+
+#[test]
+fn it_should_run_in_minimal_jail() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = dir.path().join("ac.out");
+  let output = dir.path().join("ac.txt");
+
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "g++",
+      vec![
+        "./fixtures/aplusb/source/ac.cpp",
+        "-o",
+        executable.to_str().unwrap(),
+      ],
+    )
+    .time_limit(10 * 1000)
+    .current_user()
+    .disable_ptrace()
+    .process(10)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  assert_eq!(*result.status(), Some(0));
+
+  let catbox = CatBoxBuilder::run()
+    .command(executable.to_str().unwrap(), vec![] as Vec<String>)
+    .stdin("./fixtures/aplusb/testcases/1.in")
+    .stdout(output.to_string_lossy())
+    .current_user()
+    .minimal_jail(&executable)
+    .cwd("/")
+    .build();
+  let option = catbox.single().unwrap();
+  let mounts = option.mounts();
+  assert!(mounts.iter().all(|m| m.origin() == MountOrigin::Preset));
+  assert!(mounts.iter().any(|m| m.src().ends_with("libc.so.6")));
+  assert!(mounts.iter().any(|m| m.src() == &executable));
+
+  let result = run(option).unwrap();
+  catbox.close();
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(fs::read_to_string(output).unwrap().trim(), "2");
+}
+
+#[test]
+fn it_should_read_interpreter_from_elf() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = dir.path().join("true");
+
+  // Replace PT_INTERP with a loader which does not exist, in the same length
+  let interpreter = b"/lib64/ld-linux-x86-64.so.2";
+  let missing = b"/nonexistent/catj-interp.so";
+  let mut bytes = fs::read("/bin/true").unwrap();
+  let offset = bytes
+    .windows(interpreter.len())
+    .position(|window| window == interpreter)
+    .unwrap();
+  bytes[offset..offset + missing.len()].copy_from_slice(missing);
+  fs::write(&executable, bytes).unwrap();
+  fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+
+  let catbox = CatBoxBuilder::run()
+    .command(executable.to_str().unwrap(), vec![] as Vec<String>)
+    .minimal_jail(&executable)
+    .build();
+  let option = catbox.single().unwrap();
+
+  // The missing loader is found without running the executable, and falls back to default mounts
+  let mounts = option.mounts();
+  assert!(mounts.iter().any(|m| m.origin() == MountOrigin::Default));
+  assert!(!mounts.iter().any(|m| m.src().ends_with("libc.so.6")));
+}
+
+#[test]
+fn it_should_retry_transient_mount_failure() {
+  common::setup();
+  // A flaky mount helper, which is busy for the first two calls
+  let mut calls = 0;
+  let result = retry_mount(3, || {
+    calls += 1;
+    if calls <= 2 {
+      Err(Errno::EBUSY)
+    } else {
+      Ok(calls)
+    }
+  });
+  assert_eq!(result, Ok(3));
+
+  let mut calls = 0;
+  let result = retry_mount(1, || {
+    calls += 1;
+    Err::<(), _>(Errno::EAGAIN)
+  });
+  assert_eq!(result, Err(Errno::EAGAIN));
+  assert_eq!(calls, 2);
+
+  let mut calls = 0;
+  let result = retry_mount(3, || {
+    calls += 1;
+    Err::<(), _>(Errno::EPERM)
+  });
+  assert_eq!(result, Err(Errno::EPERM));
+  assert_eq!(calls, 1);
+}
+
+#[test]
+fn it_should_mount_symlink_targets_of_program() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let dir = dir.into_path();
+  fs::copy("./fixtures/aplusb/source/ac.cpp", dir.join("ac.cpp")).unwrap();
+  let executable = dir.join("ac.out");
+
+  // /usr/bin/c++ -> /etc/alternatives/c++ -> /usr/bin/g++, while /etc is not mounted
+  let catbox = CatBoxBuilder::run()
+    .command("c++", vec!["/box/ac.cpp", "-o", "/box/ac.out"])
+    .time_limit(10 * 1000)
+    .current_user()
+    .hermetic()
+    .seccomp_default_deny(false)
+    .disable_ptrace()
+    .process(10)
+    .mount_read("/usr", "/usr")
+    .mount_read("/lib", "/lib")
+    .mount_read("/lib64", "/lib64")
+    .mount_write(&dir, "/box")
+    .env("PATH", "/usr/bin")
+    .env("TMPDIR", "/box")
+    .cwd("/box")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert!(executable.exists());
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn it_should_mount_self_dir() {
+  common::setup();
+  // The test binary itself plays the helper shipped alongside
+  let helper = std::env::current_exe().unwrap();
+  let catbox = CatBoxBuilder::run()
+    .command(helper.to_str().unwrap(), vec!["--list"])
+    .stdout("/dev/null")
+    .chroot()
+    .mount_self_dir()
+    .cwd("/")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+}
+
+#[test]
+fn it_should_fail_writing_read_only_mount() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = tempdir().unwrap();
+  let stderr = output.path().join("stderr.out");
+
+  let catbox = CatBoxBuilder::run()
+    .command("/usr/bin/touch", vec!["/data/new.txt"])
+    .stderr(stderr.to_string_lossy())
+    .chroot()
+    .mount_read(dir.path(), "/data")
+    .cwd("/")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  // The write fails with EROFS, which is an ordinary runtime error without signal
+  assert_eq!(*result.status(), Some(1));
+  assert_eq!(*result.signal(), None);
+  let message = fs::read_to_string(&stderr).unwrap();
+  assert!(message.contains("Read-only file system"), "{}", message);
+  assert!(!dir.path().join("new.txt").exists());
+}
+
+#[test]
+fn it_should_mount_tmpfs_workdir() {
+  common::setup();
+  let output = tempdir().unwrap();
+  let stdout = output.path().join("stdout.out");
+  let stderr = output.path().join("stderr.out");
+
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "sh",
+      vec![
+        "-c",
+        "pwd && echo hi > note.txt && yes | head -c 2000000 > big",
+      ],
+    )
+    .tmpfs_workdir("/work", 1024 * 1024)
+    .stdout(stdout.to_string_lossy())
+    .stderr(stderr.to_string_lossy())
+    .disable_ptrace()
+    .process(10)
+    .build();
+  let option = catbox.single().unwrap();
+  let workdir = option.chroot().clone().unwrap().join("work");
+  let result = run(option).unwrap();
+
+  assert_eq!(fs::read_to_string(&stdout).unwrap(), "/work\n");
+  assert_eq!(
+    fs::read_to_string(workdir.join("note.txt")).unwrap(),
+    "hi\n"
+  );
+  // Writing 2 MB exceeds the 1 MB cap
+  assert_ne!(*result.status(), Some(0));
+  let message = fs::read_to_string(&stderr).unwrap();
+  assert!(message.contains("No space left on device"), "{}", message);
+  assert!(fs::metadata(workdir.join("big")).unwrap().len() <= 1024 * 1024);
+
+  // The tmpfs is unmounted with its content on close
+  catbox.close();
+  assert!(!workdir.join("note.txt").exists());
+}
+
+fn run_verified(dir: &Path, hash: Option<&str>) -> Result<Option<i32>, CatBoxError> {
+  let catbox = CatBoxBuilder::run()
+    .command("/usr/bin/cat", vec!["/data/input.txt"])
+    .stdout("/dev/null")
+    .chroot()
+    .mount_read_verified(dir, "/data", hash)
+    .cwd("/")
+    .build();
+  let result = run(catbox.single().unwrap());
+  catbox.close();
+  result.map(|result| *result.status())
+}
+
+#[test]
+fn it_should_verify_mount_hash() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  fs::write(dir.path().join("input.txt"), "1 2\n").unwrap();
+  let hash = hash_tree(dir.path()).unwrap();
+
+  assert_eq!(run_verified(dir.path(), Some(&hash)).unwrap(), Some(0));
+  assert_eq!(run_verified(dir.path(), None).unwrap(), Some(0));
+
+  // The host directory changes after computing the hash
+  fs::write(dir.path().join("input.txt"), "1 3\n").unwrap();
+  assert_ne!(hash_tree(dir.path()).unwrap(), hash);
+  let err = run_verified(dir.path(), Some(&hash)).unwrap_err();
+  assert!(err.to_string().contains("has changed"));
+  assert_eq!(run_verified(dir.path(), None).unwrap(), Some(0));
+}
+
+#[test]
+fn it_should_search_program_in_bin_dir() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let bin = dir.path().join("opt/gcc/bin");
+  let work = dir.path().join("work");
+  fs::create_dir_all(&bin).unwrap();
+  fs::create_dir_all(&work).unwrap();
+  std::os::unix::fs::symlink("/usr/bin/gcc", bin.join("mycc")).unwrap();
+  fs::write(work.join("main.c"), "int main() { return 0; }\n").unwrap();
+
+  // The compiler is found only in the custom bin dir, which is mounted and added to PATH together
+  let catbox = CatBoxBuilder::run()
+    .command("mycc", vec!["main.c", "-o", "main.out"])
+    .add_bin_dir(&bin)
+    .time_limit(10 * 1000)
+    .current_user()
+    .disable_ptrace()
+    .process(10)
+    .chroot()
+    .cwd(&work)
+    .mount_write(&work, &work)
+    .build();
+  let option = catbox.single().unwrap();
+  assert!(option
+    .env()
+    .iter()
+    .any(|(key, value)| key == "PATH" && value.ends_with(&format!(":{}", bin.to_string_lossy()))));
+  let result = run(option).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert!(work.join("main.out").is_file());
+}
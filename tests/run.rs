@@ -1,6 +1,29 @@
+use std::cell::RefCell;
 use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use catj::{run, CatBoxBuilder};
+use catj::{
+  run, CatBox, CatBoxBuilder, CatBoxError, CatBoxResult, DropCapabilities, Limits, ReportFormat,
+  ReportOptions, TimeLimitReason, Verdict,
+};
+use log::info;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::libc;
+use nix::sys::pthread::{pthread_kill, pthread_self};
+use nix::sys::resource::{setrlimit, Resource};
+use nix::sys::signal::{
+  pthread_sigmask, sigaction, SaFlags, SigAction, SigHandler, SigSet, SigmaskHow, Signal,
+};
+use nix::sys::socket::{shutdown, Shutdown};
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::{close, dup2, read, write};
+use tempfile::tempdir;
 
 mod common;
 
@@ -27,6 +50,809 @@ fn it_should_echo() {
   assert_eq!(output.trim(), text)
 }
 
+#[test]
+fn it_should_keep_stderr_in_terminal() {
+  common::setup();
+  let output_path = "./debug_stdout.out";
+  let error_path = "./debug_stderr.out";
+
+  let catbox = CatBoxBuilder::run()
+    .command("ls", vec!["./not_exist_path"])
+    .stdout(output_path)
+    .stderr(error_path)
+    .debug_stderr_to_terminal(true)
+    .build();
+  run(catbox.single().unwrap()).unwrap();
+
+  assert!(Path::new(output_path).exists());
+  assert!(!Path::new(error_path).exists());
+  fs::remove_file(output_path).unwrap();
+}
+
+fn print_ld_preload(allow: bool) -> String {
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("env.out");
+
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "echo ${LD_PRELOAD-null}"])
+    .stdout(output.to_string_lossy())
+    .stderr("/dev/null")
+    .env("LD_PRELOAD", "/not_exist_lib.so")
+    .allow_ld_env(allow)
+    .build();
+  run(catbox.single().unwrap()).unwrap();
+
+  fs::read_to_string(output).unwrap().trim().to_string()
+}
+
+#[test]
+fn it_should_scrub_ld_env() {
+  common::setup();
+  assert_eq!(print_ld_preload(false), "null");
+  assert_eq!(print_ld_preload(true), "/not_exist_lib.so");
+}
+
+#[test]
+fn it_should_report_core_dump() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "ulimit -c 0; kill -ABRT $$"])
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+
+  assert_eq!(*result.signal(), Some(Signal::SIGABRT));
+  assert!(!result.core_dumped());
+}
+
+const ISATTY: &str = "if [ -t 0 ]; then echo tty; else echo pipe; fi";
+
+#[test]
+fn it_should_run_with_pty() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", ISATTY])
+    .current_user()
+    .disable_ptrace()
+    .allocate_pty(true)
+    .unwrap()
+    .build();
+  let option = catbox.single().unwrap();
+  let result = run(option).unwrap();
+  assert_eq!(*result.status(), Some(0));
+
+  // The pty is not inherited by other forks
+  let flags = fcntl(option.pty_master().unwrap(), FcntlArg::F_GETFD).unwrap();
+  assert!(FdFlag::from_bits_truncate(flags).contains(FdFlag::FD_CLOEXEC));
+
+  let mut buf = [0; 4096];
+  let len = read(option.pty_master().unwrap(), &mut buf).unwrap();
+  let output = String::from_utf8_lossy(&buf[..len]);
+  assert_eq!(output.trim().lines().last().unwrap().trim(), "tty");
+  catbox.close();
+}
+
+#[test]
+fn it_should_run_with_stdio_socketpair() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "sh",
+      vec!["-c", "while read line; do echo \"echo $line\"; done"],
+    )
+    .current_user()
+    .disable_ptrace()
+    .stdio_socketpair(true)
+    .unwrap()
+    .build();
+  let option = catbox.single().unwrap();
+  let socket = option.stdio_socket().unwrap();
+
+  let caller = thread::spawn(move || {
+    let mut replies = vec![];
+    for message in ["ping\n", "pong\n"] {
+      write(socket, message.as_bytes()).unwrap();
+      let mut buf = [0; 64];
+      let len = read(socket, &mut buf).unwrap();
+      replies.push(String::from_utf8_lossy(&buf[..len]).to_string());
+    }
+    // The child sees EOF and exits
+    shutdown(socket, Shutdown::Write).unwrap();
+    replies
+  });
+  let result = run(option).unwrap();
+  let replies = caller.join().unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(replies, vec!["echo ping\n", "echo pong\n"]);
+}
+
+#[test]
+fn it_should_keep_stdio_socketpair_open_after_closing_clone() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "cat"])
+    .current_user()
+    .disable_ptrace()
+    .stdio_socketpair(true)
+    .unwrap()
+    .build();
+  let option = catbox.single().unwrap();
+  let socket = option.stdio_socket().unwrap();
+
+  option.with_stdout("/dev/null").close();
+  assert!(fcntl(socket, FcntlArg::F_GETFD).is_ok());
+  catbox.close();
+}
+
+#[test]
+fn it_should_run_without_pty() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("isatty.out");
+
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", ISATTY])
+    .stdin("/dev/null")
+    .stdout(output.to_string_lossy())
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let option = catbox.single().unwrap();
+  assert!(option.pty_master().is_none());
+  run(option).unwrap();
+  catbox.close();
+
+  assert_eq!(fs::read_to_string(output).unwrap().trim(), "pipe");
+}
+
+#[test]
+fn it_should_skip_commands_after_wall_budget() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let marker = dir.path().join("skipped");
+
+  let mut catbox = CatBoxBuilder::run()
+    .total_wall_budget(500)
+    .command("sleep", vec!["5"])
+    .time_limit(10 * 1000)
+    .current_user()
+    .disable_ptrace()
+    .done()
+    .command("touch", vec![marker.to_str().unwrap()])
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let start = Instant::now();
+  catbox.start().unwrap();
+  assert!(start.elapsed().as_millis() < 5000);
+  assert!(catbox.pipeline_timeout());
+  catbox.close();
+
+  assert!(!marker.exists());
+}
+
+#[test]
+fn it_should_clamp_wall_time_limit_to_wall_budget() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::run()
+    .total_wall_budget(500)
+    .command("sleep", vec!["5"])
+    .time_limit(10 * 1000)
+    .wall_time_limit(10 * 1000)
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let start = Instant::now();
+  catbox.start().unwrap();
+  assert!(start.elapsed() < Duration::from_millis(2000));
+  assert!(catbox.pipeline_timeout());
+
+  let mut json = vec![];
+  catbox.report_to(&mut json, ReportFormat::Json).unwrap();
+  let report: serde_json::Value = serde_json::from_slice(&json).unwrap();
+  assert_eq!(report["pipeline_timeout"], true);
+  let mut human = vec![];
+  catbox.report_to(&mut human, ReportFormat::Human).unwrap();
+  assert!(String::from_utf8(human).unwrap().contains("Pipeline"));
+  catbox.close();
+}
+
+#[test]
+fn it_should_invoke_on_complete_callbacks() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let marker = dir.path().join("skipped");
+  let statuses = Rc::new(RefCell::new(vec![]));
+
+  let record = |statuses: &Rc<RefCell<Vec<Option<i32>>>>, next: bool| {
+    let statuses = statuses.clone();
+    move |result: &CatBoxResult| {
+      statuses.borrow_mut().push(*result.status());
+      next
+    }
+  };
+  let mut catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "exit 1"])
+    .current_user()
+    .disable_ptrace()
+    .on_complete(record(&statuses, true))
+    .done()
+    .command("true", vec![] as Vec<String>)
+    .current_user()
+    .disable_ptrace()
+    .on_complete(record(&statuses, false))
+    .done()
+    .command("touch", vec![marker.to_str().unwrap()])
+    .current_user()
+    .disable_ptrace()
+    .on_complete(record(&statuses, true))
+    .build();
+  catbox.start().unwrap();
+  catbox.close();
+
+  // The second callback stops the pipeline
+  assert_eq!(*statuses.borrow(), vec![Some(1), Some(0)]);
+  assert!(!marker.exists());
+}
+
+#[test]
+fn it_should_iterate_results_lazily() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let marker = dir.path().join("second");
+  let second = format!("touch {} && exit 2", marker.to_str().unwrap());
+
+  let mut catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "exit 1"])
+    .current_user()
+    .disable_ptrace()
+    .done()
+    .command("sh", vec!["-c", second.as_str()])
+    .current_user()
+    .disable_ptrace()
+    .process(5)
+    .done()
+    .command("sh", vec!["-c", "exit 3"])
+    .current_user()
+    .disable_ptrace()
+    .build();
+
+  let mut results = catbox.results_iter();
+  assert_eq!(*results.next().unwrap().unwrap().status(), Some(1));
+  // The next command is not run until the iterator is advanced
+  assert!(!marker.exists());
+  let statuses = results
+    .map(|result| *result.unwrap().status())
+    .collect::<Vec<_>>();
+  assert_eq!(statuses, vec![Some(2), Some(3)]);
+  assert!(marker.exists());
+  catbox.close();
+}
+
+#[test]
+fn it_should_parse_env_file() {
+  let dir = tempdir().unwrap();
+  let env_file = dir.path().join("judge.env");
+  fs::write(
+    &env_file,
+    "# judge env\nLANG=C\n\n  ONLINE_JUDGE = \"1 2\"  \nNAME='catj'\nARGS=\"a=b\"\nHOME\n",
+  )
+  .unwrap();
+
+  let catbox = CatBoxBuilder::run()
+    .parse_env_file(&env_file)
+    .unwrap()
+    .command("env", vec![] as Vec<String>)
+    .build();
+  let env = catbox.single().unwrap().env();
+  let env = &env[env.len() - 5..];
+
+  assert_eq!(env[0], ("LANG".to_string(), "C".to_string()));
+  assert_eq!(env[1], ("ONLINE_JUDGE".to_string(), "1 2".to_string()));
+  assert_eq!(env[2], ("NAME".to_string(), "catj".to_string()));
+  assert_eq!(env[3], ("ARGS".to_string(), "a=b".to_string()));
+  assert_eq!(
+    env[4],
+    (
+      "HOME".to_string(),
+      std::env::var("HOME").unwrap_or_default()
+    )
+  );
+}
+
+#[test]
+fn it_should_measure_cpu_time_consistently() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "sh",
+      vec!["-c", "i=0; while [ $i -lt 200000 ]; do i=$((i+1)); done"],
+    )
+    .time_limit(10 * 1000)
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert!(result.time() > 0);
+  assert!(result.time() >= result.time_user() + result.time_sys());
+}
+
+#[test]
+fn it_should_report_cpu_time_limit() {
+  common::setup();
+  // Lower the soft limit, so that RLIMIT_CPU fires before the alarm
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "ulimit -S -t 1; while :; do :; done"])
+    .time_limit(3000)
+    .current_user()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.signal(), Some(Signal::SIGXCPU));
+  assert_eq!(result.time_limit_reason(), Some(TimeLimitReason::Cpu));
+}
+
+#[test]
+fn it_should_limit_cpu_strictly() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "while :; do :; done"])
+    .time_limit(1000)
+    .strict_cpu_limit(true)
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let start = Instant::now();
+  let result = run(catbox.single().unwrap()).unwrap();
+  let elapsed = start.elapsed().as_millis();
+  catbox.close();
+
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  assert!((900..1800).contains(&elapsed));
+}
+
+#[test]
+fn it_should_limit_threads_by_wall_time() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let executable = dir.path().join("threads.out");
+
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "gcc",
+      vec![
+        "-pthread",
+        "./fixtures/aplusb/source/threads.c",
+        "-o",
+        executable.to_str().unwrap(),
+      ],
+    )
+    .time_limit(10 * 1000)
+    .current_user()
+    .disable_ptrace()
+    .process(10)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  assert_eq!(*result.status(), Some(0));
+
+  let catbox = CatBoxBuilder::run()
+    .command(executable.to_str().unwrap(), vec![] as Vec<String>)
+    .time_limit(1000)
+    .wall_time_basis(true)
+    .process(10)
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let start = Instant::now();
+  let result = run(catbox.single().unwrap()).unwrap();
+  let elapsed = start.elapsed().as_millis();
+  catbox.close();
+
+  assert_eq!(*result.signal(), Some(Signal::SIGALRM));
+  assert_eq!(result.time_limit_reason(), Some(TimeLimitReason::Wall));
+  assert!((1000..1500).contains(&elapsed));
+}
+
+#[test]
+fn it_should_report_wall_time_limit() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("sleep", vec!["5"])
+    .time_limit(1000)
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  assert_eq!(*result.signal(), Some(Signal::SIGALRM));
+  assert_eq!(result.time_limit_reason(), Some(TimeLimitReason::Wall));
+
+  let catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .current_user()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  assert_eq!(result.time_limit_reason(), None);
+}
+
+#[test]
+fn it_should_report_to_writer() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .current_user()
+    .disable_ptrace()
+    .build();
+  catbox.start().unwrap();
+
+  let mut json = Vec::new();
+  catbox.report_to(&mut json, ReportFormat::Json).unwrap();
+  let json = String::from_utf8(json).unwrap();
+  assert!(json.contains("\"ok\": true"));
+  assert!(json.contains("\"status\": 0"));
+  assert!(json.contains("\"core_dumped\": false"));
+  assert!(json.contains("\"time_limit_reason\": null"));
+  assert!(json.contains("\"stderr_violated\": false"));
+
+  let mut human = Vec::new();
+  catbox.report_to(&mut human, ReportFormat::Human).unwrap();
+  let human = String::from_utf8(human).unwrap();
+  assert!(human.contains("Status"));
+  assert!(human.contains("Memory"));
+  catbox.close();
+}
+
+#[test]
+fn it_should_report_with_custom_labels() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .current_user()
+    .disable_ptrace()
+    .done()
+    .report_options(
+      ReportOptions::new()
+        .label("Status", "退出码")
+        .label("Time user", "User CPU time"),
+    )
+    .build();
+  catbox.start().unwrap();
+
+  let mut human = Vec::new();
+  catbox.report_to(&mut human, ReportFormat::Human).unwrap();
+  let human = String::from_utf8(human).unwrap();
+  catbox.close();
+
+  assert!(human.contains("\x1b[1m退出码\x1b[22m        \x1b[92m0"));
+  assert!(human.contains("\x1b[1mUser CPU time\x1b[22m "));
+  // Fields without overrides keep the default labels and alignment
+  assert!(human.contains("\x1b[1mTime\x1b[22m       "));
+  assert!(!human.contains("Status"));
+}
+
+#[test]
+fn it_should_report_display_name() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::run()
+    .command("/usr/bin/python3", vec!["-c", "print(1)"])
+    .display_name("Python 3")
+    .stdout("/dev/null")
+    .current_user()
+    .disable_ptrace()
+    .done()
+    .command("/usr/bin/python3", vec!["-c", "print(2)"])
+    .stdout("/dev/null")
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let programs = catbox
+    .results_iter()
+    .map(|result| result.unwrap().program().to_string())
+    .collect::<Vec<_>>();
+  catbox.close();
+
+  // The first command still execs python3, but is reported as Python 3
+  assert_eq!(programs, vec!["Python 3", "/usr/bin/python3"]);
+}
+
+#[test]
+fn it_should_truncate_long_exec_error() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("/not/found/program", vec![] as Vec<String>)
+    .error_message_limit(16)
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let result = run(catbox.single().unwrap());
+  catbox.close();
+
+  // The error message is longer than 16 bytes
+  let message = result.unwrap_err().to_string();
+  assert!(message.ends_with("…(truncated)"), "{}", message);
+  assert!(message.len() <= 64, "{}", message);
+}
+
+#[test]
+fn it_should_reject_nul_in_arguments() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["a\0b", ""])
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let option = catbox.single().unwrap();
+  assert_eq!(option.arguments().unwrap_err().exit_code(), 2);
+  let result = run(option);
+  catbox.close();
+
+  let err = result.unwrap_err();
+  assert!(matches!(err, CatBoxError::Cli(_)), "{}", err);
+  assert!(err.to_string().contains("NUL"), "{}", err);
+
+  // Empty arguments are passed as is
+  let catbox = CatBoxBuilder::run()
+    .command("true", vec![""])
+    .current_user()
+    .disable_ptrace()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  assert_eq!(*result.status(), Some(0));
+}
+
+#[test]
+fn it_should_reset_signal_mask() {
+  common::setup();
+  let mut mask = SigSet::empty();
+  mask.add(Signal::SIGTERM);
+  pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(&mask), None).unwrap();
+
+  let run_with = |inherit: bool| {
+    let catbox = CatBoxBuilder::run()
+      .command("/usr/bin/sh", vec!["-c", "kill -TERM $$; exit 0"])
+      .inherit_signals(inherit)
+      .current_user()
+      .disable_ptrace()
+      .build();
+    let result = run(catbox.single().unwrap()).unwrap();
+    catbox.close();
+    result
+  };
+  let reset = run_with(false);
+  let inherited = run_with(true);
+  pthread_sigmask(SigmaskHow::SIG_UNBLOCK, Some(&mask), None).unwrap();
+
+  // SIGTERM is blocked in the parent, but the child can still receive it
+  assert_eq!(*reset.signal(), Some(Signal::SIGTERM));
+  assert_eq!(*inherited.status(), Some(0));
+}
+
+fn run_command_line(report: bool) -> (CatBoxResult, String) {
+  let mut catbox = CatBoxBuilder::run()
+    .command("/usr/bin/echo", vec!["hello"])
+    .launcher("stdbuf", vec!["-o0"])
+    .env("SECRET", "catbox")
+    .report_command_line(report)
+    .stdout("/dev/null")
+    .current_user()
+    .build();
+  let result = catbox.results_iter().next().unwrap().unwrap();
+  let mut report = vec![];
+  catbox.report_to(&mut report, ReportFormat::Json).unwrap();
+  catbox.close();
+  (result, String::from_utf8(report).unwrap())
+}
+
+#[test]
+fn it_should_record_command_line() {
+  common::setup();
+  // Command line and env are neither recorded nor reported unless it is enabled
+  let (result, report) = run_command_line(false);
+  assert!(result.command_line().is_empty());
+  assert!(result.env().is_empty());
+  assert!(!report.contains("SECRET"));
+
+  let (result, report) = run_command_line(true);
+  assert_eq!(
+    *result.command_line(),
+    vec!["stdbuf", "-o0", "/usr/bin/echo", "hello"]
+  );
+  assert!(result.env().contains(&"SECRET=catbox".to_string()));
+  let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+  assert_eq!(
+    report["command_line"],
+    serde_json::json!(["stdbuf", "-o0", "/usr/bin/echo", "hello"])
+  );
+  assert!(report["env"]
+    .as_array()
+    .unwrap()
+    .contains(&serde_json::json!("SECRET=catbox")));
+}
+
+fn chown_as_root(capabilities: Option<DropCapabilities>) -> Option<i32> {
+  let dir = tempdir().unwrap();
+  let file = dir.path().join("owned");
+  fs::write(&file, "").unwrap();
+
+  let builder = CatBoxBuilder::run()
+    .command("/usr/bin/chown", vec!["1", file.to_str().unwrap()])
+    .stderr("/dev/null")
+    .current_user();
+  let builder = match capabilities {
+    Some(capabilities) => builder.drop_capabilities(capabilities),
+    None => builder,
+  };
+  let catbox = builder.build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  *result.status()
+}
+
+#[test]
+fn it_should_drop_capabilities() {
+  common::setup();
+  assert_eq!(chown_as_root(None), Some(0));
+  // chown to another user requires CAP_CHOWN
+  assert_eq!(chown_as_root(Some(DropCapabilities::All)), Some(1));
+  let chown = DropCapabilities::parse(&["chown"]).unwrap();
+  assert_eq!(chown, DropCapabilities::List(vec![0]));
+  assert_eq!(chown_as_root(Some(chown)), Some(1));
+  let kill = DropCapabilities::parse(&["CAP_KILL"]).unwrap();
+  assert_eq!(chown_as_root(Some(kill)), Some(0));
+  assert!(DropCapabilities::parse(&["CAP_UNKNOWN"]).is_err());
+}
+
+/// Run the program by the cli with time limit flag, returns the JSON report and wall time
+fn run_time_limit_cli(flag: &str, program: &[&str]) -> (String, Duration) {
+  let dir = tempdir().unwrap();
+  let start = Instant::now();
+  let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args(["--report", "--json", "--user", flag, "1000", "run", "--"])
+    .args(program)
+    .env("CATJ_LOG", dir.path())
+    .output()
+    .unwrap();
+  let elapsed = start.elapsed();
+  assert!(output.status.success());
+  (String::from_utf8(output.stdout).unwrap(), elapsed)
+}
+
+#[test]
+fn it_should_limit_cpu_time_and_wall_time_separately() {
+  common::setup();
+  let sleep = ["/usr/bin/sleep", "3"];
+  let busy = ["/usr/bin/sh", "-c", "while :; do :; done"];
+  let tle = "\"time_limit_reason\": \"wall\"";
+
+  // CPU time limit only loosely bounds wall time to 1 extra second
+  let (report, elapsed) = run_time_limit_cli("--cpu-time", &sleep);
+  assert!(report.contains(tle), "{}", report);
+  assert!(elapsed >= Duration::from_millis(1900));
+  let (report, elapsed) = run_time_limit_cli("--cpu-time", &busy);
+  assert!(report.contains(tle), "{}", report);
+  assert!(elapsed >= Duration::from_millis(1900));
+
+  // Wall time limit kills both exactly after 1 second
+  let (report, elapsed) = run_time_limit_cli("--wall-time", &sleep);
+  assert!(report.contains(tle), "{}", report);
+  assert!(elapsed < Duration::from_millis(1900));
+  let (report, elapsed) = run_time_limit_cli("--wall-time", &busy);
+  assert!(report.contains(tle), "{}", report);
+  assert!(elapsed < Duration::from_millis(1900));
+}
+
+extern "C" fn ignore_signal(_: i32) {}
+
+#[test]
+fn it_should_retry_wait_when_interrupted() {
+  common::setup();
+  // Without SA_RESTART, waitpid in the parent fails with EINTR
+  let action = SigAction::new(
+    SigHandler::Handler(ignore_signal),
+    SaFlags::empty(),
+    SigSet::empty(),
+  );
+  unsafe { sigaction(Signal::SIGUSR1, &action) }.unwrap();
+
+  let current = pthread_self();
+  let sender = thread::spawn(move || {
+    thread::sleep(Duration::from_millis(200));
+    pthread_kill(current, Signal::SIGUSR1).unwrap();
+  });
+
+  let catbox = CatBoxBuilder::run().command("sleep", vec!["0.5"]).build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  sender.join().unwrap();
+
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(*result.signal(), None);
+}
+
+#[test]
+fn it_should_pin_cpu_affinity() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("status.out");
+
+  let catbox = CatBoxBuilder::run()
+    .command("grep", vec!["Cpus_allowed_list", "/proc/self/status"])
+    .stdout(output.to_string_lossy())
+    .cpu_affinity(vec![0])
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  assert_eq!(*result.status(), Some(0));
+
+  let status = fs::read_to_string(output).unwrap();
+  assert_eq!(status.split_whitespace().last(), Some("0"));
+
+  // CPUs out of range are rejected before fork
+  for cpu in [1023, 4096] {
+    let catbox = CatBoxBuilder::run()
+      .command("true", vec![] as Vec<String>)
+      .cpu_affinity(vec![cpu])
+      .build();
+    let err = run(catbox.single().unwrap()).unwrap_err();
+    assert!(err.to_string().contains("is not available"), "{}", err);
+  }
+}
+
+fn count_mounts(root: &Path) -> usize {
+  let root = root.to_string_lossy().to_string();
+  fs::read_to_string("/proc/self/mountinfo")
+    .unwrap()
+    .lines()
+    .filter(|line| line.split(' ').nth(4).unwrap().starts_with(&root))
+    .count()
+}
+
+#[test]
+fn it_should_reuse_option_across_testcases() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("sub.out");
+  let output = output.to_string_lossy().to_string();
+
+  let catbox = CatBoxBuilder::run()
+    .command("cat", vec![] as Vec<String>)
+    .chroot()
+    .cwd("/")
+    .build();
+  let option = catbox.single().unwrap();
+  let root = option.chroot().clone().unwrap();
+
+  let start = Instant::now();
+  let mut mounts = None;
+  for _ in 0..10 {
+    for i in 1..4 {
+      let input = format!("./fixtures/aplusb/testcases/{}.in", i);
+      let option = option.with_stdin(input.clone()).with_stdout(output.clone());
+      let result = run(&option).unwrap();
+      assert_eq!(*result.status(), Some(0));
+      assert_eq!(
+        fs::read_to_string(&output).unwrap(),
+        fs::read_to_string(&input).unwrap()
+      );
+
+      let count = count_mounts(&root);
+      assert_eq!(*mounts.get_or_insert(count), count);
+    }
+  }
+  info!("Run 30 testcases in {} ms", start.elapsed().as_millis());
+
+  catbox.close();
+  assert_eq!(count_mounts(&root), 0);
+}
+
 // #[test]
 // fn it_should_dup() {
 //   match unsafe { fork() } {
@@ -77,3 +903,830 @@ fn it_should_echo() {
 //   let string = string.as_str();
 //   CString::new(string).expect("Convert &str to CString should work")
 // }
+
+#[test]
+fn it_should_report_unavailable_features_in_force_mode() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let mut command = Command::new(env!("CARGO_BIN_EXE_catj"));
+  command
+    .args(["--force", "run", "--", "/usr/bin/echo", "hello"])
+    .env("CATJ_LOG", dir.path());
+  // Simulate a restrictive container, which blocks mount and chroot
+  let caps = DropCapabilities::parse(&["sys_admin", "sys_chroot"])
+    .unwrap()
+    .ids();
+  unsafe {
+    command.pre_exec(move || {
+      for cap in &caps {
+        libc::prctl(libc::PR_CAPBSET_DROP, *cap as libc::c_ulong, 0, 0, 0);
+      }
+      Ok(())
+    });
+  }
+  let output = command.output().unwrap();
+  let stderr = String::from_utf8(output.stderr).unwrap();
+
+  assert!(!output.status.success());
+  assert!(stderr.contains("Unavailable Error"), "{}", stderr);
+  assert!(
+    stderr.contains("mount requires CAP_SYS_ADMIN"),
+    "{}",
+    stderr
+  );
+  assert!(
+    stderr.contains("chroot requires CAP_SYS_CHROOT"),
+    "{}",
+    stderr
+  );
+  assert!(String::from_utf8(output.stdout).unwrap().is_empty());
+}
+
+#[test]
+fn it_should_report_without_color() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let report = |color: &str| {
+    let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+      .args(["--report", "--color", color, "run", "--no-chroot", "--"])
+      .args(["/usr/bin/echo", "hello"])
+      .env("CATJ_LOG", dir.path())
+      .output()
+      .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+  };
+
+  // The human report is used even if piped once the color is set
+  let plain = report("never");
+  assert!(plain.contains("Program    /usr/bin/echo\n"), "{}", plain);
+  assert!(plain.contains("Status     0\n"), "{}", plain);
+  assert!(!plain.contains('\x1b'));
+  assert!(plain.ends_with('\n'));
+
+  let colored = report("always");
+  assert!(colored.contains("\x1b[1mStatus\x1b[22m"));
+}
+
+#[test]
+fn it_should_apply_shared_limits() {
+  let limits = Limits {
+    cpu_time_ms: 2000,
+    wall_time_ms: Some(5000),
+    memory_kb: 65536,
+    stack_bytes: Some(8 * 1024 * 1024),
+    output_bytes: 1024,
+    processes: 4,
+  };
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["first"])
+    .limits(limits.clone())
+    .done()
+    .command("echo", vec!["second"])
+    .limits(limits)
+    .build();
+
+  for option in catbox.commands() {
+    assert_eq!(option.time_limit(), 2000);
+    assert_eq!(option.wall_time_limit(), Some(5000));
+    assert_eq!(option.memory_limit(), 65536);
+    assert_eq!(option.stack_size(), 8 * 1024 * 1024);
+    assert_eq!(option.output_limit(), 1024);
+    assert_eq!(option.process(), 4);
+  }
+
+  // The default limits match the defaults of the option
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["default"])
+    .build();
+  let default = catbox.single().unwrap();
+  let limited = CatBoxBuilder::run()
+    .command("echo", vec!["default"])
+    .limits(Limits::default())
+    .build();
+  let limited = limited.single().unwrap();
+  assert_eq!(default.time_limit(), limited.time_limit());
+  assert_eq!(default.wall_time_limit(), limited.wall_time_limit());
+  assert_eq!(default.memory_limit(), limited.memory_limit());
+  assert_eq!(default.stack_size(), limited.stack_size());
+  assert_eq!(default.output_limit(), limited.output_limit());
+  assert_eq!(default.process(), limited.process());
+}
+
+#[test]
+fn it_should_report_limit_ratios() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::run()
+    .command(
+      "dd",
+      vec!["if=/dev/zero", "of=/dev/null", "bs=8M", "count=1"],
+    )
+    .time_limit(2000)
+    .memory_limit(65536)
+    .stderr("/dev/null")
+    .build();
+  let result = catbox.results_iter().next().unwrap().unwrap();
+
+  assert_eq!(result.time_ratio(), result.time() as f64 / 2000.0);
+  assert_eq!(result.memory_ratio(), result.memory() as f64 / 65536.0);
+  // dd allocates an 8 MB buffer within the 64 MB limit
+  assert!(result.memory_ratio() > 0.1 && result.memory_ratio() < 1.0);
+  assert!(result.time_ratio() < 1.0);
+
+  let mut report = vec![];
+  catbox.report_to(&mut report, ReportFormat::Json).unwrap();
+  let report = String::from_utf8(report).unwrap();
+  let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+  assert!(report["time_ratio"].is_f64(), "{}", report);
+  let memory_ratio = report["memory_ratio"].as_f64().unwrap();
+  assert!(
+    (memory_ratio - result.memory_ratio()).abs() < 1e-4,
+    "{}",
+    report
+  );
+  catbox.close();
+}
+
+#[test]
+fn it_should_exit_with_error_code() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let exit_code = |args: &[&str]| {
+    let output = Command::new(env!("CARGO_BIN_EXE_catj"))
+      .args(args)
+      .env("CATJ_LOG", dir.path())
+      .output()
+      .unwrap();
+    output.status.code()
+  };
+
+  assert_eq!(exit_code(&["run", "--", "/usr/bin/true"]), Some(0));
+  let missing = ["run", "--no-chroot", "--", "/not/exist/program"];
+  assert_eq!(exit_code(&missing), Some(5));
+  assert_eq!(CatBoxError::exec("").exit_code(), 5);
+  let invalid = [
+    "run",
+    "--answer",
+    "1.ans",
+    "--compare-mode",
+    "fuzzy",
+    "true",
+  ];
+  assert_eq!(exit_code(&invalid), Some(2));
+  assert_eq!(CatBoxError::cli("").exit_code(), 2);
+  assert_eq!(CatBoxError::cgroup("").exit_code(), 4);
+}
+
+#[test]
+fn it_should_start_detached() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let result = dir.path().join("result.json");
+  let catbox = CatBoxBuilder::run()
+    .command("sleep", vec!["1"])
+    .stdout("/dev/null")
+    .build();
+
+  // It returns before the program finishes
+  let start = Instant::now();
+  let pid = catbox.start_detached(&result).unwrap();
+  assert!(start.elapsed() < Duration::from_millis(900));
+
+  // Poll the result file written by the supervisor
+  while !result.exists() {
+    assert!(start.elapsed() < Duration::from_secs(10));
+    thread::sleep(Duration::from_millis(50));
+  }
+  assert!(start.elapsed() >= Duration::from_millis(900));
+  let report = fs::read_to_string(&result).unwrap();
+  assert!(report.contains("\"ok\": true,"), "{}", report);
+  assert!(report.contains("\"status\": 0,"), "{}", report);
+  assert_eq!(waitpid(pid, None).unwrap(), WaitStatus::Exited(pid, 0));
+}
+
+#[test]
+fn it_should_report_detached_error() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let result = dir.path().join("result.json");
+  let missing = dir.path().join("no\x1bdir").join("out");
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["hello"])
+    .stdout(missing.to_string_lossy())
+    .build();
+
+  let pid = catbox.start_detached(&result).unwrap();
+  assert_eq!(waitpid(pid, None).unwrap(), WaitStatus::Exited(pid, 1));
+  let report: serde_json::Value =
+    serde_json::from_str(&fs::read_to_string(&result).unwrap()).unwrap();
+  assert_eq!(report["ok"], false);
+  assert!(report["message"].as_str().unwrap().contains("no\x1bdir"));
+}
+
+#[test]
+fn it_should_limit_resources_in_lite_mode() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("gen.out");
+  let catbox = CatBoxBuilder::run()
+    .sandboxed_lite()
+    .command("seq", vec!["1", "100"])
+    .stdout(output.to_string_lossy())
+    .done()
+    .command("seq", vec!["1", "100000"])
+    .stdout(output.to_string_lossy())
+    .output_limit(1024)
+    .done()
+    .command("sh", vec!["-c", "while :; do :; done"])
+    .time_limit(500)
+    .build();
+  for option in catbox.commands() {
+    assert!(option.ptrace().is_none());
+    assert!(option.chroot().is_none());
+  }
+  let results = catbox
+    .commands()
+    .map(|option| run(option).unwrap())
+    .collect::<Vec<_>>();
+  catbox.close();
+
+  // The generator runs without ptrace and chroot
+  assert_eq!(*results[0].status(), Some(0));
+  // while the output and time limits still apply
+  assert_eq!(*results[1].signal(), Some(Signal::SIGXFSZ));
+  assert!(fs::metadata(&output).unwrap().len() <= 1024);
+  assert!(results[2].signal().is_some());
+  assert!(results[2].time() >= 500);
+}
+
+#[test]
+fn it_should_run_pre_exec_hook() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("ulimit.out");
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "ulimit -n"])
+    .stdout(output.to_string_lossy())
+    .pre_exec(|| {
+      setrlimit(Resource::RLIMIT_NOFILE, 16, 16)?;
+      Ok(())
+    })
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(fs::read_to_string(&output).unwrap().trim(), "16");
+
+  // The run fails if the hook fails
+  let catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .pre_exec(|| Err(CatBoxError::cli("custom setup")))
+    .build();
+  let result = run(catbox.single().unwrap());
+  catbox.close();
+  let err = result.unwrap_err();
+  assert!(matches!(err, CatBoxError::Exec(_)), "{}", err);
+  assert!(err.to_string().contains("custom setup"), "{}", err);
+}
+
+#[test]
+fn it_should_report_multiple_results_in_table() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .label("a".to_string())
+    .current_user()
+    .disable_ptrace()
+    .done()
+    .command("sh", vec!["-c", "exit 3"])
+    .label("a-much-longer-label".to_string())
+    .current_user()
+    .disable_ptrace()
+    .done()
+    .command("sh", vec!["-c", "kill -SEGV $$"])
+    .label("segv".to_string())
+    .current_user()
+    .disable_ptrace()
+    .build();
+  catbox.start().unwrap();
+
+  let mut human = Vec::new();
+  catbox.report_to(&mut human, ReportFormat::Human).unwrap();
+  let human = String::from_utf8(human).unwrap();
+  catbox.close();
+
+  // Failed rows are colored red, and successful ones are green
+  assert!(human.contains("\x1b[91m3\x1b[39m"));
+  assert!(human.contains("\x1b[91mSIGSEGV\x1b[39m"));
+  assert!(human.contains("\x1b[92m0\x1b[39m"));
+
+  let mut plain = String::new();
+  let mut in_escape = false;
+  for c in human.chars() {
+    match c {
+      '\x1b' => in_escape = true,
+      'm' if in_escape => in_escape = false,
+      _ if in_escape => {}
+      _ => plain.push(c),
+    }
+  }
+  let lines = plain.lines().collect::<Vec<_>>();
+  let header = lines.iter().position(|l| l.starts_with("Command")).unwrap();
+  let rows = &lines[header..header + 4];
+  assert!(rows[1].starts_with("a "));
+  assert!(rows[2].starts_with("a-much-longer-label  3"));
+  assert!(rows[3].starts_with("segv "));
+  // Columns are aligned regardless of the label length
+  let column = rows[0].find("Status").unwrap();
+  assert_eq!(column, "a-much-longer-label  ".len());
+  for row in &rows[1..] {
+    assert_eq!(row.chars().nth(column - 1), Some(' '));
+    assert_ne!(row.chars().nth(column), Some(' '));
+  }
+  assert!(plain.contains("Max time"));
+  assert!(plain.contains("Sum memory"));
+}
+
+#[test]
+fn it_should_record_distinct_syscalls() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["hello"])
+    .stdout("/dev/null")
+    .record_syscalls(true)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  let syscalls = result.distinct_syscalls();
+  assert!(syscalls.windows(2).all(|w| w[0] < w[1]));
+  assert!(syscalls.contains(&(libc::SYS_write as u64)));
+  assert!(
+    syscalls.contains(&(libc::SYS_exit_group as u64))
+      || syscalls.contains(&(libc::SYS_exit as u64))
+  );
+
+  // Nothing is recorded without ptrace
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["hello"])
+    .stdout("/dev/null")
+    .record_syscalls(true)
+    .disable_ptrace()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  assert!(result.distinct_syscalls().is_empty());
+}
+
+#[test]
+fn it_should_report_multiple_results_in_json() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .label("first".to_string())
+    .current_user()
+    .disable_ptrace()
+    .done()
+    .command("sh", vec!["-c", "kill -SEGV $$"])
+    .label("second".to_string())
+    .current_user()
+    .disable_ptrace()
+    .build();
+  catbox.start().unwrap();
+
+  let mut json = Vec::new();
+  catbox.report_to(&mut json, ReportFormat::Json).unwrap();
+  let json = String::from_utf8(json).unwrap();
+  catbox.close();
+
+  assert!(json.starts_with("{\n  \"ok\": true,\n  \"aggregate\": {\n    \"max_time\": "));
+  assert!(json.contains("\"sum_memory\": "));
+  assert!(json.contains("\"results\": [\n    {\n      \"label\": \"first\",\n      \"status\": 0,\n      \"signal\": null,"));
+  assert!(json
+    .contains("\"label\": \"second\",\n      \"status\": null,\n      \"signal\": \"SIGSEGV\","));
+  assert!(json.ends_with("    }\n  ]\n}\n"));
+  // No trailing comma before closing brackets
+  assert!(!json.contains(",\n    }") && !json.contains(",\n  ]"));
+}
+
+#[test]
+fn it_should_escape_labels_in_json_report() {
+  common::setup();
+  let label = "a \"quoted\" \\ label\n";
+  let mut catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .label(label.to_string())
+    .current_user()
+    .done()
+    .command("true", vec![] as Vec<String>)
+    .current_user()
+    .build();
+  catbox.start().unwrap();
+
+  let mut json = Vec::new();
+  catbox.report_to(&mut json, ReportFormat::Json).unwrap();
+  let json = String::from_utf8(json).unwrap();
+  catbox.close();
+
+  let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+  assert_eq!(report["results"][0]["label"], label);
+  assert_eq!(report["results"][0]["status"], 0);
+  assert!(report["results"][0]["signal"].is_null());
+}
+
+#[test]
+fn it_should_classify_run_verdict() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("gen.out");
+  let catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .done()
+    .command("false", vec![] as Vec<String>)
+    .done()
+    .command("seq", vec!["1", "100000"])
+    .stdout(output.to_string_lossy())
+    .output_limit(1024)
+    .done()
+    .command("sh", vec!["-c", "kill -SEGV $$"])
+    .disable_ptrace()
+    .done()
+    .command("sh", vec!["-c", "kill -KILL $$"])
+    .disable_ptrace()
+    .done()
+    .command("sh", vec!["-c", "while :; do :; done"])
+    .time_limit(500)
+    .build();
+  let verdicts = catbox
+    .commands()
+    .map(|option| run(option).unwrap().verdict(option))
+    .collect::<Vec<_>>();
+  catbox.close();
+
+  assert_eq!(
+    verdicts,
+    vec![
+      Verdict::Ok,
+      Verdict::RuntimeError,
+      Verdict::OutputLimitExceeded,
+      Verdict::RuntimeError,
+      Verdict::Killed,
+      Verdict::TimeLimitExceeded,
+    ]
+  );
+
+  // The memory is compared with the limit of the given option
+  let catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .done()
+    .command("true", vec![] as Vec<String>)
+    .memory_limit(1)
+    .build();
+  let options = catbox.commands().collect::<Vec<_>>();
+  let result = run(options[0]).unwrap();
+  assert_eq!(result.verdict(options[0]), Verdict::Ok);
+  assert_eq!(result.verdict(options[1]), Verdict::MemoryLimitExceeded);
+  catbox.close();
+}
+
+#[test]
+fn it_should_forward_fd_to_child() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let path = dir.path().join("forward.out");
+  // Rust opens files with O_CLOEXEC
+  let file = fs::File::create(&path).unwrap();
+  let mut catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "echo forwarded >&5"])
+    .forward_fd(file.as_raw_fd(), 5)
+    .unwrap()
+    .build();
+  let result = catbox.results_iter().next().unwrap().unwrap();
+  catbox.close();
+
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(fs::read_to_string(&path).unwrap(), "forwarded\n");
+
+  assert!(CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .forward_fd(-1, 5)
+    .is_err());
+
+  // The parent fd is closed after building, and dup2 fails in the child
+  let fd = dup2(fs::File::create(&path).unwrap().as_raw_fd(), 1000).unwrap();
+  let catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .forward_fd(fd, 5)
+    .unwrap()
+    .build();
+  close(fd).unwrap();
+  let err = run(catbox.single().unwrap()).unwrap_err();
+  catbox.close();
+  assert!(err.to_string().contains("Forward fds fails"), "{}", err);
+}
+
+#[test]
+fn it_should_report_wall_time() {
+  common::setup();
+  let mut catbox = CatBoxBuilder::run().command("sleep", vec!["0.3"]).build();
+  let result = catbox.results_iter().next().unwrap().unwrap();
+  let mut report = vec![];
+  catbox.report_to(&mut report, ReportFormat::Json).unwrap();
+  catbox.close();
+
+  // Sleeping costs wall time rather than CPU time
+  assert!(result.wall_time() >= 300, "{}", result.wall_time());
+  assert!(result.time() < 300);
+  let report: serde_json::Value =
+    serde_json::from_str(&String::from_utf8(report).unwrap()).unwrap();
+  assert_eq!(report["wall_time"], result.wall_time());
+}
+
+#[test]
+fn it_should_set_output_limit_from_cli() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("seq.out");
+  let output_arg = output.to_string_lossy().to_string();
+  let report = Command::new(env!("CARGO_BIN_EXE_catj"))
+    .args(["--report", "--json", "--user", "--output-limit", "1024"])
+    .args(["run", "--no-chroot", "--stdout", &output_arg, "--"])
+    .args(["/usr/bin/seq", "1", "100000"])
+    .env("CATJ_LOG", dir.path())
+    .output()
+    .unwrap();
+  assert!(report.status.success());
+
+  let report: serde_json::Value = serde_json::from_slice(&report.stdout).unwrap();
+  assert_eq!(report["signal"], "SIGXFSZ");
+  assert!(fs::metadata(&output).unwrap().len() <= 1024);
+}
+
+#[test]
+fn it_should_assign_uids_from_pool() {
+  common::setup();
+  let pool = vec![65531, 65532, 65533];
+  let catbox = CatBoxBuilder::run()
+    .uid_pool(pool.clone())
+    .command("true", vec![] as Vec<String>)
+    .done()
+    .command("true", vec![] as Vec<String>)
+    .done()
+    .command("true", vec![] as Vec<String>)
+    .done()
+    .command("true", vec![] as Vec<String>)
+    .build();
+  let uids = catbox
+    .commands()
+    .map(|option| option.uid().as_raw())
+    .collect::<Vec<_>>();
+  for option in catbox.commands() {
+    assert_eq!(*run(option).unwrap().status(), Some(0));
+  }
+  catbox.close();
+
+  // Successive commands rotate through the pool
+  let mut distinct = uids[..3].to_vec();
+  distinct.sort();
+  assert_eq!(distinct, pool);
+  assert_eq!(uids[3], uids[0]);
+}
+
+#[test]
+fn it_should_limit_address_space() {
+  common::setup();
+  let dd = || {
+    CatBoxBuilder::run()
+      .command(
+        "dd",
+        vec!["if=/dev/zero", "of=/dev/null", "bs=64M", "count=1"],
+      )
+      .memory_limit(16 * 1024)
+      .stderr("/dev/null")
+  };
+  let status = |catbox: CatBox| {
+    let result = run(catbox.single().unwrap()).unwrap();
+    catbox.close();
+    *result.status()
+  };
+
+  // dd fails to allocate the 64 MB buffer
+  let explicit = dd().address_space_limit(32 * 1024 * 1024).build();
+  assert_eq!(
+    explicit.single().unwrap().address_space_limit(),
+    Some(32 << 20)
+  );
+  assert_eq!(status(explicit), Some(1));
+  let slack = dd()
+    .address_space_limit_with_slack(16 * 1024 * 1024)
+    .build();
+  assert_eq!(
+    slack.single().unwrap().address_space_limit(),
+    Some(32 << 20)
+  );
+  assert_eq!(status(slack), Some(1));
+  // while a large slack keeps the allocation within the limit
+  let large = dd()
+    .memory_limit(128 * 1024)
+    .address_space_limit_with_slack(64 << 20);
+  assert_eq!(status(large.build()), Some(0));
+}
+
+#[test]
+fn it_should_kill_by_wall_time_watchdog() {
+  common::setup();
+  // The program ignores SIGALRM, which evades the wall timer in the child
+  let mut catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "trap '' ALRM; exec sleep 5"])
+    .wall_time_limit(500)
+    .disable_ptrace()
+    .build();
+  assert_eq!(catbox.single().unwrap().watchdog_time_limit(), 700);
+  let start = Instant::now();
+  let result = catbox.results_iter().next().unwrap().unwrap();
+  catbox.close();
+
+  assert!(start.elapsed() < Duration::from_secs(3));
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  assert_eq!(result.time_limit_reason(), Some(TimeLimitReason::Wall));
+
+  // The watchdog defaults to 3 times the time limit
+  let catbox = CatBoxBuilder::run()
+    .command("true", vec![] as Vec<String>)
+    .time_limit(2000)
+    .build();
+  assert_eq!(catbox.single().unwrap().watchdog_time_limit(), 6200);
+  catbox.close();
+}
+
+#[test]
+fn it_should_terminate_with_kill_signal() {
+  common::setup();
+  // The program cleans up on SIGTERM sent by the wall time watchdog
+  let script = "trap 'exit 0' TERM; trap '' ALRM; while :; do sleep 0.1; done";
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", script])
+    .wall_time_limit(300)
+    .kill_signal(Signal::SIGTERM)
+    .process(4)
+    .disable_ptrace()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(*result.signal(), None);
+
+  // The forbidden syscall is skipped, and the child is terminated by SIGTERM
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "exec /usr/bin/true"])
+    .kill_signal(Signal::SIGTERM)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  assert_eq!(*result.signal(), Some(Signal::SIGTERM));
+  assert!(result.forbidden_syscall().is_some());
+
+  // The program ignores SIGTERM and retries the forbidden syscall, then it is killed
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "perl",
+      vec![
+        "-e",
+        "$SIG{TERM} = 'IGNORE'; while (1) { my $pid = fork(); exit 0 if defined $pid && !$pid }",
+      ],
+    )
+    .kill_signal(Signal::SIGTERM)
+    .stderr("/dev/null")
+    .build();
+  let start = Instant::now();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  assert!(result.forbidden_syscall().is_some());
+  assert!(start.elapsed() < Duration::from_millis(1000));
+
+  // The program ignores SIGTERM and spins, then it is killed after the grace period
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "perl",
+      vec!["-e", "$SIG{TERM} = 'IGNORE'; fork(); 1 while 1"],
+    )
+    .kill_signal(Signal::SIGTERM)
+    .build();
+  let start = Instant::now();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  assert!(result.forbidden_syscall().is_some());
+  assert!(start.elapsed() < Duration::from_millis(1000));
+
+  // The kill signal is also a time limit signal, and the retried fork is still stopped
+  let dir = tempdir().unwrap();
+  let marker = dir.path().join("forked");
+  let catbox = CatBoxBuilder::run()
+    .command(
+      "perl",
+      vec![
+        "-e",
+        "$SIG{XCPU} = 'IGNORE'; while (1) { my $pid = fork(); if (defined $pid && !$pid) { open(my $f, '>', $ARGV[0]); exit 0 } }",
+        marker.to_str().unwrap(),
+      ],
+    )
+    .kill_signal(Signal::SIGXCPU)
+    .process(4)
+    .current_user()
+    .stderr("/dev/null")
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+  assert_eq!(*result.signal(), Some(Signal::SIGKILL));
+  assert!(result.forbidden_syscall().is_some());
+  assert!(!marker.exists());
+}
+
+#[test]
+fn it_should_reject_missing_redirect_directory() {
+  common::setup();
+  let catbox = CatBoxBuilder::run()
+    .command("echo", vec!["lost"])
+    .stdout("/not/exist/dir/echo.out")
+    .build();
+  let result = run(catbox.single().unwrap());
+  catbox.close();
+
+  let err = result.unwrap_err();
+  assert_eq!(err.exit_code(), 7);
+  assert!(
+    err.to_string().contains("/not/exist/dir/echo.out"),
+    "{}",
+    err
+  );
+}
+
+#[test]
+fn it_should_capture_stdout_and_stderr() {
+  common::setup();
+  // The output is much larger than the pipe buffer
+  let mut catbox = CatBoxBuilder::run()
+    .command("seq", vec!["1", "200000"])
+    .capture_stdout()
+    .capture_stderr()
+    .done()
+    .command("sh", vec!["-c", "echo captured >&2"])
+    .capture_stderr()
+    .build();
+  let results = catbox
+    .results_iter()
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  catbox.close();
+
+  let expected = (1..=200000).map(|i| format!("{}\n", i)).collect::<String>();
+  assert_eq!(results[0].stdout_bytes(), expected.as_bytes());
+  assert!(results[0].stderr_bytes().is_empty());
+  assert!(results[1].stdout_bytes().is_empty());
+  assert_eq!(results[1].stderr_bytes(), b"captured\n");
+}
+
+#[test]
+fn it_should_not_wait_for_grandchild_holding_captured_stdout() {
+  common::setup();
+  let start = Instant::now();
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "setsid sleep 3 & echo done"])
+    .no_cgroup()
+    .disable_ptrace()
+    .capture_stdout()
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  catbox.close();
+
+  // The escaped sleep still holds the write side of the pipe
+  assert!(start.elapsed() < Duration::from_secs(2));
+  assert_eq!(*result.status(), Some(0));
+  assert_eq!(result.stdout_bytes(), b"done\n");
+}
+
+#[test]
+fn it_should_feed_stdin_bytes() {
+  common::setup();
+  // The input is much larger than the pipe buffer
+  let input = vec![b'x'; 1 << 20];
+  let mut catbox = CatBoxBuilder::run()
+    .command("wc", vec!["-c"])
+    .stdin_bytes(input)
+    .capture_stdout()
+    .done()
+    .command("cat", vec![] as Vec<String>)
+    .stdin_bytes(b"1 2\n".to_vec())
+    .capture_stdout()
+    .build();
+  let results = catbox
+    .results_iter()
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  catbox.close();
+
+  assert_eq!(results[0].stdout_bytes(), b"1048576\n");
+  assert_eq!(results[1].stdout_bytes(), b"1 2\n");
+}
@@ -0,0 +1,87 @@
+use std::fs;
+
+use catj::{serve, CatBoxBuilder};
+use serde_json::Value;
+use tempfile::tempdir;
+
+mod common;
+
+#[test]
+fn it_should_serve_requests() {
+  common::setup();
+  let dir = tempdir().unwrap();
+  let output = dir.path().join("echo.out");
+
+  let input = [
+    format!(
+      "{{\"id\": 1, \"program\": \"echo\", \"arguments\": [\"123\"], \"stdout\": {}, \"chroot\": false}}",
+      Value::from(output.to_string_lossy())
+    ),
+    "".to_string(),
+    "{\"id\": \"two\", \"program\": \"sh\", \"arguments\": [\"-c\", \"exit 3\"], \"time\": 2000}"
+      .to_string(),
+    "{\"id\": 3, \"arguments\": []}".to_string(),
+    "not json".to_string(),
+    "{\"id\": \"\\uD83D\\uDE00\\u001b\", \"arguments\": []}"
+      .to_string(),
+  ]
+  .join("\n");
+  let mut result = vec![];
+  serve(|| Ok(CatBoxBuilder::run()), input.as_bytes(), &mut result).unwrap();
+
+  let result = String::from_utf8(result).unwrap();
+  let lines = result
+    .lines()
+    .map(|line| serde_json::from_str::<Value>(line).unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(lines.len(), 5);
+  assert_eq!(lines[0]["id"], 1);
+  assert_eq!(lines[0]["ok"], true);
+  assert_eq!(lines[0]["status"], 0);
+  assert_eq!(fs::read_to_string(&output).unwrap().trim(), "123");
+  assert_eq!(lines[1]["id"], "two");
+  assert_eq!(lines[1]["ok"], true);
+  assert_eq!(lines[1]["status"], 3);
+  assert_eq!(lines[2]["id"], 3);
+  assert_eq!(lines[2]["ok"], false);
+  assert!(lines[2]["error"].is_string());
+  assert_eq!(lines[3]["id"], Value::Null);
+  assert_eq!(lines[3]["ok"], false);
+  // Surrogate pairs and control characters are read and echoed back as valid JSON
+  assert_eq!(lines[4]["id"], "\u{1F600}\x1b");
+  assert_eq!(lines[4]["ok"], false);
+}
+
+#[test]
+fn it_should_export_metrics() {
+  common::setup();
+  let input = [
+    "{\"id\": 1, \"program\": \"echo\", \"arguments\": [\"123\"], \"chroot\": false}",
+    "{\"id\": 2, \"program\": \"sh\", \"arguments\": [\"-c\", \"exit 3\"]}",
+    "{\"id\": 3, \"program\": \"sleep\", \"arguments\": [\"5\"], \"time\": 500}",
+    "{\"id\": 4, \"arguments\": []}",
+    "{\"id\": 5, \"metrics\": true}",
+  ]
+  .join("\n");
+  let mut result = vec![];
+  serve(|| Ok(CatBoxBuilder::run()), input.as_bytes(), &mut result).unwrap();
+
+  let result = String::from_utf8(result).unwrap();
+  let line = serde_json::from_str::<Value>(result.lines().last().unwrap()).unwrap();
+  assert_eq!(line["id"], 5);
+  assert_eq!(line["ok"], true);
+  let text = line["metrics"].as_str().unwrap();
+  assert!(text.contains("# TYPE catj_runs_total counter\ncatj_runs_total 3\n"));
+  assert!(text.contains("catj_verdicts_total{verdict=\"ok\"} 1\n"));
+  assert!(text.contains("catj_verdicts_total{verdict=\"tle\"} 1\n"));
+  assert!(text.contains("catj_verdicts_total{verdict=\"mle\"} 0\n"));
+  assert!(text.contains("catj_verdicts_total{verdict=\"re\"} 1\n"));
+  assert!(text.contains("catj_verdicts_total{verdict=\"ole\"} 0\n"));
+  assert!(text.contains("catj_verdicts_total{verdict=\"killed\"} 0\n"));
+  assert!(text.contains("catj_errors_total 1\n"));
+  assert!(text.contains("# TYPE catj_time_ms histogram\n"));
+  assert!(text.contains("catj_time_ms_bucket{le=\"+Inf\"} 3\n"));
+  assert!(text.contains("catj_time_ms_count 3\n"));
+  assert!(text.contains("catj_memory_kb_bucket{le=\"+Inf\"} 3\n"));
+  assert!(text.contains("catj_memory_kb_count 3\n"));
+}
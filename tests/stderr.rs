@@ -0,0 +1,22 @@
+use catj::{run, CatBoxBuilder};
+
+// No logger here, otherwise the log lines of the child process are written into captured stderr
+#[test]
+fn it_should_require_empty_stderr() {
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "echo warning >&2"])
+    .require_empty_stderr(true)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  assert_eq!(*result.status(), Some(0));
+  assert!(result.stderr_violated());
+
+  let catbox = CatBoxBuilder::run()
+    .command("sh", vec!["-c", "echo 1"])
+    .stdout("/dev/null")
+    .require_empty_stderr(true)
+    .build();
+  let result = run(catbox.single().unwrap()).unwrap();
+  assert_eq!(*result.status(), Some(0));
+  assert!(!result.stderr_violated());
+}
@@ -0,0 +1,64 @@
+use catj::{ReportFormat, RestrictedSyscall, SyscallFilter};
+
+#[test]
+fn it_should_export_policy() {
+  let filter = SyscallFilter::parse_presets(vec!["net".to_string()])
+    .unwrap()
+    .unwrap();
+  let text = filter.export(ReportFormat::Human);
+  assert!(text
+    .lines()
+    .any(|line| line.starts_with("socketpair") && line.ends_with("forbid")));
+  assert!(!text.contains("execve"));
+
+  let json = SyscallFilter::default().export(ReportFormat::Json);
+  assert!(json.contains("{ \"id\": 59, \"name\": \"execve\", \"perm\": \"allow\", \"count\": 1 }"));
+  assert!(json.contains("\"name\": \"clone\", \"perm\": \"forbid\""));
+
+  let json = SyscallFilter::new().export(ReportFormat::Json);
+  assert!(json.contains("\"syscalls\": []"));
+}
+
+#[test]
+fn it_should_build_from_presets() {
+  let filter = SyscallFilter::from_presets(&[RestrictedSyscall::Net, RestrictedSyscall::Process]);
+  let text = filter.export(ReportFormat::Human);
+  let rules = text
+    .lines()
+    .skip(1)
+    .map(|line| {
+      let columns = line.split_whitespace().collect::<Vec<_>>();
+      (columns[0], columns[2..].join(" "))
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(rules.len(), 16);
+  assert!(rules.contains(&("socketpair", "forbid".to_string())));
+  assert!(rules.contains(&("shutdown", "forbid".to_string())));
+  assert!(rules.contains(&("clone3", "forbid".to_string())));
+  assert!(rules.contains(&("execve", "allow 1 times".to_string())));
+  assert!(rules.contains(&("execveat", "allow 1 times".to_string())));
+
+  // Same as the default filter and the CLI presets
+  let json = filter.export(ReportFormat::Json);
+  assert_eq!(json, SyscallFilter::default().export(ReportFormat::Json));
+  let parsed = SyscallFilter::parse_presets(vec!["net process".to_string()])
+    .unwrap()
+    .unwrap();
+  assert_eq!(json, parsed.export(ReportFormat::Json));
+  assert!(SyscallFilter::from_presets(&[])
+    .export(ReportFormat::Json)
+    .contains("\"syscalls\": []"));
+}
+
+#[test]
+fn it_should_export_mmap_limit() {
+  let mut filter = SyscallFilter::new();
+  filter.limit_mmap(1024);
+  assert!(filter
+    .export(ReportFormat::Human)
+    .lines()
+    .any(|line| line.starts_with("mmap") && line.ends_with("arg1 <= 1024")));
+  assert!(filter
+    .export(ReportFormat::Json)
+    .contains("\"name\": \"mmap\", \"perm\": \"max_arg\", \"arg\": 1, \"max\": 1024"));
+}